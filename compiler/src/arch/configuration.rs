@@ -1,16 +1,26 @@
 use std::fs;
-use graph::graph::{UnGraph, NodeIndex};
-use serde::{
-    Deserialize,
-    Deserializer,
+use std::fmt;
+use graph::{
+    graph::{UnGraph, NodeIndex},
+    algo::dijkstra,
 };
+use serde::{Deserialize, Serialize};
 
-pub type ConnectionGraph = UnGraph<(), u32>;
+pub type ConnectionGraph = UnGraph<(), LinkInfo>;
 
-#[derive(Deserialize, Debug, Clone)]
+/// Light in optical fibers travels a kilometer in 5 microseconds.
+const FIBER_DELAY_PER_KM: f64 = 5000.0; // [ns]
+/// Photons in optical fibers are lost by 0.2 dB per kilometer.
+const FIBER_LOSS_PER_KM: f64 = 0.2; // [dB]
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct NodeInfo {
-    /// The number of available qubits
-    num_of_qubits: u32,
+    /// The number of data qubits
+    #[serde(alias = "num_of_qubits")]
+    data_qubits: u32,
+    /// The communication qubits, which are given by the weights of the links when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    comm_qubits: Option<CommQubits>,
     #[serde(default = "default_single_gate_cost")]
     single_gate_cost: u64,
     #[serde(default = "default_local_cx_cost")]
@@ -21,18 +31,321 @@ pub struct NodeInfo {
     measure_cost: u64,
     #[serde(default = "default_classical_comm_cost")]
     classical_comm_cost: u64,
+    /// The number of purification rounds of each Bell pair over the links of the node.
+    /// A failed round discards both pairs and the purification restarts from raw pairs,
+    /// which the simulator charges as the expected number of generations given `fidelity` of the links.
+    /// The fidelity is not tracked further: it is not degraded by swapping or storage,
+    /// and the interpreter takes every round to succeed.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    purification_rounds: u32,
+}
+
+/// The communication qubits of a node.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum CommQubits {
+    /// The number of qubits, each of which serves all the links of the node
+    Shared(u32),
+    /// The nodes with which each qubit can share Bell pairs
+    Dedicated(Vec<Vec<u32>>),
+}
+
+/// The attributes of a link. Omitted ones are derived from the length if given,
+/// or from the nodes at the ends otherwise.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct LinkInfo {
+    /// The number of communication qubits at both ends, which is superseded by `comm_qubits` of the nodes
+    #[serde(default = "default_capacity")]
+    capacity: u32,
+    /// The time of an attempt to generate a Bell pair
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gen_ent_time: Option<u64>,
+    /// The probability that an attempt succeeds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    success_probability: Option<f64>,
+    /// The propagation delay of classical messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    classical_latency: Option<u64>,
+    /// The fidelity of the raw Bell pairs to the Bell state, as a Werner state, which is 1 when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fidelity: Option<f64>,
+    /// The length of the fiber [km]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    length: Option<f64>,
+}
+
+impl LinkInfo {
+    pub fn new(capacity: u32) -> Self {
+        Self { capacity, gen_ent_time: None, success_probability: None, classical_latency: None, fidelity: None, length: None }
+    }
+
+    pub fn with_gen_ent_time(mut self, time: u64) -> Self {
+        self.gen_ent_time = Some(time);
+        self
+    }
+
+    pub fn with_success_probability(mut self, p: f64) -> Self {
+        self.success_probability = Some(p);
+        self
+    }
+
+    pub fn with_classical_latency(mut self, latency: u64) -> Self {
+        self.classical_latency = Some(latency);
+        self
+    }
+
+    pub fn with_fidelity(mut self, fidelity: f64) -> Self {
+        self.fidelity = Some(fidelity);
+        self
+    }
+
+    pub fn with_length(mut self, length: f64) -> Self {
+        self.length = Some(length);
+        self
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// The propagation delay of light over the fiber.
+    fn fiber_delay(&self) -> u64 {
+        (self.length.unwrap_or(0.0) * FIBER_DELAY_PER_KM).round() as u64
+    }
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(try_from = "RawConfiguration")]
 pub struct Configuration {
-    #[serde(deserialize_with = "from_graph")]
     connections: ConnectionGraph,
     nodes: Vec<NodeInfo>,
 }
 
+/// A configuration as written in the file, which is validated into `Configuration`.
+#[derive(Deserialize)]
+pub(crate) struct RawConfiguration {
+    pub(crate) nodes: Vec<NodeInfo>,
+    pub(crate) connections: Vec<RawLink>,
+}
+
+/// A link as written in the file, which is either `[u, v, capacity]` or an object with `ends` and the attributes.
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+pub(crate) enum RawLink {
+    Weight(u32, u32, u32),
+    Attributed {
+        ends: (u32, u32),
+        #[serde(flatten)]
+        info: LinkInfo,
+    },
+}
+
+impl RawLink {
+    fn ends(&self) -> (u32, u32) {
+        match *self {
+            RawLink::Weight(u, v, _) => (u, v),
+            RawLink::Attributed { ends, .. } => ends,
+        }
+    }
+
+    fn into_info(self) -> LinkInfo {
+        match self {
+            RawLink::Weight(_, _, capacity) => LinkInfo::new(capacity),
+            RawLink::Attributed { info, .. } => info,
+        }
+    }
+}
+
+/// An invalid configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The file cannot be read.
+    Io(String),
+    /// The file is not a configuration.
+    Syntax(String),
+    /// The connections refer to a different number of nodes from the one given.
+    NodeCount { nodes: usize, connections: usize },
+    SelfLoop(u32),
+    DuplicateLink(u32, u32),
+    /// A link without communication qubits at either end.
+    ZeroCapacity(u32, u32),
+    /// A link whose pairs are purified holds only one Bell pair at once.
+    PurificationCapacity(u32, u32),
+    /// A communication qubit serves a node which is not a neighbor.
+    NotAdjacent { node: u32, partner: u32 },
+    /// The connected components of the nodes.
+    Disconnected(Vec<Vec<u32>>),
+    /// A success probability out of (0, 1].
+    InvalidProbability(u32, u32),
+    /// A fidelity out of (1/2, 1].
+    InvalidFidelity(u32, u32),
+    /// A negative or infinite length.
+    InvalidLength(u32, u32),
+    /// A circuit has more qubits than the data qubits of the nodes.
+    TooManyQubits { qubits: usize, capacity: usize },
+    /// The parameters of a generated network are invalid.
+    InvalidTopology(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Syntax(msg) => write!(f, "{}", msg),
+            Error::NodeCount { nodes, connections } => {
+                write!(f, "{} nodes are given, but the connections refer to {} nodes", nodes, connections)
+            },
+            Error::SelfLoop(u) => write!(f, "node {} is connected to itself", u),
+            Error::DuplicateLink(u, v) => write!(f, "nodes {} and {} are connected more than once", u, v),
+            Error::ZeroCapacity(u, v) => {
+                write!(f, "the link between nodes {} and {} has no communication qubits; give it a positive weight or `comm_qubits`", u, v)
+            },
+            Error::PurificationCapacity(u, v) => {
+                write!(f, "the link between nodes {} and {} needs two communication qubits at both ends for purification", u, v)
+            },
+            Error::NotAdjacent { node, partner } => {
+                write!(f, "a communication qubit of node {} serves node {}, which is not connected to it", node, partner)
+            },
+            Error::Disconnected(comps) => {
+                let comps: Vec<_> = comps.iter()
+                    .map(|c| format!("{{{}}}", c.iter().map(|u| u.to_string()).collect::<Vec<_>>().join(", ")))
+                    .collect();
+                write!(f, "the nodes are not connected: {}", comps.join(", "))
+            },
+            Error::InvalidProbability(u, v) => {
+                write!(f, "the success probability of the link between nodes {} and {} is not in (0, 1]", u, v)
+            },
+            Error::InvalidFidelity(u, v) => {
+                write!(f, "the fidelity of the link between nodes {} and {} is not in (0.5, 1], where purification improves it", u, v)
+            },
+            Error::InvalidLength(u, v) => write!(f, "the length of the link between nodes {} and {} is invalid", u, v),
+            Error::TooManyQubits { qubits, capacity } => {
+                write!(f, "the circuit has {} qubits, but the nodes have {} data qubits", qubits, capacity)
+            },
+            Error::InvalidTopology(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl TryFrom<RawConfiguration> for Configuration {
+    type Error = Error;
+
+    fn try_from(raw: RawConfiguration) -> Result<Self, Error> {
+        let RawConfiguration { connections, nodes } = raw;
+        let n = nodes.len();
+        let referred = connections.iter().map(|l| l.ends()).map(|(u, v)| u.max(v) as usize + 1).max().unwrap_or(0);
+        // a single node needs no connections
+        if referred != n && !(connections.is_empty() && n == 1) {
+            return Err(Error::NodeCount { nodes: n, connections: referred });
+        }
+        let mut links = std::collections::HashSet::new();
+        let mut g = ConnectionGraph::new();
+        for _ in 0..n {
+            g.add_node(());
+        }
+        for link in connections {
+            let (u, v) = link.ends();
+            if u == v {
+                return Err(Error::SelfLoop(u));
+            }
+            if !links.insert((u.min(v), u.max(v))) {
+                return Err(Error::DuplicateLink(u, v));
+            }
+            let info = link.into_info();
+            if info.success_probability.is_some_and(|p| !(p > 0.0 && p <= 1.0)) {
+                return Err(Error::InvalidProbability(u, v));
+            }
+            if info.fidelity.is_some_and(|f| !(f > 0.5 && f <= 1.0)) {
+                return Err(Error::InvalidFidelity(u, v));
+            }
+            if info.length.is_some_and(|l| !(l >= 0.0 && l.is_finite())) {
+                return Err(Error::InvalidLength(u, v));
+            }
+            g.add_edge(u as NodeIndex, v as NodeIndex, info);
+        }
+        let config = Self { connections: g, nodes };
+
+        for (u, node) in config.nodes.iter().enumerate() {
+            if let Some(CommQubits::Dedicated(qubits)) = &node.comm_qubits {
+                let partner = qubits.iter().flatten().find(|&&v| !links.contains(&((u as u32).min(v), (u as u32).max(v))));
+                if let Some(&partner) = partner {
+                    return Err(Error::NotAdjacent { node: u as u32, partner });
+                }
+            }
+        }
+        for e in config.connections.edges() {
+            let (u, v) = (e.source(), e.target());
+            let capacity = config.link_capacity(u, v);
+            if capacity == 0 {
+                return Err(Error::ZeroCapacity(u as u32, v as u32));
+            }
+            // a round of purification holds the pair being purified and a sacrificed one at once
+            if config.purification_rounds(u, v) > 0 && capacity < 2 {
+                return Err(Error::PurificationCapacity(u as u32, v as u32));
+            }
+        }
+        let comps = config.components();
+        if comps.len() > 1 {
+            return Err(Error::Disconnected(comps));
+        }
+        Ok(config)
+    }
+}
+
 impl NodeInfo {
-    pub fn num_of_qubits(&self) -> u32 {
-        self.num_of_qubits
+    /// A node of `data_qubits` data qubits with the default costs.
+    pub fn new(data_qubits: u32) -> Self {
+        Self {
+            data_qubits,
+            comm_qubits: None,
+            single_gate_cost: default_single_gate_cost(),
+            local_cx_cost: default_local_cx_cost(),
+            gen_ent_cost: default_gen_ent_cost(),
+            measure_cost: default_measure_cost(),
+            classical_comm_cost: default_classical_comm_cost(),
+            purification_rounds: 0,
+        }
+    }
+
+    pub fn with_single_gate_cost(mut self, cost: u64) -> Self {
+        self.single_gate_cost = cost;
+        self
+    }
+
+    pub fn with_local_cx_cost(mut self, cost: u64) -> Self {
+        self.local_cx_cost = cost;
+        self
+    }
+
+    pub fn with_gen_ent_cost(mut self, cost: u64) -> Self {
+        self.gen_ent_cost = cost;
+        self
+    }
+
+    pub fn with_measure_cost(mut self, cost: u64) -> Self {
+        self.measure_cost = cost;
+        self
+    }
+
+    pub fn with_classical_comm_cost(mut self, cost: u64) -> Self {
+        self.classical_comm_cost = cost;
+        self
+    }
+
+    pub fn with_comm_qubits(mut self, comm_qubits: CommQubits) -> Self {
+        self.comm_qubits = Some(comm_qubits);
+        self
+    }
+
+    pub fn with_purification_rounds(mut self, rounds: u32) -> Self {
+        self.purification_rounds = rounds;
+        self
+    }
+
+    pub fn num_of_data_qubits(&self) -> u32 {
+        self.data_qubits
     }
 
     pub fn single_gate_cost(&self) -> u64 {
@@ -54,6 +367,10 @@ impl NodeInfo {
     pub fn classical_comm_cost(&self) -> u64 {
         self.classical_comm_cost
     }
+
+    pub fn purification_rounds(&self) -> u32 {
+        self.purification_rounds
+    }
 }
 
 /// Configuration of a distributed system
@@ -65,9 +382,72 @@ impl Configuration {
         }
     }
 
-    pub fn from_json(path: String) -> Self {
-        let json_str = fs::read_to_string(path).unwrap();
-        serde_json::from_str(&json_str).unwrap()
+    pub fn from_json(path: String) -> Result<Self, Error> {
+        let json_str = fs::read_to_string(path).map_err(|e| Error::Io(e.to_string()))?;
+        let raw: RawConfiguration = serde_json::from_str(&json_str).map_err(|e| Error::Syntax(e.to_string()))?;
+        Self::try_from(raw)
+    }
+
+    /// The configuration in the JSON format of the configuration files.
+    pub fn to_json(&self) -> String {
+        let mut nodes = Vec::new();
+        for node in &self.nodes {
+            let mut buf = Vec::new();
+            let mut ser = serde_json::Serializer::with_formatter(&mut buf, serde_json::ser::PrettyFormatter::with_indent(b"    "));
+            node.serialize(&mut ser).unwrap();
+            let node = String::from_utf8(buf).unwrap();
+            nodes.push(node.lines().map(|l| format!("        {}", l)).collect::<Vec<_>>().join("\n"));
+        }
+        let connections: Vec<_> = self.connections.edges().iter()
+            .map(|e| {
+                let (u, v, info) = (e.source() as u32, e.target() as u32, e.weight().clone());
+                let link = if info == LinkInfo::new(info.capacity) {
+                    RawLink::Weight(u, v, info.capacity)
+                } else {
+                    RawLink::Attributed { ends: (u, v), info }
+                };
+                format!("        {}", serde_json::to_string(&link).unwrap().replace(',', ", "))
+            })
+            .collect();
+        format!(
+            "{{\n    \"nodes\": [\n{}\n    ],\n    \"connections\": [\n{}\n    ]\n}}\n",
+            nodes.join(",\n"), connections.join(",\n")
+        )
+    }
+
+    /// Checks that a circuit of `qubits` qubits fits in the data qubits of the nodes.
+    pub fn check_qubits(&self, qubits: usize) -> Result<(), Error> {
+        let capacity = self.nodes.iter().map(|node| node.data_qubits as usize).sum();
+        if qubits > capacity {
+            return Err(Error::TooManyQubits { qubits, capacity });
+        }
+        Ok(())
+    }
+
+    /// The connected components of the nodes.
+    fn components(&self) -> Vec<Vec<u32>> {
+        let mut comp = vec![usize::MAX; self.nodes.len()];
+        let mut res = Vec::new();
+        for s in 0..self.nodes.len() {
+            if comp[s] != usize::MAX {
+                continue;
+            }
+            comp[s] = res.len();
+            let mut stack = vec![s];
+            let mut members = Vec::new();
+            while let Some(u) = stack.pop() {
+                members.push(u as u32);
+                for v in self.connections.neighbors(u) {
+                    if comp[v] == usize::MAX {
+                        comp[v] = res.len();
+                        stack.push(v);
+                    }
+                }
+            }
+            members.sort();
+            res.push(members);
+        }
+        res
     }
 
     pub fn node_size(&self) -> usize {
@@ -81,22 +461,143 @@ impl Configuration {
     pub fn connections(&self) -> &ConnectionGraph {
         &self.connections
     }
-}
 
-fn from_graph<'de, D>(deserializer: D) -> Result<ConnectionGraph, D::Error>
-where
-    D: Deserializer<'de>
-{
-    let edges: Vec<(u32, u32, u32)> = Deserialize::deserialize(deserializer)?;
-    let node_count = edges.iter().fold(0, |acc, &(u, v, _)| u32::max(acc, u32::max(u, v))) + 1;
-    let mut g = ConnectionGraph::new();
-    for _ in 0..node_count {
-        g.add_node(());
+    /// The nodes with which each communication qubit of `u` can share Bell pairs.
+    /// When the node does not declare them, each link of weight `w` has `w` qubits at both ends,
+    /// which is how configurations without `comm_qubits` have been interpreted.
+    pub fn comm_qubits(&self, u: usize) -> Vec<Vec<u32>> {
+        let g = &self.connections;
+        match &self.nodes[u].comm_qubits {
+            Some(CommQubits::Shared(n)) => {
+                let mut nbrs: Vec<_> = g.edges().iter()
+                    .filter_map(|e| match (e.source(), e.target()) {
+                        (s, t) if s == u && t != u => Some(t as u32),
+                        (s, t) if t == u && s != u => Some(s as u32),
+                        _ => None,
+                    })
+                    .collect();
+                nbrs.sort();
+                nbrs.dedup();
+                vec![nbrs; *n as usize]
+            },
+            Some(CommQubits::Dedicated(qubits)) => qubits.clone(),
+            None => g.edges().iter().flat_map(|e| {
+                let v = match (e.source(), e.target()) {
+                    (s, t) if s == t => return vec![],
+                    (s, t) if s == u => t,
+                    (s, t) if t == u => s,
+                    _ => return vec![],
+                };
+                vec![vec![v as u32]; e.weight().capacity as usize]
+            }).collect(),
+        }
     }
-    for (u, v, cap) in edges {
-        g.add_edge(u as NodeIndex, v as NodeIndex, cap);
+
+    /// The number of communication qubits of `u` which can share Bell pairs with `v`.
+    pub fn comm_capacity(&self, u: usize, v: usize) -> u32 {
+        self.comm_qubits(u).iter().filter(|partners| partners.contains(&(v as u32))).count() as u32
+    }
+
+    /// Whether the communication qubits of `u` can hold Bell pairs with each of `partners` at once,
+    /// that is, the pairs can be matched to distinct qubits serving their partners.
+    pub fn can_hold(&self, u: usize, partners: &[u32]) -> bool {
+        let qubits = self.comm_qubits(u);
+        // the pair held by each qubit
+        let mut pair_of = vec![None; qubits.len()];
+        fn augment(i: usize, partners: &[u32], qubits: &[Vec<u32>], pair_of: &mut [Option<usize>], visited: &mut [bool]) -> bool {
+            for q in 0..qubits.len() {
+                if visited[q] || !qubits[q].contains(&partners[i]) {
+                    continue;
+                }
+                visited[q] = true;
+                if pair_of[q].is_none_or(|j| augment(j, partners, qubits, pair_of, visited)) {
+                    pair_of[q] = Some(i);
+                    return true;
+                }
+            }
+            false
+        }
+        (0..partners.len()).all(|i| augment(i, partners, &qubits, &mut pair_of, &mut vec![false; qubits.len()]))
+    }
+
+    /// The link between `u` and `v`.
+    pub fn link(&self, u: usize, v: usize) -> Option<&LinkInfo> {
+        let g = &self.connections;
+        g.outgoing_edges(u).iter().chain(g.incoming_edges(u)).map(|&e| g.edge(e))
+            .find(|e| (e.source(), e.target()) == (u, v) || (e.source(), e.target()) == (v, u))
+            .map(|e| e.weight())
+    }
+
+    /// The time of an attempt to generate a Bell pair between `u` and `v`,
+    /// which is the same from both ends. The heralding signal travels the whole fiber.
+    pub fn gen_ent_time(&self, u: usize, v: usize) -> u64 {
+        let nodes = self.nodes[u].gen_ent_cost.max(self.nodes[v].gen_ent_cost);
+        match self.link(u, v) {
+            Some(link) => link.gen_ent_time.unwrap_or(nodes + link.fiber_delay()),
+            None => nodes,
+        }
+    }
+
+    /// The probability that an attempt to generate a Bell pair between `u` and `v` succeeds.
+    pub fn success_probability(&self, u: usize, v: usize) -> f64 {
+        self.link(u, v).map_or(1.0, |link| {
+            link.success_probability.unwrap_or_else(|| 10f64.powf(-FIBER_LOSS_PER_KM * link.length.unwrap_or(0.0) / 10.0))
+        })
+    }
+
+    /// The expected time to generate a Bell pair between `u` and `v` with retries of failed attempts.
+    pub fn expected_gen_ent_time(&self, u: usize, v: usize) -> u64 {
+        (self.gen_ent_time(u, v) as f64 / self.success_probability(u, v)).round() as u64
+    }
+
+    /// The number of purification rounds of the Bell pairs between adjacent nodes `u` and `v`.
+    pub fn purification_rounds(&self, u: usize, v: usize) -> u32 {
+        self.nodes[u].purification_rounds().max(self.nodes[v].purification_rounds())
+    }
+
+    /// The expected number of Bell pairs generated between `u` and `v` per pair generated by a program.
+    /// A purified pair takes a raw pair for each round in a program, which only has the run where every round succeeds.
+    /// The rounds pump the pair being purified with raw pairs by BBPSSW,
+    /// and a failed round restarts the purification from a raw pair.
+    pub fn purification_overhead(&self, u: usize, v: usize) -> f64 {
+        let rounds = self.purification_rounds(u, v);
+        let raw = self.link(u, v).and_then(|link| link.fidelity).unwrap_or(1.0);
+        // the fidelity of the pair being purified and the expected number of raw pairs to have it
+        let (mut fidelity, mut pairs) = (raw, 1.0);
+        for _ in 0..rounds {
+            let (f, r) = (fidelity, raw);
+            let success = f * r + f * (1.0 - r) / 3.0 + (1.0 - f) * r / 3.0 + 5.0 * (1.0 - f) * (1.0 - r) / 9.0;
+            fidelity = (f * r + (1.0 - f) * (1.0 - r) / 9.0) / success;
+            pairs = (pairs + 1.0) / success;
+        }
+        pairs / (rounds + 1) as f64
+    }
+
+    /// The propagation delay of classical messages over the link between `u` and `v`,
+    /// which is in addition to `classical_comm_cost` of the nodes.
+    pub fn classical_latency(&self, u: usize, v: usize) -> u64 {
+        self.link(u, v).map_or(0, |link| link.classical_latency.unwrap_or_else(|| link.fiber_delay()))
+    }
+
+    /// The propagation delays of classical messages from `u` to each node along the links.
+    pub fn message_latencies(&self, u: usize) -> Vec<u64> {
+        let g = &self.connections;
+        let dist = dijkstra(g, |e| self.classical_latency(g.edge(e).source(), g.edge(e).target()) as u32, u).0;
+        dist.into_iter().map(|d| d.map_or(0, u64::from)).collect()
+    }
+
+    /// The number of Bell pairs which the link between `u` and `v` can hold at once.
+    pub fn link_capacity(&self, u: usize, v: usize) -> u32 {
+        self.comm_capacity(u, v).min(self.comm_capacity(v, u))
     }
-    Ok(g)
+}
+
+fn default_capacity() -> u32 {
+    1
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
 }
 
 /*
@@ -130,3 +631,111 @@ fn default_measure_cost() -> u64 {
 fn default_classical_comm_cost() -> u64 {
     30
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::testing::ConfigBuilder;
+
+    #[test]
+    fn migrates_link_weights() {
+        let node = r#"{ "num_of_qubits": 4 }"#;
+        let config: Configuration = serde_json::from_str(&format!(
+            r#"{{ "nodes": [{}], "connections": [[0, 1, 2], [1, 2, 1]] }}"#, [node; 3].join(", ")
+        )).unwrap();
+        assert_eq!(config.node_info_ref(0).num_of_data_qubits(), 4);
+        assert_eq!(config.comm_qubits(1), vec![vec![0], vec![0], vec![2]]);
+        assert_eq!((config.comm_capacity(1, 0), config.comm_capacity(1, 2), config.comm_capacity(0, 2)), (2, 1, 0));
+        assert!(config.can_hold(1, &[0, 0, 2]));
+        assert!(!config.can_hold(1, &[2, 2]));
+    }
+
+    #[test]
+    fn declares_comm_qubits() {
+        let line = |comm_qubits| ConfigBuilder::new(&[4; 3]).node(1, |n| n.with_comm_qubits(comm_qubits)).links(&[(0, 1, 2), (1, 2, 1)]).build();
+        let config = line(CommQubits::Shared(2));
+        assert_eq!(config.comm_qubits(1), vec![vec![0, 2], vec![0, 2]]);
+        assert_eq!(config.link_capacity(0, 1), 2);
+        assert_eq!(config.link_capacity(1, 2), 1);
+        assert!(config.can_hold(1, &[0, 2]));
+        assert!(!config.can_hold(1, &[0, 2, 0]));
+
+        let config = line(CommQubits::Dedicated(vec![vec![0], vec![0, 2]]));
+        // the first pair with node 0 needs to be on the dedicated qubit
+        assert!(config.can_hold(1, &[0, 2]));
+        assert!(!config.can_hold(1, &[2, 2]));
+    }
+
+    fn validate(nodes: &str, connections: &str) -> Result<Configuration, Error> {
+        let raw: RawConfiguration = serde_json::from_str(&format!(r#"{{ "nodes": [{}], "connections": [{}] }}"#, nodes, connections)).unwrap();
+        Configuration::try_from(raw)
+    }
+
+    #[test]
+    fn rejects_invalid_configurations() {
+        let node = r#"{ "data_qubits": 2 }"#;
+        let three = [node; 3].join(", ");
+        assert!(validate(&three, "[0, 1, 1], [1, 2, 1]").is_ok());
+        assert!(validate(node, "").is_ok());
+        assert_eq!(validate(&three, "[0, 1, 1], [1, 3, 1]").unwrap_err(), Error::NodeCount { nodes: 3, connections: 4 });
+        assert_eq!(validate(&three, "[0, 1, 1]").unwrap_err(), Error::NodeCount { nodes: 3, connections: 2 });
+        assert_eq!(validate(&three, "[0, 1, 1], [1, 1, 1], [1, 2, 1]").unwrap_err(), Error::SelfLoop(1));
+        assert_eq!(validate(&three, "[0, 1, 1], [1, 2, 1], [2, 1, 1]").unwrap_err(), Error::DuplicateLink(2, 1));
+        assert_eq!(validate(&three, "[0, 1, 1], [1, 2, 0]").unwrap_err(), Error::ZeroCapacity(1, 2));
+        let purified = format!(r#"{}, {}, {{ "data_qubits": 2, "purification_rounds": 1 }}"#, node, node);
+        assert_eq!(validate(&purified, "[0, 1, 1], [1, 2, 1]").unwrap_err(), Error::PurificationCapacity(1, 2));
+        assert!(validate(&purified, "[0, 1, 1], [1, 2, 2]").is_ok());
+        let four = [node; 4].join(", ");
+        assert_eq!(validate(&four, "[0, 2, 1], [1, 3, 1]").unwrap_err(), Error::Disconnected(vec![vec![0, 2], vec![1, 3]]));
+
+        let dedicated = format!(r#"{}, {{ "data_qubits": 2, "comm_qubits": [[0], [3]] }}, {}, {}"#, node, node, node);
+        assert_eq!(validate(&dedicated, "[0, 1, 1], [1, 2, 1], [2, 3, 1]").unwrap_err(), Error::NotAdjacent { node: 1, partner: 3 });
+        // no qubit of node 1 serves node 2
+        let dedicated = format!(r#"{}, {{ "data_qubits": 2, "comm_qubits": [[0]] }}, {}"#, node, node);
+        assert_eq!(validate(&dedicated, "[0, 1, 1], [1, 2, 1]").unwrap_err(), Error::ZeroCapacity(1, 2));
+
+        let config = validate(&three, "[0, 1, 1], [1, 2, 1]").unwrap();
+        assert!(config.check_qubits(6).is_ok());
+        assert_eq!(config.check_qubits(7).unwrap_err(), Error::TooManyQubits { qubits: 7, capacity: 6 });
+    }
+
+    #[test]
+    fn link_attributes() {
+        let nodes = r#"{ "data_qubits": 2, "gen_ent_cost": 1000 }, { "data_qubits": 2, "gen_ent_cost": 2000 }, { "data_qubits": 2 }"#;
+        let links = r#"[0, 1, 1], { "ends": [1, 2], "capacity": 2, "length": 10.0 }"#;
+        let config = validate(nodes, links).unwrap();
+        // the same from both ends
+        assert_eq!((config.gen_ent_time(0, 1), config.gen_ent_time(1, 0)), (2000, 2000));
+        assert_eq!(config.gen_ent_time(1, 2), 2000 + 50_000);
+        assert!((config.success_probability(1, 2) - 10f64.powf(-0.2)).abs() < 1e-9);
+        assert_eq!(config.expected_gen_ent_time(0, 1), 2000);
+        assert_eq!(config.message_latencies(0), [0, 0, 50_000]);
+        assert_eq!(config.comm_capacity(2, 1), 2);
+
+        let links = r#"[0, 1, 1], { "ends": [1, 2], "gen_ent_time": 300, "success_probability": 0.5, "classical_latency": 7 }"#;
+        let config = validate(nodes, links).unwrap();
+        assert_eq!(config.expected_gen_ent_time(2, 1), 600);
+        assert_eq!(config.message_latencies(2), [7, 7, 0]);
+        // the attributes are kept in the file
+        let raw: RawConfiguration = serde_json::from_str(&config.to_json()).unwrap();
+        assert_eq!(Configuration::try_from(raw).unwrap().expected_gen_ent_time(1, 2), 600);
+
+        let links = r#"[0, 1, 1], { "ends": [1, 2], "success_probability": 0.0 }"#;
+        assert_eq!(validate(nodes, links).unwrap_err(), Error::InvalidProbability(1, 2));
+    }
+
+    #[test]
+    fn purification_overhead() {
+        let pair = |rounds, link: LinkInfo| ConfigBuilder::new(&[2, 2]).node(1, |n| n.with_purification_rounds(rounds)).link_with(0, 1, link).try_build();
+        let config = pair(1, LinkInfo::new(2)).unwrap();
+        assert_eq!(config.purification_overhead(0, 1), 1.0);
+        let config = pair(1, LinkInfo::new(2).with_fidelity(0.9)).unwrap();
+        // a round succeeds with probability 0.81 + 2 * 0.9 * 0.1 / 3 + 5 * 0.1 * 0.1 / 9
+        let success = 0.81 + 0.06 + 0.05 / 9.0;
+        assert!((config.purification_overhead(1, 0) - 2.0 / success / 2.0).abs() < 1e-9);
+        let config = pair(0, LinkInfo::new(1).with_fidelity(0.9)).unwrap();
+        assert_eq!(config.purification_overhead(0, 1), 1.0);
+
+        assert_eq!(pair(1, LinkInfo::new(2).with_fidelity(0.5)).unwrap_err(), Error::InvalidFidelity(0, 1));
+    }
+}
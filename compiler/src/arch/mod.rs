@@ -1,3 +1,7 @@
 pub mod configuration;
+pub mod topology;
+#[cfg(test)]
+pub(crate) mod testing;
 
-pub use configuration::{NodeInfo, Configuration};
+pub use configuration::{NodeInfo, LinkInfo, CommQubits, Configuration, Error as ConfigError};
+pub use topology::{Topology, generate};
@@ -0,0 +1,50 @@
+use crate::arch::configuration::{Configuration, Error, LinkInfo, NodeInfo, RawConfiguration, RawLink};
+
+/// Builds configurations in tests, which are validated as the configuration files are.
+pub(crate) struct ConfigBuilder {
+    nodes: Vec<NodeInfo>,
+    links: Vec<RawLink>,
+}
+
+impl ConfigBuilder {
+    /// Nodes with `data_qubits` data qubits each and the default costs, without links.
+    pub fn new(data_qubits: &[u32]) -> Self {
+        Self { nodes: data_qubits.iter().map(|&n| NodeInfo::new(n)).collect(), links: Vec::new() }
+    }
+
+    /// Modifies node `u` by `f`.
+    pub fn node(mut self, u: usize, f: impl FnOnce(NodeInfo) -> NodeInfo) -> Self {
+        self.nodes[u] = f(self.nodes[u].clone());
+        self
+    }
+
+    /// Modifies every node by `f`.
+    pub fn nodes(mut self, f: impl Fn(NodeInfo) -> NodeInfo) -> Self {
+        self.nodes = self.nodes.into_iter().map(f).collect();
+        self
+    }
+
+    /// Connects `u` and `v` by a link of `capacity` communication qubits at both ends.
+    pub fn link(self, u: u32, v: u32, capacity: u32) -> Self {
+        self.link_with(u, v, LinkInfo::new(capacity))
+    }
+
+    /// Connects each `(u, v, capacity)` of `links`.
+    pub fn links(self, links: &[(u32, u32, u32)]) -> Self {
+        links.iter().fold(self, |b, &(u, v, capacity)| b.link(u, v, capacity))
+    }
+
+    /// Connects `u` and `v` by a link of the attributes `info`.
+    pub fn link_with(mut self, u: u32, v: u32, info: LinkInfo) -> Self {
+        self.links.push(RawLink::Attributed { ends: (u, v), info });
+        self
+    }
+
+    pub fn try_build(self) -> Result<Configuration, Error> {
+        Configuration::try_from(RawConfiguration { nodes: self.nodes, connections: self.links })
+    }
+
+    pub fn build(self) -> Configuration {
+        self.try_build().unwrap()
+    }
+}
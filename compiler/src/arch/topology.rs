@@ -0,0 +1,198 @@
+use std::collections::BTreeSet;
+use rand::{seq::SliceRandom, SeedableRng, rngs::StdRng};
+use crate::arch::configuration::{Configuration, Error, NodeInfo, RawConfiguration, RawLink};
+
+/// The number of attempts to draw a random regular network.
+const RANDOM_ATTEMPTS: usize = 1000;
+
+/// Shapes of networks of nodes.
+#[derive(Debug, Clone, PartialEq, Eq, clap::Subcommand)]
+pub enum Topology {
+    /// Nodes in a chain
+    Line { nodes: u32 },
+    /// Nodes in a cycle
+    Ring { nodes: u32 },
+    /// Node 0 connected to all the others
+    Star { nodes: u32 },
+    /// A two-dimensional grid, where node `r * cols + c` is at row `r` and column `c`
+    Grid { rows: u32, cols: u32 },
+    /// A grid whose rows and columns wrap around
+    Torus { rows: u32, cols: u32 },
+    /// `2^dim` nodes connected when their indices differ in one bit
+    Hypercube { dim: u32 },
+    /// Every pair of nodes connected
+    Full { nodes: u32 },
+    /// A complete tree, where the children of node `i` are `arity * i + 1` to `arity * i + arity`
+    Tree { arity: u32, depth: u32 },
+    /// A connected network where every node has `degree` links, drawn with `seed`
+    RandomRegular {
+        nodes: u32,
+        degree: u32,
+        #[clap(long, default_value = "0")]
+        seed: u64,
+    },
+}
+
+impl Topology {
+    /// The number of nodes, which is an error if it does not fit in `u32`.
+    pub fn node_count(&self) -> Result<u32, Error> {
+        let count = match *self {
+            Topology::Line { nodes } | Topology::Ring { nodes } | Topology::Star { nodes }
+            | Topology::Full { nodes } | Topology::RandomRegular { nodes, .. } => Some(nodes),
+            Topology::Grid { rows, cols } | Topology::Torus { rows, cols } => rows.checked_mul(cols),
+            Topology::Hypercube { dim } => 1u32.checked_shl(dim),
+            Topology::Tree { arity, depth } => {
+                (0..=depth).try_fold(0u32, |n, d| arity.checked_pow(d).and_then(|m| n.checked_add(m)))
+            },
+        };
+        count.ok_or_else(|| Error::InvalidTopology("the network has too many nodes".to_string()))
+    }
+
+    /// The links between the nodes. Links which would be duplicated, such as the wraps of a ring of two nodes, are merged.
+    pub fn links(&self) -> Result<Vec<(u32, u32)>, Error> {
+        let n = self.node_count()?;
+        let invalid = |msg: &str| Err(Error::InvalidTopology(msg.to_string()));
+        if n == 0 {
+            return invalid("a network needs a node");
+        }
+        let links: Vec<_> = match *self {
+            Topology::Line { nodes } => (1..nodes).map(|i| (i - 1, i)).collect(),
+            Topology::Ring { nodes } => (0..nodes).map(|i| (i, (i + 1) % nodes)).collect(),
+            Topology::Star { nodes } => (1..nodes).map(|i| (0, i)).collect(),
+            Topology::Grid { rows, cols } | Topology::Torus { rows, cols } => {
+                let wrap = matches!(self, Topology::Torus { .. });
+                let id = |r: u32, c: u32| r * cols + c;
+                let mut links = Vec::new();
+                for r in 0..rows {
+                    for c in 0..cols {
+                        if c + 1 < cols || wrap {
+                            links.push((id(r, c), id(r, (c + 1) % cols)));
+                        }
+                        if r + 1 < rows || wrap {
+                            links.push((id(r, c), id((r + 1) % rows, c)));
+                        }
+                    }
+                }
+                links
+            },
+            Topology::Hypercube { dim } => {
+                (0..n).flat_map(|u| (0..dim).map(move |b| (u, u ^ (1 << b)))).filter(|(u, v)| u < v).collect()
+            },
+            Topology::Full { nodes } => (0..nodes).flat_map(|u| (u + 1..nodes).map(move |v| (u, v))).collect(),
+            Topology::Tree { arity, .. } => (1..n).map(|v| ((v - 1) / arity, v)).collect(),
+            Topology::RandomRegular { nodes, degree, seed } => {
+                if degree >= nodes || (nodes as u64 * degree as u64) % 2 == 1 || (nodes > 1 && degree == 0) {
+                    return invalid("a random regular network needs a degree less than the nodes and an even number of link ends");
+                }
+                match random_regular(nodes, degree, seed) {
+                    Some(links) => links,
+                    None => return invalid("no connected random regular network is found; try another seed"),
+                }
+            },
+        };
+        // merges the duplicates and removes the loops of sizes 1 and 2
+        let links: BTreeSet<_> = links.into_iter().filter(|(u, v)| u != v).map(|(u, v)| (u.min(v), u.max(v))).collect();
+        Ok(links.into_iter().collect())
+    }
+}
+
+/// Draws a connected simple regular network by pairing the ends of the links at random.
+fn random_regular(nodes: u32, degree: u32, seed: u64) -> Option<Vec<(u32, u32)>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut ends: Vec<_> = (0..nodes).flat_map(|u| std::iter::repeat_n(u, degree as usize)).collect();
+    for _ in 0..RANDOM_ATTEMPTS {
+        ends.shuffle(&mut rng);
+        let links: Vec<_> = ends.chunks(2).map(|p| (p[0].min(p[1]), p[0].max(p[1]))).collect();
+        let simple = links.iter().all(|(u, v)| u != v) && links.iter().collect::<BTreeSet<_>>().len() == links.len();
+        if simple && connected(nodes, &links) {
+            return Some(links);
+        }
+    }
+    None
+}
+
+fn connected(nodes: u32, links: &[(u32, u32)]) -> bool {
+    let mut adj = vec![Vec::new(); nodes as usize];
+    for &(u, v) in links {
+        adj[u as usize].push(v as usize);
+        adj[v as usize].push(u as usize);
+    }
+    let mut visited = vec![false; nodes as usize];
+    let mut stack = vec![0];
+    visited[0] = true;
+    while let Some(u) = stack.pop() {
+        for &v in &adj[u] {
+            if !visited[v] {
+                visited[v] = true;
+                stack.push(v);
+            }
+        }
+    }
+    visited.into_iter().all(|v| v)
+}
+
+/// A configuration of `topology` whose nodes are `node` and whose links have `link_capacity` communication qubits at both ends.
+pub fn generate(topology: &Topology, node: &NodeInfo, link_capacity: u32) -> Result<Configuration, Error> {
+    let connections = topology.links()?.into_iter().map(|(u, v)| RawLink::Weight(u, v, link_capacity)).collect();
+    let nodes = vec![node.clone(); topology.node_count()? as usize];
+    Configuration::try_from(RawConfiguration { nodes, connections })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn degrees(topology: &Topology) -> Vec<usize> {
+        let mut deg = vec![0; topology.node_count().unwrap() as usize];
+        for (u, v) in topology.links().unwrap() {
+            deg[u as usize] += 1;
+            deg[v as usize] += 1;
+        }
+        deg
+    }
+
+    #[test]
+    fn shapes() {
+        assert_eq!(Topology::Line { nodes: 3 }.links().unwrap(), [(0, 1), (1, 2)]);
+        assert_eq!(Topology::Ring { nodes: 2 }.links().unwrap(), [(0, 1)]);
+        assert_eq!(Topology::Grid { rows: 2, cols: 3 }.links().unwrap().len(), 7);
+        assert_eq!(degrees(&Topology::Torus { rows: 3, cols: 4 }), vec![4; 12]);
+        assert_eq!(degrees(&Topology::Hypercube { dim: 3 }), vec![3; 8]);
+        assert_eq!(degrees(&Topology::Full { nodes: 5 }), vec![4; 5]);
+        assert_eq!(degrees(&Topology::Star { nodes: 4 }), [3, 1, 1, 1]);
+        assert_eq!(degrees(&Topology::Tree { arity: 2, depth: 2 }), [2, 3, 3, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn random_regular_networks() {
+        let topology = Topology::RandomRegular { nodes: 10, degree: 3, seed: 7 };
+        assert_eq!(degrees(&topology), vec![3; 10]);
+        assert_eq!(topology.links().unwrap(), topology.links().unwrap());
+        assert!(matches!(Topology::RandomRegular { nodes: 5, degree: 3, seed: 0 }.links(), Err(Error::InvalidTopology(_))));
+    }
+
+    #[test]
+    fn too_many_nodes() {
+        for topology in [
+            Topology::Hypercube { dim: 40 },
+            Topology::Tree { arity: 70000, depth: 3 },
+            Topology::Grid { rows: 70000, cols: 70000 },
+        ] {
+            assert!(matches!(topology.links(), Err(Error::InvalidTopology(_))));
+        }
+        assert_eq!(Topology::Hypercube { dim: 31 }.node_count(), Ok(1 << 31));
+    }
+
+    #[test]
+    fn writes_loadable_configurations() {
+        let node = NodeInfo::new(4).with_gen_ent_cost(500);
+        let config = generate(&Topology::Torus { rows: 3, cols: 3 }, &node, 2).unwrap();
+        let raw: RawConfiguration = serde_json::from_str(&config.to_json()).unwrap();
+        let loaded = Configuration::try_from(raw).unwrap();
+        assert_eq!(loaded.node_size(), 9);
+        assert_eq!(loaded.connections().edges().len(), 18);
+        assert_eq!(loaded.comm_capacity(0, 1), 2);
+        assert_eq!(loaded.node_info_ref(8).gen_ent_cost(), 500);
+        assert_eq!(loaded.node_info_ref(8).num_of_data_qubits(), 4);
+    }
+}
@@ -1,55 +1,30 @@
 pub mod decomposer;
+pub mod network;
 pub mod routing;
 
-use routing::{RemoteOpRouter, RemoteOp};
+use routing::{RemoteOpRouter, RemoteOp, Context};
 use inquir;
 use inquir::{SessionId, ParticipantId, Label, Process, OpenProc, System, LocProc};
-use graph::{
-    graph::NodeIndex,
-    algo::dijkstra,
-};
 use crate::{
-    arch::{Configuration, configuration::ConnectionGraph},
-    codegen::decomposer::Decomposer,
+    arch::Configuration,
+    codegen::{decomposer::Decomposer, network::{Network, Swapping}},
     optimizer,
     hir,
     utils::fresh_ids::{fresh_var_id, fresh_ent_id, fresh_label_id},
 };
-use std::collections::HashMap;
-
-fn build_all_pair_shortest_path(g: &ConnectionGraph) -> Vec<Vec<Option<NodeIndex>>> {
-    let mut prevs = vec![Vec::new(); g.node_count()];
-    for s in 0..g.node_count() {
-        let (_, p) = dijkstra(g, |_| 1, s);
-        prevs[s] = p;
-    }
-
-    prevs
-}
+use std::collections::{HashMap, HashSet};
 
-fn construct_shortest_path(
-    prev: &Vec<Option<NodeIndex>>,
-    from: NodeIndex,
-    to: NodeIndex
-) -> Vec<NodeIndex> {
-    let mut path = Vec::new();
-    path.push(to);
-    let mut current = to;
-    while prev[current] != None {
-        current = prev[current].unwrap();
-        path.push(current);
-    }
-    assert!(current == from);
-
-    path.into_iter().rev().collect()
-}
+/// Where each qubit of the source circuit is at the end of the program: `q -> (p, x)`.
+/// The variable names can be changed by `RemoteOp::RSwap` and `RemoteOp::Move`.
+pub type Layout = HashMap<String, (ParticipantId, String)>;
 
-pub fn codegen(exps: Vec<hir::Expr>, config: &Configuration, allocator: Box<dyn RemoteOpRouter>, quasi: bool) -> inquir::System {
+pub fn codegen(exps: Vec<hir::Expr>, config: &Configuration, allocator: Box<dyn RemoteOpRouter>, swapping: Swapping, quasi: bool) -> (inquir::System, Layout) {
     let mut decomposer = Decomposer::new();
-    let s = route_telegates(exps, config, allocator);
+    let (s, layout) = route_telegates(exps, config, allocator, swapping);
     println!("[codegen] finish routing.");
     let s = decomposer.decompose(s);
     println!("[codegen] finish decomposition.");
+    assert_colocated(&s);
     let s = if quasi {
         let s = optimizer::standardize(s);
         println!("[codegen] finish standardization.");
@@ -59,33 +34,98 @@ pub fn codegen(exps: Vec<hir::Expr>, config: &Configuration, allocator: Box<dyn
     };
     //let s = optimizer::vectorize(s, config);
     //println!("[codegen] finish vectorization.");
-    s
+    (s, layout)
 }
 
-fn insert_entswap_chain(program: &mut Vec<Vec<inquir::Process>>, path: Vec<usize>) -> (String, String) {
-    let world = SessionId::new("world".to_string()); // TODO
-    // entanglement generations
+/// Panics if a gate is applied to a qubit which is not on the participant.
+fn assert_colocated(s: &System) {
+    match s {
+        System::Located(LocProc { p, procs }) => {
+            let mut qubits = HashSet::new();
+            for proc in procs {
+                if let Process::Apply(inquir::ApplyProc { gate, args, .. }) = proc {
+                    if let Some(x) = args.iter().find(|&x| !qubits.contains(x)) {
+                        panic!("{} is applied to {} which is not on participant {}", gate, x, p);
+                    }
+                }
+                qubits.extend(proc.gen_variables());
+                for x in proc.consumed_variables() {
+                    qubits.remove(&x);
+                }
+            }
+        },
+        System::Composition(ss) => ss.iter().for_each(assert_colocated),
+    }
+}
+
+/// Inserts a Bell pair between `from` and `to` through a path chosen by `network`,
+/// which is swapped by the schedule of `network`. Returns the qubits of `from` and `to`.
+fn insert_bell_pair(program: &mut [Vec<Process>], network: &mut Network, from: usize, to: usize) -> (String, String) {
+    let path = network.path(from, to);
+    let ent_ids = insert_gen_ents(program, network, &path);
+    match network.swapping() {
+        Swapping::Chain => insert_entswap_chain(program, &path, &ent_ids),
+        Swapping::Tree => insert_entswap_tree(program, &path, &ent_ids, 0, path.len() - 1),
+    }
+    (ent_ids[0].clone(), ent_ids[ent_ids.len() - 1].clone())
+}
+
+/// Inserts the entanglement generations of the links of `path`.
+/// `path[i]` has the qubits `ent_ids[2*i - 1]` toward `path[i - 1]` and `ent_ids[2*i]` toward `path[i + 1]`.
+fn insert_gen_ents(program: &mut [Vec<Process>], network: &Network, path: &[usize]) -> Vec<String> {
     let ent_ids: Vec<_> = (0..path.len()*2-2).map(|_| format!("_cq{}", fresh_ent_id())).collect();
-    let gen_ent_labels: Vec<_> = (0..path.len()-1).map(|_| Label::new(format!("l{}", fresh_label_id()))).collect();
-    for i in 0..path.len() {
-        if i >= 1 { // path[i] -> path[i - 1]
-            let gen = inquir::Process::GenEnt(inquir::GenEntProc {
-                x: ent_ids[2*i - 1].clone(),
-                p: ParticipantId::new(path[i - 1] as u32),
-                label: gen_ent_labels[i - 1].clone(),
-            });
-            program[path[i]].push(gen);
-        }
-        if i + 1 < path.len() { // path[i] -> path[i + 1]
-            let gen = inquir::Process::GenEnt(inquir::GenEntProc {
-                x: ent_ids[2*i].clone(),
-                p: ParticipantId::new(path[i + 1] as u32),
-                label: gen_ent_labels[i].clone(),
-            });
-            program[path[i]].push(gen);
+    for i in 0..path.len()-1 {
+        let (a, b) = (path[i], path[i + 1]);
+        insert_gen_ent(program, (a, &ent_ids[2*i]), (b, &ent_ids[2*i + 1]));
+        for _ in 0..network.purification_rounds(a, b) {
+            insert_purification_round(program, (a, &ent_ids[2*i]), (b, &ent_ids[2*i + 1]));
         }
     }
+    ent_ids
+}
+
+/// Inserts the generation of a Bell pair of `x` on `a` and `y` on `b`.
+fn insert_gen_ent(program: &mut [Vec<Process>], (a, x): (usize, &str), (b, y): (usize, &str)) {
+    let label = Label::new(format!("l{}", fresh_label_id()));
+    for (p, x, partner) in [(a, x, b), (b, y, a)] {
+        program[p].push(Process::GenEnt(inquir::GenEntProc {
+            x: x.to_string(),
+            p: ParticipantId::new(partner as u32),
+            label: label.clone(),
+        }));
+    }
+}
+
+/// Inserts a round of the recurrence purification (BBPSSW) of the Bell pair of `x` on `a` and `y` on `b`,
+/// which sacrifices another pair: both ends apply CX from their halves to the halves of the sacrificed pair,
+/// measure them, and compare the outcomes.
+/// The outcomes disagree if either pair has been flipped, and then the round has failed
+/// and the purification is retried from raw pairs. InQuIR has no loops, so this is the run where the round succeeds,
+/// and the simulator charges the retries by `Configuration::purification_overhead`.
+fn insert_purification_round(program: &mut [Vec<Process>], (a, x): (usize, &str), (b, y): (usize, &str)) {
+    let world = SessionId::new("world".to_string());
+    let (x2, y2) = (format!("_cq{}", fresh_ent_id()), format!("_cq{}", fresh_ent_id()));
+    insert_gen_ent(program, (a, &x2), (b, &y2));
+    let (label_a, label_b) = (Label::new(format!("l{}", fresh_label_id())), Label::new(format!("l{}", fresh_label_id())));
+    for (p, (kept, sacrificed), partner, (sent, received)) in [(a, (x, x2), b, (&label_a, &label_b)), (b, (y, y2), a, (&label_b, &label_a))] {
+        let (m, r) = (format!("_m{}", fresh_var_id()), format!("_m{}", fresh_var_id()));
+        program[p].extend([
+            Process::Apply(inquir::ApplyProc { gate: inquir::PrimitiveGate::CX, args: vec![kept.to_string(), sacrificed.clone()], ctrl: None }),
+            Process::Measure(inquir::MeasureProc { dst: m.clone(), args: vec![sacrificed.clone()] }),
+            Process::Free(inquir::FreeProc { arg: sacrificed }),
+            Process::Send(inquir::SendProc {
+                s: world.clone(),
+                dst: ParticipantId::new(partner as u32),
+                data: (sent.clone(), inquir::Expr::Var(m.clone())),
+            }),
+            Process::Recv(inquir::RecvProc { s: world.clone(), data: (received.clone(), r.clone()) }),
+        ]);
+    }
+}
 
+/// Swaps the Bell pairs hop by hop from `path[0]`.
+fn insert_entswap_chain(program: &mut [Vec<Process>], path: &[usize], ent_ids: &[String]) {
+    let world = SessionId::new("world".to_string()); // TODO
     // entanglement swapping chain
     // sending directions: X => i -> i + 1,   Z => i + 1 -> i
     let entswap_labels: Vec<_> = (0..(path.len()-2)*2).map(|_| Label::new(format!("l{}", fresh_label_id()))).collect();
@@ -142,22 +182,217 @@ fn insert_entswap_chain(program: &mut Vec<Vec<inquir::Process>>, path: Vec<usize
             program[path[0]].push(app_z);
         }
     }
+}
 
-    (ent_ids[0].clone(), ent_ids[ent_ids.len() - 1].clone())
+/// Swaps the Bell pairs of `path[l..=r]` into one between `path[l]` and `path[r]`
+/// by swapping both halves in parallel first, so that the corrections take logarithmic rounds.
+fn insert_entswap_tree(program: &mut [Vec<Process>], path: &[usize], ent_ids: &[String], l: usize, r: usize) {
+    if r - l < 2 {
+        return;
+    }
+    let m = (l + r) / 2;
+    // `path[m]` receives the corrections of its qubits from the halves before swapping them
+    insert_entswap_tree(program, path, ent_ids, l, m);
+    insert_entswap_tree(program, path, ent_ids, m, r);
+
+    let world = SessionId::new("world".to_string());
+    let (x1, x2) = (format!("_m{}", fresh_var_id()), format!("_m{}", fresh_var_id()));
+    let (label_z, label_x) = (Label::new(format!("l{}", fresh_label_id())), Label::new(format!("l{}", fresh_label_id())));
+    program[path[m]].push(Process::EntSwap(inquir::EntSwapProc {
+        x1: x1.clone(),
+        x2: x2.clone(),
+        arg1: ent_ids[2*m-1].clone(),
+        arg2: ent_ids[2*m].clone(),
+    }));
+    for (dst, label, x) in [(path[l], &label_z, x1), (path[r], &label_x, x2)] {
+        program[path[m]].push(Process::Send(inquir::SendProc {
+            s: world.clone(),
+            dst: ParticipantId::new(dst as u32),
+            data: (label.clone(), inquir::Expr::Var(x)),
+        }));
+    }
+    for (p, label, gate, arg) in [(path[l], label_z, inquir::PrimitiveGate::Z, &ent_ids[2*l]), (path[r], label_x, inquir::PrimitiveGate::X, &ent_ids[2*r-1])] {
+        let x = format!("_m{}", fresh_var_id());
+        program[p].push(Process::Recv(inquir::RecvProc { s: world.clone(), data: (label, x.clone()) }));
+        program[p].push(Process::Apply(inquir::ApplyProc { gate, args: vec![arg.clone()], ctrl: Some(inquir::Expr::Var(x)) }));
+    }
+}
+
+/// Inserts a remote CX from `c` on `pos1` to `t` on `pos2` through a path chosen by `network`,
+/// which is applied only if `ctrl` holds when it is given.
+fn insert_rcx(
+    program: &mut [Vec<Process>],
+    network: &mut Network,
+    (pos1, c): (usize, String),
+    (pos2, t): (usize, String),
+    uid: u32,
+    ctrl: Option<inquir::Expr>
+) {
+    let world = SessionId::new("world".to_string());
+    let (ent1, ent2) = insert_bell_pair(program, network, pos1, pos2);
+    let label = Label::new(format!("l{}", fresh_label_id()));
+    let rcxc = inquir::RCXCProc {
+        s: world.clone(),
+        p: ParticipantId::new(pos2 as u32),
+        label: label.clone(),
+        arg: c,
+        ent: ent1,
+        uid,
+    };
+    match ctrl {
+        Some(ctrl) => program[pos1].extend(Decomposer::new().decompose_conditional(rcxc, ctrl)),
+        None => program[pos1].push(Process::RCXC(rcxc)),
+    }
+    program[pos2].push(Process::RCXT(inquir::RCXTProc {
+        s: world,
+        p: ParticipantId::new(pos1 as u32),
+        label,
+        arg: t,
+        ent: ent2,
+        uid,
+    }));
+}
+
+/// Inserts CX gates from `c` on `pos1` to each of `targets` on `pos2`
+/// by a cat-entangler and a cat-disentangler, consuming a single Bell pair.
+fn insert_fanout(
+    program: &mut [Vec<Process>],
+    network: &mut Network,
+    (pos1, c): (usize, String),
+    (pos2, targets): (usize, Vec<String>)
+) {
+    let world = SessionId::new("world".to_string());
+    let (ent1, ent2) = insert_bell_pair(program, network, pos1, pos2);
+    let label = Label::new(format!("l{}", fresh_label_id()));
+    let mut decomposer = Decomposer::new();
+    let (control, copy) = decomposer.cat_entangle(
+        world.clone(), label.clone(), (c.clone(), ent1), (ParticipantId::new(pos2 as u32), ent2.clone())
+    );
+    program[pos1].extend(control);
+    program[pos2].extend(copy);
+    program[pos2].extend(targets.into_iter().map(|t| Process::Apply(inquir::ApplyProc {
+        gate: inquir::PrimitiveGate::CX,
+        args: vec![ent2.clone(), t],
+        ctrl: None,
+    })));
+    let (control, copy) = decomposer.cat_disentangle(world, label, (ParticipantId::new(pos1 as u32), c), ent2);
+    program[pos2].extend(copy);
+    program[pos1].extend(control);
+}
+
+/// Consecutive remote CX gates with the same control and targets on the same node.
+struct Fanout {
+    control: String,
+    pos1: usize,
+    pos2: usize,
+    targets: Vec<String>,
+}
+
+impl Fanout {
+    /// Inserts the gates, where a single gate is done by an RCX.
+    fn insert(mut self, program: &mut [Vec<Process>], network: &mut Network, uid: u32) {
+        if self.targets.len() == 1 {
+            insert_rcx(program, network, (self.pos1, self.control), (self.pos2, self.targets.remove(0)), uid, None);
+        } else {
+            insert_fanout(program, network, (self.pos1, self.control), (self.pos2, self.targets));
+        }
+    }
+}
+
+/// Inserts a teleportation of `x` from `from` to `to` through a path chosen by `network`.
+/// Returns the new name of the qubit.
+fn insert_move(
+    program: &mut [Vec<Process>],
+    network: &mut Network,
+    x: &str,
+    (from, to): (usize, usize),
+    uid: u32
+) -> String {
+    let world = SessionId::new("world".to_string());
+    let (ent1, ent2) = insert_bell_pair(program, network, from, to);
+    let label = Label::new(format!("l{}", fresh_label_id()));
+    program[from].push(Process::QSend(inquir::QSendProc {
+        s: world.clone(),
+        p: ParticipantId::new(to as u32),
+        label: label.clone(),
+        arg: x.to_string(),
+        ent: ent1,
+        uid,
+    }));
+    let new = format!("_x{}", fresh_var_id());
+    program[to].push(Process::QRecv(inquir::QRecvProc {
+        s: world,
+        label,
+        dst: new.clone(),
+        ent: ent2,
+        uid,
+    }));
+    new
+}
+
+/// Where the classical bits of the source circuit are, so that conditions can be evaluated on any participant.
+#[derive(Default)]
+struct ClassicalBits {
+    /// The participant which has measured each bit last.
+    pos: HashMap<String, usize>,
+    /// `(c, p) -> x` means that `p` has received the current value of `c` as `x`.
+    copies: HashMap<(String, usize), String>,
+}
+
+impl ClassicalBits {
+    fn measured(&mut self, c: &str, p: usize) {
+        self.pos.insert(c.to_string(), p);
+        self.copies.retain(|(c2, _), _| c2 != c);
+    }
+
+    /// Builds the condition `bits == value` evaluated on `p`.
+    /// The bits measured on other participants are sent to `p` unless they have been already.
+    fn condition(&mut self, program: &mut [Vec<Process>], bits: &[String], value: u64, p: usize) -> inquir::Expr {
+        if bits.len() < 64 && value >> bits.len() != 0 {
+            return inquir::Expr::BLit(false);
+        }
+        let world = SessionId::new("world".to_string());
+        bits.iter().enumerate().map(|(i, c)| {
+            let expected = value.checked_shr(i as u32).unwrap_or(0) & 1 == 1;
+            let x = match self.pos.get(c) {
+                None => return inquir::Expr::BLit(!expected),
+                Some(&q) if q == p => c.clone(),
+                Some(&q) => self.copies.entry((c.clone(), p)).or_insert_with(|| {
+                    let x = format!("_m{}", fresh_var_id());
+                    let label = Label::new(format!("l{}", fresh_label_id()));
+                    program[q].push(Process::Send(inquir::SendProc {
+                        s: world.clone(),
+                        dst: ParticipantId::new(p as u32),
+                        data: (label.clone(), inquir::Expr::Var(c.clone())),
+                    }));
+                    program[p].push(Process::Recv(inquir::RecvProc { s: world.clone(), data: (label, x.clone()) }));
+                    x
+                }).clone(),
+            };
+            if expected {
+                inquir::Expr::Var(x)
+            } else {
+                inquir::Expr::Not(Box::new(inquir::Expr::Var(x)))
+            }
+        }).reduce(|l, r| inquir::Expr::BinOp(inquir::BinOp::And, Box::new(l), Box::new(r)))
+          .unwrap_or(inquir::Expr::BLit(true))
+    }
 }
 
 fn route_telegates(
     exps: Vec<hir::Expr>,
     config: &Configuration,
-    mut router: Box<dyn RemoteOpRouter>
-) -> inquir::System {
+    mut router: Box<dyn RemoteOpRouter>,
+    swapping: Swapping
+) -> (inquir::System, Layout) {
     let mut tele_uid = 0;
     let mut fresh_tele_uid = || {
         let res = tele_uid;
         tele_uid += 1;
         res
     };
-    let mut rename = HashMap::new();
+    let mut rename: HashMap<String, String> = HashMap::new();
+    let mut cbits = ClassicalBits::default();
 
     let world_session = SessionId::new("world".to_string());
     let mut res = {
@@ -165,150 +400,128 @@ fn route_telegates(
         let ps = (0..config.node_size()).map(|i| ParticipantId::new(i as u32)).collect();
         vec![vec![Process::Open(OpenProc { id: world_session.clone(), ps })]; config.node_size()]
     };
-    let prevs = build_all_pair_shortest_path(config.connections());
-    for e in exps {
-        match e {
-            hir::Expr::Ret => {},
-            hir::Expr::Init(e) => {
-                let pos = router.current_pos(&e.dst);
-                rename.insert(e.dst.clone(), e.dst.clone());
-                res[pos as usize].push(inquir::Process::Init(inquir::InitProc { dst: e.dst }));
+    let mut network = Network::new(config, swapping);
+    // where each qubit of the source circuit is
+    let mut pos: HashMap<String, usize> = HashMap::new();
+    // the remote CX gates delayed until the run sharing the control ends
+    let mut fanout: Option<Fanout> = None;
+    for index in 0..exps.len() {
+        let plan = router.route(&Context { index, exps: &exps, config });
+        let remote_cx = match (&exps[index], &plan[..]) {
+            (hir::Expr::Apply(hir::ApplyExpr { gate: hir::PrimitiveGate::CX, args }), [RemoteOp::Execute])
+                if pos[&args[0]] != pos[&args[1]] => Some((&args[0], &args[1])),
+            _ => None,
+        };
+        match (fanout.as_mut(), remote_cx) {
+            (Some(f), Some((c, t))) if f.control == rename[c] && f.pos2 == pos[t] => {
+                f.targets.push(rename[t].clone());
+                continue;
             },
-            hir::Expr::Apply(hir::ApplyExpr{ gate, args }) => {
-                match gate {
-                    hir::PrimitiveGate::CX => {
-                        match router.next(&args[0], &args[1]) {
-                            RemoteOp::LocalCX => {
-                                let pos1 = router.current_pos(&args[0]) as usize;
-                                let pos2 = router.current_pos(&args[1]) as usize;
-                                assert!(pos1 == pos2);
-                                res[pos1].push(
-                                    inquir::Process::Apply(inquir::ApplyProc {
-                                        gate: inquir::PrimitiveGate::CX,
-                                        args: args.into_iter().map(|var| rename[&var].clone()).collect(),
-                                        ctrl: None
-                                    })
-                                );
-                            },
-                            RemoteOp::RCX => {
-                                let pos1 = router.current_pos(&args[0]) as usize;
-                                let pos2 = router.current_pos(&args[1]) as usize;
-                                assert!(pos1 != pos2);
-                                let path = construct_shortest_path(&prevs[pos1], pos1, pos2);
-                                let (ent1, ent2) = insert_entswap_chain(&mut res, path);
-                                let uid = fresh_tele_uid();
-                                let label = Label::new(format!("l{}", fresh_label_id()));
-                                res[pos1].push(inquir::Process::RCXC(inquir::RCXCProc {
-                                    s: world_session.clone(),
-                                    p: ParticipantId::new(pos2 as u32),
-                                    label: label.clone(),
-                                    arg: rename[&args[0]].clone(),
-                                    ent: ent1,
-                                    uid,
-                                }));
-                                res[pos2].push(inquir::Process::RCXT(inquir::RCXTProc {
-                                    s: world_session.clone(),
-                                    p: ParticipantId::new(pos1 as u32),
-                                    label,
-                                    arg: rename[&args[1]].clone(),
-                                    ent: ent2,
-                                    uid,
-                                }));
-                            },
-                            RemoteOp::RSwap => {
-                                let pos2 = router.current_pos(&args[0]) as usize;
-                                let pos1 = router.current_pos(&args[1]) as usize;
-                                assert!(pos1 != pos2);
-                                let positions = [pos1, pos2];
-                                for i in 0..3 { // RSWAP = RCX*3
-                                    let pos1 = positions[i % 2];
-                                    let pos2 = positions[(i + 1) % 2];
-                                    let path = construct_shortest_path(&prevs[pos1], pos1, pos2);
-                                    let (ent1, ent2) = insert_entswap_chain(&mut res, path);
-                                    let uid = fresh_tele_uid();
-                                    let label = Label::new(format!("l{}", fresh_label_id()));
-                                    res[pos1].push(inquir::Process::RCXC(inquir::RCXCProc {
-                                        s: world_session.clone(),
-                                        p: ParticipantId::new(pos2 as u32),
-                                        label: label.clone(),
-                                        arg: rename[&args[i % 2]].clone(),
-                                        ent: ent1,
-                                        uid,
-                                    }));
-                                    res[pos2].push(inquir::Process::RCXT(inquir::RCXTProc {
-                                        s: world_session.clone(),
-                                        p: ParticipantId::new(pos1 as u32),
-                                        label,
-                                        arg: rename[&args[(i + 1) % 2]].clone(),
-                                        ent: ent2,
-                                        uid,
-                                    }));
-                                }
-                                let tmp = rename[&args[0]].clone();
-                                *rename.get_mut(&args[0]).unwrap() = rename[&args[1]].clone();
-                                *rename.get_mut(&args[1]).unwrap() = tmp;
+            _ => {
+                if let Some(f) = fanout.take() {
+                    f.insert(&mut res, &mut network, fresh_tele_uid());
+                }
+                if let Some((c, t)) = remote_cx {
+                    fanout = Some(Fanout { control: rename[c].clone(), pos1: pos[c], pos2: pos[t], targets: vec![rename[t].clone()] });
+                    continue;
+                }
+            },
+        }
+        // A conditioned gate is evaluated on the participant where it is applied.
+        let (e, cond) = match &exps[index] {
+            hir::Expr::If(hir::IfExpr { bits, value, body }) => (&**body, Some((bits, *value))),
+            e => (e, None),
+        };
+        let ctrl_at = |cbits: &mut ClassicalBits, res: &mut [Vec<Process>], p: usize| {
+            cond.map(|(bits, value)| cbits.condition(res, bits, value, p))
+        };
+        for op in plan {
+            match op {
+                RemoteOp::Move(x, from, to) => {
+                    let (from, to) = (from as usize, to as usize);
+                    assert_eq!(pos[&x], from, "{} is not on node {}", x, from);
+                    let new = insert_move(&mut res, &mut network, &rename[&x], (from, to), fresh_tele_uid());
+                    rename.insert(x.clone(), new);
+                    pos.insert(x, to);
+                },
+                RemoteOp::RemoteCX(c, t) => {
+                    let (pos1, pos2) = (pos[&c], pos[&t]);
+                    assert!(pos1 != pos2);
+                    insert_rcx(&mut res, &mut network, (pos1, rename[&c].clone()), (pos2, rename[&t].clone()), fresh_tele_uid(), None);
+                },
+                RemoteOp::Exchange(a, b) => {
+                    let (pa, pb) = (pos[&a], pos[&b]);
+                    pos.insert(a.clone(), pb);
+                    pos.insert(b.clone(), pa);
+                    let (xa, xb) = (rename[&a].clone(), rename[&b].clone());
+                    rename.insert(a, xb);
+                    rename.insert(b, xa);
+                },
+                RemoteOp::Execute => match e {
+                    hir::Expr::Ret => {},
+                    hir::Expr::Init(e) => {
+                        let p = router.current_pos(&e.dst) as usize;
+                        pos.insert(e.dst.clone(), p);
+                        rename.insert(e.dst.clone(), e.dst.clone());
+                        res[p].push(inquir::Process::Init(inquir::InitProc { dst: e.dst.clone() }));
+                    },
+                    // a Toffoli is lowered here, so its CX gates are remote unless the router has gathered the operands
+                    hir::Expr::Apply(e) => for hir::ApplyExpr { gate, args } in e.lower() {
+                        let pos1 = pos[&args[0]];
+                        let ctrl = ctrl_at(&mut cbits, &mut res, pos1);
+                        match args.get(1).map(|arg| pos[arg]) {
+                            Some(pos2) if pos1 != pos2 => {
+                                assert!(gate == hir::PrimitiveGate::CX, "{:?} is not lowered", gate);
+                                let (c, t) = (rename[&args[0]].clone(), rename[&args[1]].clone());
+                                insert_rcx(&mut res, &mut network, (pos1, c), (pos2, t), fresh_tele_uid(), ctrl);
                             },
-                            RemoteOp::Move(id, from, to) => {
-                                let from = from as usize;
-                                let to = to as usize;
-                                let path = construct_shortest_path(&prevs[from], from, to);
-                                let (ent1, ent2) = insert_entswap_chain(&mut res, path);
-                                let tele_uid = fresh_tele_uid();
-                                let label = Label::new(format!("l{}", fresh_label_id()));
-                                res[from].push(inquir::Process::QSend(inquir::QSendProc {
-                                    s: world_session.clone(),
-                                    p: ParticipantId::new(to as u32),
-                                    label: label.clone(),
-                                    arg: rename[&id].clone(),
-                                    ent: ent1,
-                                    uid: tele_uid,
-                                }));
-                                let new = format!("_x{}", fresh_var_id());
-                                res[to].push(inquir::Process::QRecv(inquir::QRecvProc {
-                                    s: world_session.clone(),
-                                    label,
-                                    dst: new.clone(),
-                                    ent: ent2,
-                                    uid: tele_uid,
-                                }));
-                                *rename.get_mut(&id).unwrap() = new;
-                                let pos1 = router.current_pos(&args[0]);
-                                let pos2 = router.current_pos(&args[1]);
-                                assert!(pos1 == pos2);
-                                res[pos1 as usize].push(inquir::Process::Apply(inquir::ApplyProc {
-                                    gate: inquir::PrimitiveGate::CX,
-                                    args: args.into_iter().map(|var| rename[&var].clone()).collect(),
-                                    ctrl: None
-                                }));
-                            }
+                            _ => res[pos1].push(inquir::Process::Apply(inquir::ApplyProc {
+                                gate: gate.into(),
+                                args: args.iter().map(|var| rename[var].clone()).collect(),
+                                ctrl
+                            })),
                         }
                     },
-                    gate => {
-                        let pos = router.current_pos(&args[0]) as usize;
-                        res[pos].push(inquir::Process::Apply(inquir::ApplyProc {
-                            gate: gate.into(),
-                            args: args.into_iter().map(|var| rename[&var].clone()).collect(),
-                            ctrl: None
+                    hir::Expr::Measure(e) => {
+                        assert!(e.args.len() == 1); // TODO
+                        let p = pos[&e.args[0]];
+                        cbits.measured(&e.dst, p);
+                        res[p].push(inquir::Process::Measure(inquir::MeasureProc {
+                            dst: e.dst.clone(),
+                            args: e.args.iter().map(|var| rename[var].clone()).collect(),
                         }));
                     },
-                }
-            },
-            hir::Expr::Measure(e) => {
-                assert!(e.args.len() == 1); // TODO
-                let pos = router.current_pos(&e.args[0]);
-                res[pos as usize].push(inquir::Process::Measure(inquir::MeasureProc {
-                    dst: e.dst,
-                    args: e.args.into_iter().map(|var| rename[&var].clone()).collect(),
-                }));
-            },
-            hir::Expr::Barrier(_) => {
-                // TODO: Currently, this compiler ignore all barriers.
-            },
+                    hir::Expr::Reset(e) => {
+                        // measure and flip if the outcome is 1
+                        let p = pos[&e.arg];
+                        let x = format!("_m{}", fresh_var_id());
+                        res[p].push(inquir::Process::Measure(inquir::MeasureProc { dst: x.clone(), args: vec![rename[&e.arg].clone()] }));
+                        res[p].push(inquir::Process::Apply(inquir::ApplyProc {
+                            gate: inquir::PrimitiveGate::X,
+                            args: vec![rename[&e.arg].clone()],
+                            ctrl: Some(inquir::Expr::Var(x)),
+                        }));
+                    },
+                    hir::Expr::If(_) => unreachable!("nested conditions are not allowed"),
+                    hir::Expr::Barrier(_) => {
+                        // TODO: Currently, this compiler ignore all barriers.
+                    },
+                },
+            }
         }
     }
 
-    System::Composition(
+    if let Some(f) = fanout {
+        f.insert(&mut res, &mut network, fresh_tele_uid());
+    }
+
+    let layout = rename.into_iter().map(|(q, x)| {
+        let p = ParticipantId::new(pos[&q] as u32);
+        (q, (p, x))
+    }).collect();
+    let s = System::Composition(
         res.into_iter().enumerate()
            .map(|(i, procs)| System::Located(LocProc { p: ParticipantId::new(i as u32), procs })).collect()
-    )
+    );
+    (s, layout)
 }
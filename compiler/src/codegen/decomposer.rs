@@ -1,16 +1,22 @@
 use inquir::{
-    Label,
+    Label, SessionId, ParticipantId,
     Process,
     InitProc, FreeProc, ApplyProc, MeasureProc, RCXCProc, RCXTProc, QSendProc, QRecvProc, SendProc, RecvProc,
     System, LocProc,
     PrimitiveGate,
-    Expr,
+    Expr, BinOp,
 };
 use crate::utils::fresh_ids::fresh_var_id;
 
 pub struct Decomposer {
 }
 
+impl Default for Decomposer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Decomposer {
     pub fn new() -> Self {
         Self {
@@ -20,7 +26,7 @@ impl Decomposer {
     pub fn decompose(&mut self, s: System) -> System {
         match s {
             System::Located(LocProc { p, procs }) => {
-                let procs: Vec<_> = procs.into_iter().map(|proc| self.decompose_proc(proc)).flatten().collect();
+                let procs: Vec<_> = procs.into_iter().flat_map(|proc| self.decompose_proc(proc)).collect();
                 System::Located(LocProc { p, procs })
             },
             System::Composition(ss) => {
@@ -29,6 +35,73 @@ impl Decomposer {
         }
     }
 
+    /// Decomposes `rcxc` whose effect is conditioned on `ctrl`.
+    /// Only the local gates of the control side need to be conditioned:
+    /// if the control qubit is not entangled with `ent`, the target side has no effect as a whole.
+    pub fn decompose_conditional(&mut self, proc: RCXCProc, ctrl: Expr) -> Vec<Process> {
+        self.decompose_proc(Process::RCXC(proc)).into_iter().map(|proc| match proc {
+            Process::Apply(ApplyProc { gate, args, ctrl: c }) => {
+                let ctrl = match c {
+                    Some(c) => Expr::BinOp(BinOp::And, Box::new(c), Box::new(ctrl.clone())),
+                    None => ctrl.clone(),
+                };
+                Process::Apply(ApplyProc { gate, args, ctrl: Some(ctrl) })
+            },
+            proc => proc,
+        }).collect()
+    }
+
+    /// The cat-entangler sharing `arg` with `ent2` on `p2` through the Bell pair of `ent1` and `ent2`.
+    /// Afterwards, `ent2` can be used on `p2` as the control of CX gates in place of `arg`.
+    /// Returns the processes of the control side and `p2`.
+    pub fn cat_entangle(
+        &mut self,
+        s: SessionId,
+        label: Label,
+        (arg, ent1): (String, String),
+        (p2, ent2): (ParticipantId, String)
+    ) -> (Vec<Process>, Vec<Process>) {
+        let meas_var = self.fresh_var_id();
+        let control = vec![
+            Process::Apply(ApplyProc { gate: PrimitiveGate::CX, args: vec![arg, ent1.clone()], ctrl: None }),
+            Process::Measure(MeasureProc { dst: meas_var.clone(), args: vec![ent1.clone()] }),
+            Process::Free(FreeProc { arg: ent1 }),
+            Process::Send(SendProc { s: s.clone(), dst: p2, data: (label.clone(), Expr::Var(meas_var)) }),
+        ];
+        let recv_var = self.fresh_var_id();
+        let copy = vec![
+            Process::Recv(RecvProc { s, data: (label, recv_var.clone()) }),
+            Process::Apply(ApplyProc { gate: PrimitiveGate::X, args: vec![ent2], ctrl: Some(Expr::Var(recv_var)) }),
+        ];
+        (control, copy)
+    }
+
+    /// The cat-disentangler ending the cat-entangler of `label`,
+    /// which measures `ent2` out and corrects `arg` on `p1`.
+    /// Returns the processes of `p1` and the side of `ent2`.
+    pub fn cat_disentangle(
+        &mut self,
+        s: SessionId,
+        label: Label,
+        (p1, arg): (ParticipantId, String),
+        ent2: String
+    ) -> (Vec<Process>, Vec<Process>) {
+        let label2 = Label::new(label.to_string() + "_2");
+        let meas_var = self.fresh_var_id();
+        let copy = vec![
+            Process::Apply(ApplyProc { gate: PrimitiveGate::H, args: vec![ent2.clone()], ctrl: None }),
+            Process::Measure(MeasureProc { dst: meas_var.clone(), args: vec![ent2.clone()] }),
+            Process::Free(FreeProc { arg: ent2 }),
+            Process::Send(SendProc { s: s.clone(), dst: p1, data: (label2.clone(), Expr::Var(meas_var)) }),
+        ];
+        let recv_var = self.fresh_var_id();
+        let control = vec![
+            Process::Recv(RecvProc { s, data: (label2, recv_var.clone()) }),
+            Process::Apply(ApplyProc { gate: PrimitiveGate::Z, args: vec![arg], ctrl: Some(Expr::Var(recv_var)) }),
+        ];
+        (control, copy)
+    }
+
     fn decompose_proc(&mut self, e: Process) -> Vec<Process> {
         match e {
             Process::RCXC(RCXCProc { s, p, label, arg, ent, uid: _ }) => {
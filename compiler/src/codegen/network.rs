@@ -0,0 +1,164 @@
+use crate::arch::Configuration;
+use crate::codegen::routing::placement::hop_distances;
+
+/// How the Bell pairs along a path are swapped into one between the ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ArgEnum)]
+pub enum Swapping {
+    /// Swap hop by hop from the first node, where the corrections take rounds linear in the length.
+    #[default]
+    Chain,
+    /// Swap the halves of the path in parallel recursively, where the corrections take logarithmic rounds.
+    Tree,
+}
+
+/// The number of extra hops allowed for a path in addition to the shortest one.
+/// With one extra hop, the chosen paths are always simple.
+const SLACK: u64 = 1;
+/// The load of every link decreases by this factor per remote operation,
+/// so that the load reflects the operations which may run at the same time.
+const LOAD_DECAY: f64 = 0.9;
+
+/// Chooses the paths of Bell pairs, spreading remote operations over the links
+/// by their generation times, capacities and the recent load.
+pub struct Network {
+    /// `adj[u]`: the neighbors of `u` and the indices of the links.
+    adj: Vec<Vec<(usize, usize)>>,
+    /// The number of Bell pairs which each link can hold at once.
+    capacity: Vec<u32>,
+    /// The expected time to generate a Bell pair over each link relative to the average of the links.
+    time: Vec<f64>,
+    /// The recent number of Bell pairs over each link.
+    load: Vec<f64>,
+    dist: Vec<Vec<u64>>,
+    swapping: Swapping,
+    /// The number of purification rounds of each node.
+    rounds: Vec<u32>,
+}
+
+impl Network {
+    pub fn new(config: &Configuration, swapping: Swapping) -> Self {
+        let g = config.connections();
+        let mut adj = vec![Vec::new(); config.node_size()];
+        let rounds: Vec<_> = (0..config.node_size()).map(|i| config.node_info_ref(i).purification_rounds()).collect();
+        let mut capacity = Vec::new();
+        let mut time = Vec::new();
+        for (i, e) in g.edges().iter().enumerate() {
+            let (u, v) = (e.source(), e.target());
+            if u != v {
+                adj[u].push((v, i));
+                adj[v].push((u, i));
+            }
+            capacity.push(config.link_capacity(u, v));
+            time.push(config.expected_gen_ent_time(u, v) as f64);
+        }
+        let average = time.iter().sum::<f64>() / time.len().max(1) as f64;
+        time.iter_mut().for_each(|t| *t = if average > 0.0 { *t / average } else { 1.0 });
+        Self { adj, load: vec![0.0; capacity.len()], capacity, time, dist: hop_distances(config), swapping, rounds }
+    }
+
+    /// The number of purification rounds of the Bell pairs between adjacent nodes `u` and `v`.
+    pub fn purification_rounds(&self, u: usize, v: usize) -> u32 {
+        self.rounds[u].max(self.rounds[v])
+    }
+
+    pub fn swapping(&self) -> Swapping {
+        self.swapping
+    }
+
+    /// The cost of a Bell pair over link `e`, which is its generation time plus the expected wait for the link.
+    fn cost(&self, e: usize) -> f64 {
+        self.time[e] * (1.0 + self.load[e] / self.capacity[e].max(1) as f64)
+    }
+
+    /// Chooses the path from `from` to `to` with the lowest cost among the paths
+    /// at most `SLACK` hops longer than the shortest, and records its load.
+    pub fn path(&mut self, from: usize, to: usize) -> Vec<usize> {
+        let n = self.adj.len();
+        let limit = (self.dist[from][to] + SLACK) as usize;
+        // best[k][v]: the lowest cost of a path to `v` with `k` hops and the previous node and link
+        let mut best = vec![vec![None; n]; limit + 1];
+        best[0][from] = Some((0.0, usize::MAX, usize::MAX));
+        for k in 0..limit {
+            for v in 0..n {
+                let Some((c, _, _)) = best[k][v] else { continue };
+                for &(u, e) in &self.adj[v] {
+                    if (k + 1) as u64 + self.dist[u][to] > limit as u64 {
+                        continue;
+                    }
+                    let c = c + self.cost(e);
+                    if best[k + 1][u].is_none_or(|(c2, _, _)| c < c2) {
+                        best[k + 1][u] = Some((c, v, e));
+                    }
+                }
+            }
+        }
+        // prefers fewer hops among the paths of the same cost
+        let mut k = (0..=limit).filter(|&k| best[k][to].is_some())
+            .min_by(|&k1, &k2| best[k1][to].unwrap().0.total_cmp(&best[k2][to].unwrap().0))
+            .unwrap();
+
+        self.load.iter_mut().for_each(|l| *l *= LOAD_DECAY);
+        let mut path = vec![to];
+        let mut v = to;
+        while k > 0 {
+            let (_, u, e) = best[k][v].unwrap();
+            self.load[e] += 1.0;
+            path.push(u);
+            v = u;
+            k -= 1;
+        }
+        path.reverse();
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{LinkInfo, testing::ConfigBuilder};
+
+    fn network(edges: &[(u32, u32, u32)]) -> Network {
+        let n = edges.iter().map(|&(u, v, _)| u.max(v)).max().unwrap() + 1;
+        Network::new(&ConfigBuilder::new(&vec![2; n as usize]).links(edges).build(), Swapping::Chain)
+    }
+
+    #[test]
+    fn alternates_equal_paths() {
+        // a ring of four nodes
+        let mut network = network(&[(0, 1, 1), (1, 2, 1), (2, 3, 1), (3, 0, 1)]);
+        let paths: Vec<_> = (0..4).map(|_| network.path(0, 2)).collect();
+        assert_eq!(paths[0], [0, 1, 2]);
+        assert_eq!(paths[1], [0, 3, 2]);
+        assert_eq!(paths[2], [0, 1, 2]);
+        assert_eq!(paths[3], [0, 3, 2]);
+        assert_eq!(network.path(0, 1), [0, 1]);
+    }
+
+    #[test]
+    fn follows_capacity() {
+        let mut network = network(&[(0, 1, 4), (1, 2, 4), (2, 3, 1), (3, 0, 1)]);
+        let via_1 = (0..10).filter(|_| network.path(0, 2)[1] == 1).count();
+        assert!(via_1 >= 7, "{} of 10 paths use the wide links", via_1);
+    }
+
+    #[test]
+    fn avoids_slow_links() {
+        // 0 - 1 - 3 is long and 0 - 2 - 3 is short
+        let config = ConfigBuilder::new(&[2; 4])
+            .link_with(0, 1, LinkInfo::new(1).with_length(20.0))
+            .links(&[(1, 3, 1), (0, 2, 1), (2, 3, 1)])
+            .build();
+        let mut network = Network::new(&config, Swapping::Chain);
+        assert_eq!(network.path(0, 3), [0, 2, 3]);
+        assert_eq!(network.path(0, 3), [0, 2, 3]);
+    }
+
+    #[test]
+    fn detours_by_a_hop() {
+        // 0 - 1 is a busy link of capacity 1, and 0 - 2 - 1 is a detour
+        let mut network = network(&[(0, 1, 1), (0, 2, 2), (2, 1, 2)]);
+        assert_eq!(network.path(0, 1), [0, 1]);
+        assert_eq!(network.path(0, 1), [0, 1]);
+        assert_eq!(network.path(0, 1), [0, 2, 1]);
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use crate::hir;
+use crate::arch::Configuration;
+use crate::codegen::routing::{RemoteOpRouter, RemoteOp, Context, interactions, gatherings};
+use crate::codegen::routing::placement::{self, Placement};
+
+/// The number of upcoming CX gates taken into account.
+const WINDOW: usize = 32;
+/// The weight of a gate in the window decreases by this factor per gate.
+const DECAY: f64 = 0.9;
+
+/// Chooses an RCX, a move of either operand or an RSwap for each remote CX
+/// by estimated latencies of the operation and the upcoming gates.
+pub struct Hybrid {
+    current_pos: BTreeMap<String, u32>,
+    free_qubits: Vec<u32>,
+    cost: Costs,
+}
+
+/// Latencies of the operations on each node and between nodes.
+struct Costs {
+    single: Vec<f64>,
+    local_cx: Vec<f64>,
+    measure: Vec<f64>,
+    classical: Vec<f64>,
+    /// `ent[a][b]`: the expected time of a Bell pair between `a` and `b`, which are generated over the links
+    /// of the fastest of the shortest paths and swapped at the nodes in between.
+    ent: Vec<Vec<f64>>,
+    /// `message[a][b]`: the propagation delay of a classical message from `a` to `b`.
+    message: Vec<Vec<f64>>,
+}
+
+impl Costs {
+    fn new(config: &Configuration) -> Self {
+        let n = config.node_size();
+        let collect = |f: fn(&crate::arch::NodeInfo) -> u64| {
+            (0..n).map(|i| f(config.node_info_ref(i)) as f64).collect::<Vec<_>>()
+        };
+        let (single, local_cx, measure, classical) = (
+            collect(|n| n.single_gate_cost()),
+            collect(|n| n.local_cx_cost()),
+            collect(|n| n.measure_cost()),
+            collect(|n| n.classical_comm_cost()),
+        );
+        let message: Vec<Vec<f64>> = (0..n).map(|a| config.message_latencies(a).into_iter().map(|t| t as f64).collect()).collect();
+        let mut adj = vec![Vec::new(); n];
+        for e in config.connections().edges() {
+            adj[e.source()].push(e.target());
+            adj[e.target()].push(e.source());
+        }
+        let dist = placement::hop_distances(config);
+        let ent = (0..n).map(|a| {
+            let mut order: Vec<_> = (0..n).collect();
+            order.sort_by_key(|&v| dist[a][v]);
+            let mut ent = vec![f64::INFINITY; n];
+            ent[a] = 0.0;
+            for v in order.into_iter().skip(1) {
+                for &u in adj[v].iter().filter(|&&u| dist[a][u] + 1 == dist[a][v]) {
+                    let swap = if u == a { 0.0 } else { local_cx[u] + single[u] + measure[u] + classical[u] + message[u][v] };
+                    ent[v] = ent[v].min(ent[u] + swap + config.expected_gen_ent_time(u, v) as f64);
+                }
+            }
+            ent
+        }).collect();
+        Self { single, local_cx, measure, classical, ent, message }
+    }
+
+    fn rcx(&self, a: usize, b: usize) -> f64 {
+        self.ent[a][b]
+            + self.local_cx[a] + self.measure[a] + self.classical[a] + self.message[a][b] + self.single[a]
+            + self.single[b] + self.local_cx[b] + self.single[b] + self.measure[b] + self.classical[b] + self.message[b][a]
+    }
+
+    fn teleport(&self, from: usize, to: usize) -> f64 {
+        self.ent[from][to]
+            + self.local_cx[from] + self.single[from] + 2.0 * self.measure[from] + self.classical[from] + self.message[from][to]
+            + 2.0 * self.single[to]
+    }
+}
+
+impl Hybrid {
+    pub fn new(exps: &[hir::Expr], config: &Configuration) -> Self {
+        Self::from_initial_map(placement::initial_map(exps, config, Placement::default()), config)
+    }
+
+    pub fn from_initial_map(current_pos: BTreeMap<String, u32>, config: &Configuration) -> Self {
+        Self {
+            free_qubits: placement::free_qubits(&current_pos, config),
+            current_pos,
+            cost: Costs::new(config),
+        }
+    }
+
+    /// The estimated latency of the upcoming gates when `moved` qubits are at the given nodes.
+    fn lookahead(&self, upcoming: &[hir::Expr], moved: &[(&str, usize)]) -> f64 {
+        let pos = |q: &str| moved.iter().find(|(x, _)| *x == q).map_or(self.current_pos[q] as usize, |&(_, p)| p);
+        let mut weight = 1.0;
+        let mut total = 0.0;
+        for (a, b) in upcoming.iter().flat_map(interactions).take(WINDOW) {
+            let (pa, pb) = (pos(a), pos(b));
+            if pa != pb {
+                total += weight * self.cost.rcx(pa, pb);
+            }
+            weight *= DECAY;
+        }
+        total
+    }
+
+    /// Either lowers the Toffoli of `ctx` into local and remote CX gates, or gathers its operands on one node.
+    fn route_ccx(&mut self, ctx: &Context, operands: [&str; 3]) -> Vec<RemoteOp> {
+        let upcoming = ctx.upcoming();
+        let cxs = interactions(ctx.expr());
+        let lowered: f64 = cxs.iter().map(|&(a, b)| {
+            let (pa, pb) = (self.current_pos[a] as usize, self.current_pos[b] as usize);
+            if pa == pb { self.cost.local_cx[pa] } else { self.cost.rcx(pa, pb) }
+        }).sum();
+        let mut best = (lowered + self.lookahead(upcoming, &[]), Vec::new());
+        for moves in gatherings(&operands, &self.current_pos, &self.free_qubits) {
+            let Some(&(_, _, to)) = moves.first() else {
+                return vec![RemoteOp::Execute];
+            };
+            let to = to as usize;
+            let moved: Vec<_> = moves.iter().map(|(x, _, _)| (x.as_str(), to)).collect();
+            let cost = moves.iter().map(|&(_, from, _)| self.cost.teleport(from as usize, to)).sum::<f64>()
+                + cxs.len() as f64 * self.cost.local_cx[to]
+                + self.lookahead(upcoming, &moved);
+            if cost < best.0 {
+                best = (cost, moves);
+            }
+        }
+        let mut plan = Vec::new();
+        for (x, from, to) in best.1 {
+            self.relocate(&x, to);
+            plan.push(RemoteOp::Move(x, from, to));
+        }
+        plan.push(RemoteOp::Execute);
+        plan
+    }
+
+    fn relocate(&mut self, id: &str, to: u32) {
+        let from = std::mem::replace(self.current_pos.get_mut(id).unwrap(), to);
+        self.free_qubits[from as usize] += 1;
+        self.free_qubits[to as usize] -= 1;
+    }
+}
+
+impl RemoteOpRouter for Hybrid {
+    fn current_pos(&self, id: &str) -> u32 {
+        self.current_pos[id]
+    }
+
+    fn route(&mut self, ctx: &Context) -> Vec<RemoteOp> {
+        if let Some(operands) = ctx.ccx() {
+            return self.route_ccx(ctx, operands);
+        }
+        let Some((id1, id2)) = ctx.cx() else {
+            return vec![RemoteOp::Execute];
+        };
+        let pos1 = self.current_pos(id1) as usize;
+        let pos2 = self.current_pos(id2) as usize;
+        if pos1 == pos2 {
+            return vec![RemoteOp::Execute];
+        }
+        let upcoming = ctx.upcoming();
+        let mut best = (self.cost.rcx(pos1, pos2) + self.lookahead(upcoming, &[]), vec![RemoteOp::Execute]);
+        let mut consider = |cost: f64, plan: Vec<RemoteOp>| {
+            if cost < best.0 {
+                best = (cost, plan);
+            }
+        };
+        if self.free_qubits[pos1] > 0 {
+            let cost = self.cost.teleport(pos2, pos1) + self.cost.local_cx[pos1] + self.lookahead(upcoming, &[(id2, pos1)]);
+            consider(cost, vec![RemoteOp::Move(id2.to_string(), pos2 as u32, pos1 as u32), RemoteOp::Execute]);
+        }
+        if self.free_qubits[pos2] > 0 {
+            let cost = self.cost.teleport(pos1, pos2) + self.cost.local_cx[pos2] + self.lookahead(upcoming, &[(id1, pos2)]);
+            consider(cost, vec![RemoteOp::Move(id1.to_string(), pos1 as u32, pos2 as u32), RemoteOp::Execute]);
+        }
+        let plan = RemoteOp::cx_and_swap(id1, id2, ctx.conditioned());
+        let rcxs = plan.iter().filter(|op| matches!(op, RemoteOp::Execute | RemoteOp::RemoteCX(..))).count();
+        let cost = rcxs as f64 * self.cost.rcx(pos1, pos2) + self.lookahead(upcoming, &[(id1, pos2), (id2, pos1)]);
+        consider(cost, plan);
+
+        for op in &best.1 {
+            match op {
+                RemoteOp::Move(id, _, to) => self.relocate(id, *to),
+                RemoteOp::Exchange(a, b) => {
+                    *self.current_pos.get_mut(a).unwrap() = pos2 as u32;
+                    *self.current_pos.get_mut(b).unwrap() = pos1 as u32;
+                },
+                _ => {},
+            }
+        }
+        best.1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{LinkInfo, testing::ConfigBuilder};
+    use crate::parser::qasm2;
+    use crate::codegen::routing::router::cx_plans;
+
+    /// The plans for the CX gates of `body`, where `q` is on node 0 and `r` is on node 1.
+    fn route(body: &str, capacity: u32) -> (Vec<Vec<RemoteOp>>, Hybrid) {
+        let exps = qasm2::parse(&format!("OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[2];\nqreg r[2];\n{}", body)).unwrap();
+        let config = ConfigBuilder::new(&[capacity, 2]).link(0, 1, 1).build();
+        let map = [("q0", 0), ("q1", 0), ("r0", 1), ("r1", 1)].into_iter().map(|(q, p)| (q.to_string(), p)).collect();
+        let mut router = Hybrid::from_initial_map(map, &config);
+        (cx_plans(&mut router, &exps, &config), router)
+    }
+
+    #[test]
+    fn link_costs() {
+        let config = ConfigBuilder::new(&[2; 3])
+            .nodes(|n| n.with_single_gate_cost(1).with_local_cx_cost(2).with_measure_cost(3).with_classical_comm_cost(4))
+            .link_with(0, 1, LinkInfo::new(1).with_gen_ent_time(100).with_classical_latency(10))
+            .link_with(1, 2, LinkInfo::new(1).with_gen_ent_time(300).with_success_probability(0.5).with_classical_latency(20))
+            .build();
+        let cost = Costs::new(&config);
+        // the swap on node 1 sends its outcome toward the far end
+        assert_eq!((cost.ent[0][1], cost.ent[0][2], cost.ent[2][0]), (100.0, 100.0 + 30.0 + 600.0, 600.0 + 20.0 + 100.0));
+        assert_eq!(cost.message[0][2], 30.0);
+        assert_eq!(cost.rcx(0, 2), 730.0 + (2.0 + 3.0 + 4.0 + 30.0 + 1.0) + (1.0 + 2.0 + 1.0 + 3.0 + 4.0 + 30.0));
+    }
+
+    #[test]
+    fn single_interaction_uses_rcx() {
+        let (plans, _) = route("cx q[0], r[0];\ncx q[0], q[1];\ncx r[0], r[1];\n", 3);
+        assert_eq!(plans, vec![vec![RemoteOp::Execute]; 3]);
+    }
+
+    #[test]
+    fn gathers_or_lowers_toffolis() {
+        let plan = |capacity| {
+            let (_, mut router) = route("", capacity);
+            let exps = qasm2::parse("OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[2];\nqreg r[2];\nccx q[0], q[1], r[0];\n").unwrap();
+            let config = ConfigBuilder::new(&[3, 2]).link(0, 1, 1).build();
+            router.route(&Context { index: exps.len() - 1, exps: &exps, config: &config })
+        };
+        assert_eq!(plan(3), [RemoteOp::Move("r0".to_string(), 1, 0), RemoteOp::Execute]);
+        assert_eq!(plan(2), [RemoteOp::Execute]);
+    }
+
+    #[test]
+    fn repeated_interactions_move_qubits() {
+        // `r0` moves to the node of `q0`, which has a free qubit
+        let (plans, _) = route("cx q[0], r[0];\ncx r[0], q[0];\ncx q[0], r[0];\n", 3);
+        assert_eq!(plans[0], [RemoteOp::Move("r0".to_string(), 1, 0), RemoteOp::Execute]);
+        assert_eq!(plans[1], [RemoteOp::Execute]);
+
+        // both nodes are full
+        let (plans, router) = route("cx q[0], r[0];\ncx r[0], q[1];\ncx r[0], q[1];\ncx q[0], r[1];\ncx q[0], r[1];\n", 2);
+        assert_eq!(plans[0], RemoteOp::cx_and_swap("q0", "r0", false));
+        assert_eq!((router.current_pos("q0"), router.current_pos("r0")), (1, 0));
+    }
+}
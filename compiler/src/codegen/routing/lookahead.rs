@@ -0,0 +1,226 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::hir;
+use crate::arch::Configuration;
+use crate::codegen::routing::{RemoteOpRouter, RemoteOp, Context, Moves, interactions, gatherings};
+use crate::codegen::routing::placement::{self, Placement};
+
+/// The number of upcoming CX gates in the front layer and the extended set.
+const WINDOW: usize = 20;
+/// The weight of the extended set relative to the front layer.
+const EXTENDED_WEIGHT: f64 = 0.5;
+/// The increase of the decay of a qubit per move.
+const DECAY_DELTA: f64 = 0.1;
+/// The decays are reset every this number of remote gates.
+const DECAY_RESET: usize = 5;
+
+/// A teledata router choosing moves by a SABRE-like heuristic,
+/// that is, the average distances of the front layer and the extended set
+/// of the upcoming gates after the moves.
+/// Operands can be gathered on a third node, and a qubit can be evicted to make room.
+pub struct Lookahead {
+    current_pos: BTreeMap<String, u32>,
+    free_qubits: Vec<u32>,
+    dist: Vec<Vec<u64>>,
+    /// Penalties of qubits moved recently, which avoid moving the same qubits back and forth.
+    decay: HashMap<String, f64>,
+    remote_gates: usize,
+}
+
+impl Lookahead {
+    pub fn new(exps: &[hir::Expr], config: &Configuration) -> Self {
+        Self::from_initial_map(placement::initial_map(exps, config, Placement::default()), config)
+    }
+
+    pub fn from_initial_map(current_pos: BTreeMap<String, u32>, config: &Configuration) -> Self {
+        Self {
+            free_qubits: placement::free_qubits(&current_pos, config),
+            current_pos,
+            dist: placement::hop_distances(config),
+            decay: HashMap::new(),
+            remote_gates: 0,
+        }
+    }
+
+    /// Candidates of moves which bring `id1` and `id2` to the same node.
+    fn candidates(&self, id1: &str, id2: &str) -> Vec<Moves> {
+        let pos1 = self.current_pos[id1];
+        let pos2 = self.current_pos[id2];
+        let nodes = 0..self.free_qubits.len() as u32;
+        let mut res = Vec::new();
+        if self.free_qubits[pos1 as usize] > 0 {
+            res.push(vec![(id2.to_string(), pos2, pos1)]);
+        }
+        if self.free_qubits[pos2 as usize] > 0 {
+            res.push(vec![(id1.to_string(), pos1, pos2)]);
+        }
+        for m in nodes.clone().filter(|&m| m != pos1 && m != pos2 && self.free_qubits[m as usize] >= 2) {
+            res.push(vec![(id1.to_string(), pos1, m), (id2.to_string(), pos2, m)]);
+        }
+        // evict another qubit from a full node
+        for (id, pos, other, other_pos) in [(id1, pos1, id2, pos2), (id2, pos2, id1, pos1)] {
+            if self.free_qubits[pos as usize] > 0 {
+                continue;
+            }
+            for (x, _) in self.current_pos.iter().filter(|&(x, &p)| p == pos && x != id) {
+                for m in nodes.clone().filter(|&m| m != pos && self.free_qubits[m as usize] > 0) {
+                    res.push(vec![(x.clone(), pos, m), (other.to_string(), other_pos, pos)]);
+                }
+            }
+        }
+        res
+    }
+
+    /// The heuristic cost of `moves` followed by the upcoming gates.
+    fn score(&self, upcoming: &[hir::Expr], moves: &Moves) -> f64 {
+        let pos = |q: &str| moves.iter().rev().find(|(x, _, _)| x == q).map_or(self.current_pos[q], |&(_, _, to)| to) as usize;
+        let mut touched = HashSet::new();
+        let (mut front, mut extended) = ((0.0, 0), (0.0, 0));
+        for (a, b) in upcoming.iter().flat_map(interactions).take(WINDOW) {
+            let d = self.dist[pos(a)][pos(b)] as f64;
+            let layer = if touched.contains(a) || touched.contains(b) { &mut extended } else { &mut front };
+            layer.0 += d;
+            layer.1 += 1;
+            touched.insert(a);
+            touched.insert(b);
+        }
+        let average = |(sum, n): (f64, usize)| if n == 0 { 0.0 } else { sum / n as f64 };
+        let cost: u64 = moves.iter().map(|&(_, from, to)| self.dist[from as usize][to as usize]).sum();
+        let decay = moves.iter().map(|(x, _, _)| self.decay.get(x).copied().unwrap_or(1.0)).fold(1.0, f64::max);
+        decay * (cost as f64 + average(front) + EXTENDED_WEIGHT * average(extended))
+    }
+
+    /// Gathers the operands of the Toffoli of `ctx` on one node,
+    /// unless lowering it into local and remote CX gates is estimated to be cheaper.
+    /// A remote CX costs the distance of its operands as a move does.
+    fn route_ccx(&mut self, ctx: &Context, operands: [&str; 3]) -> Vec<RemoteOp> {
+        let candidates = gatherings(&operands, &self.current_pos, &self.free_qubits);
+        if candidates.iter().any(|moves| moves.is_empty()) {
+            return vec![RemoteOp::Execute];
+        }
+        self.count_remote_gate();
+        let lowered: u64 = interactions(ctx.expr()).into_iter()
+            .map(|(a, b)| self.dist[self.current_pos[a] as usize][self.current_pos[b] as usize])
+            .sum();
+        let mut best = (lowered as f64 + self.score(ctx.upcoming(), &Vec::new()), Vec::new());
+        for moves in candidates {
+            let score = self.score(ctx.upcoming(), &moves);
+            if score < best.0 {
+                best = (score, moves);
+            }
+        }
+        let mut plan = Vec::new();
+        for (x, from, to) in best.1 {
+            self.relocate(&x, to);
+            plan.push(RemoteOp::Move(x, from, to));
+        }
+        plan.push(RemoteOp::Execute);
+        plan
+    }
+
+    fn count_remote_gate(&mut self) {
+        self.remote_gates += 1;
+        if self.remote_gates.is_multiple_of(DECAY_RESET) {
+            self.decay.clear();
+        }
+    }
+
+    fn relocate(&mut self, id: &str, to: u32) {
+        let from = std::mem::replace(self.current_pos.get_mut(id).unwrap(), to);
+        self.free_qubits[from as usize] += 1;
+        self.free_qubits[to as usize] -= 1;
+        *self.decay.entry(id.to_string()).or_insert(1.0) += DECAY_DELTA;
+    }
+}
+
+impl RemoteOpRouter for Lookahead {
+    fn current_pos(&self, id: &str) -> u32 {
+        self.current_pos[id]
+    }
+
+    fn route(&mut self, ctx: &Context) -> Vec<RemoteOp> {
+        if let Some(operands) = ctx.ccx() {
+            return self.route_ccx(ctx, operands);
+        }
+        let Some((id1, id2)) = ctx.cx() else {
+            return vec![RemoteOp::Execute];
+        };
+        let pos1 = self.current_pos(id1);
+        let pos2 = self.current_pos(id2);
+        if pos1 == pos2 {
+            return vec![RemoteOp::Execute];
+        }
+        self.count_remote_gate();
+        let best = self.candidates(id1, id2).into_iter()
+            .map(|moves| (self.score(ctx.upcoming(), &moves), moves))
+            .min_by(|(s1, _), (s2, _)| s1.total_cmp(s2));
+        match best {
+            Some((_, moves)) => {
+                let mut plan = Vec::new();
+                for (x, from, to) in moves {
+                    self.relocate(&x, to);
+                    plan.push(RemoteOp::Move(x, from, to));
+                }
+                plan.push(RemoteOp::Execute);
+                plan
+            },
+            // every node is full
+            None => {
+                *self.current_pos.get_mut(id1).unwrap() = pos2;
+                *self.current_pos.get_mut(id2).unwrap() = pos1;
+                RemoteOp::cx_and_swap(id1, id2, ctx.conditioned())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::testing::ConfigBuilder;
+    use crate::parser::qasm2;
+    use crate::codegen::routing::{TeledataOnly, dry_run};
+    use crate::codegen::routing::router::cx_plans;
+
+    fn setup(body: &str, capacity: [u32; 3]) -> (Vec<hir::Expr>, Configuration, BTreeMap<String, u32>) {
+        let exps = qasm2::parse(&format!("OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg a[2];\nqreg b[2];\n{}", body)).unwrap();
+        let config = ConfigBuilder::new(&capacity).links(&[(0, 2, 1), (2, 1, 1)]).build();
+        let map = [("a0", 0), ("a1", 0), ("b0", 1), ("b1", 1)].into_iter().map(|(q, p)| (q.to_string(), p)).collect();
+        (exps, config, map)
+    }
+
+    #[test]
+    fn moves_the_operand_which_stays() {
+        let (exps, config, map) = setup("cx a[0], b[0];\ncx b[0], b[1];\ncx b[1], b[0];\n", [3, 3, 0]);
+        let plans = cx_plans(&mut Lookahead::from_initial_map(map.clone(), &config), &exps, &config);
+        assert_eq!(plans[0], [RemoteOp::Move("a0".to_string(), 0, 1), RemoteOp::Execute]);
+        assert_eq!(plans[1], [RemoteOp::Execute]);
+
+        let ours = dry_run(&mut Lookahead::from_initial_map(map.clone(), &config), &exps, &config);
+        let greedy = dry_run(&mut TeledataOnly::from_initial_map(map, &config), &exps, &config);
+        assert_eq!((ours.moves, greedy.moves), (1, 2));
+    }
+
+    #[test]
+    fn gathers_or_lowers_toffolis() {
+        let plan = |capacity| {
+            let (exps, config, map) = setup("ccx a[0], a[1], b[0];\n", capacity);
+            let mut router = Lookahead::from_initial_map(map, &config);
+            (0..exps.len()).map(|index| router.route(&Context { index, exps: &exps, config: &config })).last().unwrap()
+        };
+        assert_eq!(plan([3, 3, 0]), [RemoteOp::Move("b0".to_string(), 1, 0), RemoteOp::Execute]);
+        // no node has room, so four of the six CX gates are remote
+        assert_eq!(plan([2, 2, 0]), [RemoteOp::Execute]);
+    }
+
+    #[test]
+    fn migrates_to_a_third_node() {
+        let (exps, config, map) = setup("cx a[0], b[0];\ncx b[0], a[0];\n", [2, 2, 2]);
+        let plans = cx_plans(&mut Lookahead::from_initial_map(map, &config), &exps, &config);
+        // node 2 is between the full nodes, which is nearer than evicting a qubit and moving the other
+        assert_eq!(plans[0], [
+            RemoteOp::Move("a0".to_string(), 0, 2),
+            RemoteOp::Move("b0".to_string(), 1, 2),
+            RemoteOp::Execute,
+        ]);
+    }
+}
@@ -1,13 +1,21 @@
 pub mod router;
 pub mod telegate_only;
 pub mod teledata_only;
+pub mod placement;
+pub mod hybrid;
+pub mod lookahead;
 
-pub use router::{RemoteOp, RemoteOpRouter};
+pub use router::{RemoteOp, RemoteOpRouter, Context, RouteStats, Moves, cx_operands, interactions, gatherings, dry_run};
 pub use telegate_only::*;
 pub use teledata_only::*;
+pub use placement::Placement;
+pub use hybrid::Hybrid;
+pub use lookahead::Lookahead;
 
 #[derive(Debug, Clone, clap::ArgEnum)]
 pub enum Strategy {
     TelegateOnly,
     TeledataOnly,
+    Hybrid,
+    Lookahead,
 }
@@ -0,0 +1,276 @@
+use std::collections::{BTreeMap, HashMap};
+use graph::algo::dijkstra;
+use crate::hir;
+use crate::arch::Configuration;
+
+/// How qubits are initially placed on nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ArgEnum)]
+pub enum Placement {
+    /// Fill node 0, then node 1, and so on in the order of declarations.
+    Sequential,
+    /// Partition the interaction graph so that interacting qubits are close.
+    #[default]
+    Partition,
+}
+
+/// The maximum number of refinement passes.
+const MAX_PASSES: usize = 10;
+
+/// Where each qubit is placed at the beginning: `q -> node`.
+pub fn initial_map(exps: &[hir::Expr], config: &Configuration, placement: Placement) -> BTreeMap<String, u32> {
+    let qubits: Vec<&str> = exps.iter().filter_map(|e| match e {
+        hir::Expr::Init(e) => Some(e.dst.as_str()),
+        _ => None,
+    }).collect();
+    let capacity: Vec<usize> = (0..config.node_size()).map(|i| config.node_info_ref(i).num_of_data_qubits() as usize).collect();
+    assert!(
+        qubits.len() <= capacity.iter().sum(),
+        "{} qubits do not fit in the nodes with {} qubits", qubits.len(), capacity.iter().sum::<usize>()
+    );
+    let nodes = match placement {
+        Placement::Sequential => sequential(qubits.len(), &capacity),
+        Placement::Partition => {
+            let mut partition = Partition::new(&qubits, exps, config);
+            // the refinement is local, so it starts from both the greedy and the sequential placements
+            let starts = [partition.greedy(&capacity), sequential(qubits.len(), &capacity)];
+            starts.into_iter().map(|start| {
+                partition.assign(start);
+                partition.refine(&capacity);
+                (partition.total_cost(), partition.node.clone())
+            }).min_by_key(|(cost, _)| *cost).unwrap().1
+        },
+    };
+    let mut map = BTreeMap::new();
+    for (q, node) in qubits.into_iter().zip(nodes) {
+        assert!(map.insert(q.to_string(), node as u32).is_none(), "qubit {} is initialized twice", q);
+    }
+    map
+}
+
+/// The number of qubits left on each node after the placement.
+pub fn free_qubits(map: &BTreeMap<String, u32>, config: &Configuration) -> Vec<u32> {
+    let mut free: Vec<u32> = (0..config.node_size()).map(|i| config.node_info_ref(i).num_of_data_qubits()).collect();
+    map.values().for_each(|&node| free[node as usize] -= 1);
+    free
+}
+
+/// The numbers of hops between nodes.
+/// Unreachable nodes are regarded as farther than any path.
+pub fn hop_distances(config: &Configuration) -> Vec<Vec<u64>> {
+    let n = config.node_size();
+    let g = config.connections();
+    (0..n).map(|s| {
+        let d = if s < g.node_count() { dijkstra(g, |_| 1, s).0 } else { vec![] };
+        (0..n).map(|t| if s == t { 0 } else { d.get(t).copied().flatten().map_or(n as u64, u64::from) }).collect()
+    }).collect()
+}
+
+fn sequential(n: usize, capacity: &[usize]) -> Vec<usize> {
+    capacity.iter().enumerate().flat_map(|(node, &c)| std::iter::repeat_n(node, c)).take(n).collect()
+}
+
+/// Kernighan-Lin style partitioning minimizing the sum of `weight * distance`
+/// over the interacting pairs of qubits.
+struct Partition {
+    /// `adj[q]`: the qubits interacting with `q` and the number of interactions.
+    adj: Vec<Vec<(usize, u64)>>,
+    weight: HashMap<(usize, usize), u64>,
+    /// Hop counts between nodes.
+    dist: Vec<Vec<u64>>,
+    /// The node of each qubit.
+    node: Vec<usize>,
+    /// `cost[q][n]`: the cost of the interactions of `q` when it is on `n`.
+    cost: Vec<Vec<i64>>,
+}
+
+impl Partition {
+    fn new(qubits: &[&str], exps: &[hir::Expr], config: &Configuration) -> Self {
+        let index: HashMap<&str, usize> = qubits.iter().enumerate().map(|(i, &q)| (q, i)).collect();
+        let mut weight = HashMap::new();
+        for e in exps {
+            let e = match e {
+                hir::Expr::If(e) => &*e.body,
+                e => e,
+            };
+            if let hir::Expr::Apply(hir::ApplyExpr { args, .. }) = e {
+                for (i, a) in args.iter().enumerate() {
+                    for b in &args[i + 1..] {
+                        let (a, b) = (index[a.as_str()], index[b.as_str()]);
+                        *weight.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+        let mut adj = vec![Vec::new(); qubits.len()];
+        for (&(a, b), &w) in &weight {
+            adj[a].push((b, w));
+            adj[b].push((a, w));
+        }
+        adj.iter_mut().for_each(|nbrs| nbrs.sort());
+
+        Self { adj, weight, dist: hop_distances(config), node: vec![], cost: vec![] }
+    }
+
+    /// Places qubits greedily next to their placed neighbors,
+    /// visiting the interaction graph in the breadth-first order.
+    fn greedy(&self, capacity: &[usize]) -> Vec<usize> {
+        let n = self.adj.len();
+        let mut free = capacity.to_vec();
+        let mut node = vec![usize::MAX; n];
+        let mut visited = vec![false; n];
+        for s in 0..n {
+            if visited[s] {
+                continue;
+            }
+            visited[s] = true;
+            let mut que = std::collections::VecDeque::from([s]);
+            while let Some(q) = que.pop_front() {
+                let best = (0..free.len()).filter(|&m| free[m] > 0).min_by_key(|&m| {
+                    self.adj[q].iter().filter(|&&(r, _)| node[r] != usize::MAX).map(|&(r, w)| w * self.dist[m][node[r]]).sum::<u64>()
+                }).unwrap();
+                node[q] = best;
+                free[best] -= 1;
+                for &(r, _) in &self.adj[q] {
+                    if !visited[r] {
+                        visited[r] = true;
+                        que.push_back(r);
+                    }
+                }
+            }
+        }
+        node
+    }
+
+    fn assign(&mut self, node: Vec<usize>) {
+        self.node = node;
+        self.cost = (0..self.adj.len()).map(|q| {
+            (0..self.dist.len()).map(|m| self.adj[q].iter().map(|&(r, w)| (w * self.dist[m][self.node[r]]) as i64).sum()).collect()
+        }).collect();
+    }
+
+    /// The change of the total cost when `q` moves to `to`.
+    fn move_gain(&self, q: usize, to: usize) -> i64 {
+        self.cost[q][self.node[q]] - self.cost[q][to]
+    }
+
+    /// The change of the total cost when `q` and `r` are exchanged.
+    fn swap_gain(&self, q: usize, r: usize) -> i64 {
+        let (a, b) = (self.node[q], self.node[r]);
+        let w = self.weight.get(&(q.min(r), q.max(r))).copied().unwrap_or(0);
+        self.move_gain(q, b) + self.move_gain(r, a) - 2 * (w * self.dist[a][b]) as i64
+    }
+
+    fn relocate(&mut self, q: usize, to: usize) {
+        let from = self.node[q];
+        self.node[q] = to;
+        for &(r, w) in &self.adj[q] {
+            for m in 0..self.dist.len() {
+                self.cost[r][m] += (w * self.dist[m][to]) as i64 - (w * self.dist[m][from]) as i64;
+            }
+        }
+    }
+
+    /// Kernighan-Lin passes: each pass tentatively applies the best move or exchange
+    /// of unlocked qubits repeatedly and keeps the prefix with the largest total gain.
+    fn refine(&mut self, capacity: &[usize]) {
+        let n = self.adj.len();
+        let mut free = capacity.to_vec();
+        self.node.iter().for_each(|&m| free[m] -= 1);
+        for _ in 0..MAX_PASSES {
+            let mut locked = vec![false; n];
+            // (qubit, exchanged qubit, from, to) in the order of application
+            let mut history = Vec::new();
+            let (mut total, mut best, mut best_len) = (0, 0, 0);
+            loop {
+                let mut choice = None;
+                let mut consider = |gain: i64, op: (usize, Option<usize>, usize)| {
+                    if choice.is_none_or(|(g, _)| gain > g) {
+                        choice = Some((gain, op));
+                    }
+                };
+                for q in (0..n).filter(|&q| !locked[q]) {
+                    for m in (0..free.len()).filter(|&m| m != self.node[q] && free[m] > 0) {
+                        consider(self.move_gain(q, m), (q, None, m));
+                    }
+                    for r in (q + 1..n).filter(|&r| !locked[r] && self.node[r] != self.node[q]) {
+                        consider(self.swap_gain(q, r), (q, Some(r), self.node[r]));
+                    }
+                }
+                let Some((gain, (q, r, to))) = choice else { break };
+                let from = self.node[q];
+                self.relocate(q, to);
+                locked[q] = true;
+                if let Some(r) = r {
+                    self.relocate(r, from);
+                    locked[r] = true;
+                } else {
+                    free[from] += 1;
+                    free[to] -= 1;
+                }
+                history.push((q, r, from, to));
+                total += gain;
+                if total > best {
+                    (best, best_len) = (total, history.len());
+                }
+            }
+            for (q, r, from, to) in history.drain(best_len..).rev() {
+                self.relocate(q, from);
+                if let Some(r) = r {
+                    self.relocate(r, to);
+                } else {
+                    free[to] += 1;
+                    free[from] -= 1;
+                }
+            }
+            if best == 0 {
+                break;
+            }
+        }
+    }
+
+    fn total_cost(&self) -> i64 {
+        (0..self.adj.len()).map(|q| self.cost[q][self.node[q]]).sum::<i64>() / 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::testing::ConfigBuilder;
+    use crate::parser::qasm2;
+
+    fn circuit(body: &str) -> Vec<hir::Expr> {
+        qasm2::parse(&format!("OPENQASM 2.0;\ninclude \"qelib1.inc\";\n{}", body)).unwrap()
+    }
+
+    #[test]
+    fn interacting_qubits_share_a_node() {
+        let exps = circuit("qreg q[4];\ncx q[0], q[2];\ncx q[2], q[0];\ncx q[1], q[3];\ncx q[0], q[1];\n");
+        let config = ConfigBuilder::new(&[2, 2]).link(0, 1, 1).build();
+        let map = initial_map(&exps, &config, Placement::Sequential);
+        assert_eq!((map["q0"], map["q1"], map["q2"], map["q3"]), (0, 0, 1, 1));
+        let map = initial_map(&exps, &config, Placement::Partition);
+        assert_eq!(map["q0"], map["q2"]);
+        assert_eq!(map["q1"], map["q3"]);
+        assert_ne!(map["q0"], map["q1"]);
+        assert_eq!(free_qubits(&map, &config), vec![0, 0]);
+    }
+
+    #[test]
+    fn distance_and_capacity() {
+        // a chain of interactions on a line of three nodes
+        let exps = circuit("qreg q[6];\ncx q[0], q[3];\ncx q[3], q[5];\ncx q[5], q[1];\ncx q[1], q[4];\ncx q[4], q[2];\n");
+        let config = ConfigBuilder::new(&[2, 3, 2]).links(&[(0, 1, 1), (1, 2, 1)]).build();
+        let qubits: Vec<_> = (0..6).map(|i| format!("q{}", i)).collect();
+        let qubits: Vec<_> = qubits.iter().map(|q| q.as_str()).collect();
+        let capacity = [2, 3, 2];
+        let mut partition = Partition::new(&qubits, &exps, &config);
+        partition.assign(partition.greedy(&capacity));
+        partition.refine(&capacity);
+        // two cut edges between adjacent nodes is the optimum
+        assert_eq!(partition.total_cost(), 2);
+        for (m, &c) in capacity.iter().enumerate() {
+            assert!(partition.node.iter().filter(|&&x| x == m).count() <= c);
+        }
+    }
+}
@@ -1,11 +1,185 @@
+use std::collections::{BTreeMap, HashMap};
+use crate::hir;
+use crate::arch::Configuration;
+
+/// A step of the plan of a router for an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RemoteOp {
-    LocalCX,
-    RCX,
-    RSwap,
+    /// Executes the expression itself where its operands are.
+    /// A CX whose operands are on different nodes is done by a remote CX,
+    /// and a Toffoli whose operands are on different nodes is lowered into local and remote CX.
+    Execute,
+    /// A remote CX from the first qubit to the second, which is not a gate of the source circuit.
+    RemoteCX(String, String),
+    /// Teleports a qubit from a node to another.
     Move(String, u32, u32),
+    /// Exchanges two qubits after their states have been swapped.
+    Exchange(String, String),
+}
+
+impl RemoteOp {
+    /// CX(a, b) followed by a remote swap of `a` and `b`.
+    /// They are merged into CX(b, a) followed by CX(a, b) unless the CX is conditioned.
+    pub fn cx_and_swap(a: &str, b: &str, conditioned: bool) -> Vec<RemoteOp> {
+        let cx = |c: &str, t: &str| RemoteOp::RemoteCX(c.to_string(), t.to_string());
+        let mut ops = if conditioned {
+            vec![RemoteOp::Execute, cx(a, b), cx(b, a), cx(a, b)]
+        } else {
+            vec![cx(b, a), cx(a, b)]
+        };
+        ops.push(RemoteOp::Exchange(a.to_string(), b.to_string()));
+        ops
+    }
+}
+
+/// Teleportations of qubits from a node to another.
+pub type Moves = Vec<(String, u32, u32)>;
+
+/// The moves gathering `operands` on each of their nodes which has room for the others.
+pub fn gatherings(operands: &[&str], current_pos: &BTreeMap<String, u32>, free_qubits: &[u32]) -> Vec<Moves> {
+    let mut res = Vec::new();
+    let nodes: Vec<_> = operands.iter().map(|&q| current_pos[q]).collect();
+    for (i, &to) in nodes.iter().enumerate() {
+        if nodes[..i].contains(&to) {
+            continue;
+        }
+        let moves: Moves = operands.iter()
+            .filter(|&&q| current_pos[q] != to)
+            .map(|&q| (q.to_string(), current_pos[q], to))
+            .collect();
+        if moves.len() <= free_qubits[to as usize] as usize {
+            res.push(moves);
+        }
+    }
+    res
+}
+
+/// An expression of the circuit being routed.
+pub struct Context<'a> {
+    pub index: usize,
+    pub exps: &'a [hir::Expr],
+    pub config: &'a Configuration,
+}
+
+impl<'a> Context<'a> {
+    pub fn expr(&self) -> &'a hir::Expr {
+        &self.exps[self.index]
+    }
+
+    /// The expressions after the current one.
+    pub fn upcoming(&self) -> &'a [hir::Expr] {
+        &self.exps[self.index + 1..]
+    }
+
+    /// The operands of the current expression if it is a CX.
+    pub fn cx(&self) -> Option<(&'a str, &'a str)> {
+        cx_operands(self.expr())
+    }
+
+    /// The operands of the current expression if it is a Toffoli.
+    pub fn ccx(&self) -> Option<[&'a str; 3]> {
+        match unconditioned(self.expr()) {
+            hir::Expr::Apply(hir::ApplyExpr { gate: hir::PrimitiveGate::CCX, args }) => Some([&args[0], &args[1], &args[2]]),
+            _ => None,
+        }
+    }
+
+    pub fn conditioned(&self) -> bool {
+        matches!(self.expr(), hir::Expr::If(_))
+    }
+}
+
+/// The operands of `e` if it is a CX, which may be conditioned.
+pub fn cx_operands(e: &hir::Expr) -> Option<(&str, &str)> {
+    match unconditioned(e) {
+        hir::Expr::Apply(hir::ApplyExpr { gate: hir::PrimitiveGate::CX, args }) => Some((&args[0], &args[1])),
+        _ => None,
+    }
+}
+
+/// The operands of the CX gates which `e` is lowered into.
+pub fn interactions(e: &hir::Expr) -> Vec<(&str, &str)> {
+    match unconditioned(e) {
+        hir::Expr::Apply(app) => {
+            let arg = |x: &str| app.args.iter().find(|&y| y == x).unwrap().as_str();
+            app.lower().iter()
+                .filter(|g| g.gate == hir::PrimitiveGate::CX)
+                .map(|g| (arg(&g.args[0]), arg(&g.args[1])))
+                .collect()
+        },
+        _ => vec![],
+    }
+}
+
+fn unconditioned(e: &hir::Expr) -> &hir::Expr {
+    match e {
+        hir::Expr::If(e) => &e.body,
+        e => e,
+    }
 }
 
 pub trait RemoteOpRouter {
-    fn current_pos(&self, id: &String) -> u32;
-    fn next(&mut self, id1: &String, id2: &String) -> RemoteOp;
+    /// The node where `id` is at the moment.
+    fn current_pos(&self, id: &str) -> u32;
+
+    /// Plans how the current expression of `ctx` is executed,
+    /// updating the positions of the qubits by the plan.
+    fn route(&mut self, ctx: &Context) -> Vec<RemoteOp>;
+}
+
+/// The numbers of the remote operations chosen by a router.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RouteStats {
+    pub rcx: usize,
+    /// The number of teleported qubits.
+    pub moves: usize,
+    pub rswaps: usize,
+}
+
+impl RouteStats {
+    /// The number of qubits carried to other nodes, where an RSwap exchanges two qubits.
+    pub fn transfers(&self) -> usize {
+        self.moves + 2 * self.rswaps
+    }
+}
+
+/// Runs `router` on `exps` without generating code.
+pub fn dry_run(router: &mut dyn RemoteOpRouter, exps: &[hir::Expr], config: &Configuration) -> RouteStats {
+    let mut stats = RouteStats::default();
+    let mut pos = HashMap::new();
+    for index in 0..exps.len() {
+        let ctx = Context { index, exps, config };
+        if let hir::Expr::Init(e) = ctx.expr() {
+            pos.insert(e.dst.as_str(), router.current_pos(&e.dst));
+        }
+        for op in router.route(&ctx) {
+            match op {
+                RemoteOp::Execute => {
+                    stats.rcx += interactions(ctx.expr()).into_iter().filter(|(a, b)| pos[a] != pos[b]).count();
+                },
+                RemoteOp::RemoteCX(..) => stats.rcx += 1,
+                RemoteOp::Move(x, _, to) => {
+                    *pos.get_mut(x.as_str()).unwrap() = to;
+                    stats.moves += 1;
+                },
+                RemoteOp::Exchange(a, b) => {
+                    let (pa, pb) = (pos[a.as_str()], pos[b.as_str()]);
+                    *pos.get_mut(a.as_str()).unwrap() = pb;
+                    *pos.get_mut(b.as_str()).unwrap() = pa;
+                    stats.rswaps += 1;
+                },
+            }
+        }
+    }
+    stats
+}
+
+/// The plans of `router` for the CX gates of `exps`.
+#[cfg(test)]
+pub(crate) fn cx_plans(router: &mut dyn RemoteOpRouter, exps: &[hir::Expr], config: &Configuration) -> Vec<Vec<RemoteOp>> {
+    (0..exps.len()).filter_map(|index| {
+        let ctx = Context { index, exps, config };
+        let plan = router.route(&ctx);
+        ctx.cx().map(|_| plan)
+    }).collect()
 }
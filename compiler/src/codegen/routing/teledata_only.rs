@@ -1,7 +1,8 @@
 use std::collections::BTreeMap;
 use crate::hir;
 use crate::arch::Configuration;
-use crate::codegen::routing::{RemoteOpRouter, RemoteOp};
+use crate::codegen::routing::{RemoteOpRouter, RemoteOp, Context, gatherings};
+use crate::codegen::routing::placement::{self, Placement};
 
 pub struct TeledataOnly {
     current_pos: BTreeMap<String, u32>,
@@ -9,70 +10,59 @@ pub struct TeledataOnly {
 }
 
 impl TeledataOnly {
-    pub fn new(exps: &Vec<hir::Expr>, config: &Configuration) -> Self {
-        let (current_pos, free_qubits) = TeledataOnly::create_initial_map(&exps, &config);
+    pub fn new(exps: &[hir::Expr], config: &Configuration) -> Self {
+        Self::from_initial_map(placement::initial_map(exps, config, Placement::default()), config)
+    }
+
+    pub fn from_initial_map(current_pos: BTreeMap<String, u32>, config: &Configuration) -> Self {
+        let free_qubits = placement::free_qubits(&current_pos, config);
         Self {
             current_pos,
             free_qubits,
         }
     }
-
-    fn create_initial_map(exps: &Vec<hir::Expr>, config: &Configuration) -> (BTreeMap<String, u32>, Vec<u32>) {
-        let mut num_of_qubits: Vec<u32> = (0..config.node_size()).map(|i| config.node_info_ref(i).num_of_qubits()).collect();
-        let mut map = BTreeMap::new();
-        let mut target_node = 0;
-        let mut next_node = || {
-            while target_node < num_of_qubits.len() && num_of_qubits[target_node] == 0 {
-                target_node += 1;
-            }
-            if target_node >= num_of_qubits.len() {
-                panic!("target_node >= node_of_qubits.len()");
-            }
-            num_of_qubits[target_node as usize] -= 1;
-            target_node
-        };
-        for e in exps {
-            match e {
-                hir::Expr::Init(hir::InitExpr { dst }) => {
-                    assert!(!map.contains_key(dst));
-                    let location = next_node() as u32;
-                    map.insert(dst.clone(), location);
-                },
-                _ => {},
-            }
-        }
-        (map, num_of_qubits)
-    }
 }
 
 impl RemoteOpRouter for TeledataOnly {
-    fn current_pos(&self, id: &String) -> u32 {
+    fn current_pos(&self, id: &str) -> u32 {
         self.current_pos[id]
     }
 
-    fn next(&mut self, id1: &String, id2: &String) -> RemoteOp {
-        if !self.current_pos.contains_key(id1) || !self.current_pos.contains_key(id2) {
-            panic!("NaiveNodeAllocator::next");
+    fn route(&mut self, ctx: &Context) -> Vec<RemoteOp> {
+        if let Some(operands) = ctx.ccx() {
+            // gather the operands on the first of their nodes with room, or lower the Toffoli with remote CX gates
+            let mut plan = Vec::new();
+            for (x, from, to) in gatherings(&operands, &self.current_pos, &self.free_qubits).into_iter().next().unwrap_or_default() {
+                self.free_qubits[to as usize] -= 1;
+                self.free_qubits[from as usize] += 1;
+                *self.current_pos.get_mut(&x).unwrap() = to;
+                plan.push(RemoteOp::Move(x, from, to));
+            }
+            plan.push(RemoteOp::Execute);
+            return plan;
         }
-        let pos1 = *self.current_pos.get(id1).unwrap();
-        let pos2 = *self.current_pos.get(id2).unwrap();
+        let Some((id1, id2)) = ctx.cx() else {
+            return vec![RemoteOp::Execute];
+        };
+        let pos1 = self.current_pos[id1];
+        let pos2 = self.current_pos[id2];
         if pos1 == pos2 { // local operation
-            RemoteOp::LocalCX
+            vec![RemoteOp::Execute]
         } else if self.free_qubits[pos1 as usize] > 0 {
             // move to pos1
             self.free_qubits[pos1 as usize] -= 1;
             self.free_qubits[pos2 as usize] += 1;
             *self.current_pos.get_mut(id2).unwrap() = pos1;
-            RemoteOp::Move(id2.clone(), pos2, pos1)
+            vec![RemoteOp::Move(id2.to_string(), pos2, pos1), RemoteOp::Execute]
         } else if self.free_qubits[pos2 as usize] > 0 {
             self.free_qubits[pos2 as usize] -= 1;
             self.free_qubits[pos1 as usize] += 1;
             *self.current_pos.get_mut(id1).unwrap() = pos2;
-            RemoteOp::Move(id1.clone(), pos1, pos2)
-        } else { // Use swap (RCX * 3)
+            vec![RemoteOp::Move(id1.to_string(), pos1, pos2), RemoteOp::Execute]
+        } else { // Use swap
             *self.current_pos.get_mut(id1).unwrap() = pos2;
             *self.current_pos.get_mut(id2).unwrap() = pos1;
-            RemoteOp::RSwap
+            RemoteOp::cx_and_swap(id1, id2, ctx.conditioned())
         }
     }
 }
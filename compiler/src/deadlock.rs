@@ -0,0 +1,316 @@
+use crate::{
+    arch::Configuration,
+    dependency_graph::{Dependency, DependencyGraph, DependencyGraphBuilder},
+};
+use graph::{
+    graph::{NodeIndex, EdgeIndex},
+    algo::scc,
+};
+use inquir::{
+    ParticipantId,
+    Process, GenEntProc,
+    System, LocProc,
+};
+use std::fmt;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// An instruction which waits forever.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaitingInstr {
+    pub p: ParticipantId,
+    pub index: usize,
+    pub process: Process,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deadlock {
+    /// The instructions on a wait cycle. Each instruction waits for the previous one,
+    /// and the first one waits for the last one.
+    pub cycle: Vec<WaitingInstr>,
+    /// Links `(p, q)` such that `p` holds all the communication qubits for `q` in the cycle,
+    /// possibly for other partners sharing them.
+    pub exhausted_links: Vec<(ParticipantId, ParticipantId)>,
+}
+
+impl fmt::Display for Deadlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "deadlock:")?;
+        for instr in &self.cycle {
+            writeln!(f, "  participant {}, instruction {}: {}", instr.p, instr.index, instr.process)?;
+        }
+        for (p, q) in &self.exhausted_links {
+            writeln!(f, "  participant {} holds all the communication qubits for participant {}", p, q)?;
+        }
+        Ok(())
+    }
+}
+
+/// Detects deadlocks of a system statically.
+///
+/// Each participant issues its instructions in order, so a participant waiting on a `recv` or a `genEnt`
+/// blocks all of its following instructions. A deadlock is a cycle of such waits across participants.
+/// In addition, `genEnt[q]` on `p` waits until `p` releases a communication qubit for `q`
+/// when all of them are held, possibly for other partners in a shared pool, which is a resource-induced deadlock if they are released only later.
+pub fn detect(s: &System, config: &Configuration) -> Vec<Deadlock> {
+    let mut g = DependencyGraphBuilder::new().with_program_order().build(s.clone());
+    let mut nodes = HashMap::new();
+    for idx in 0..g.node_count() {
+        let (p, _) = g.node(idx).weight();
+        if let Some(index) = g.instruction_index(idx) {
+            nodes.entry((*p, index)).or_insert(idx);
+        }
+    }
+
+    let mut res = Vec::new();
+    let mut resource_edges = HashMap::new();
+    for LocProc { p, procs } in located_processes(s) {
+        for wait in resource_waits(*p, procs, config) {
+            match wait.release {
+                Some(release) => {
+                    let eidx = g.add_edge(nodes[&(*p, release)], nodes[&(*p, wait.acquire)], Dependency::new("__resource".to_string()));
+                    resource_edges.insert(eidx, (*p, wait.partner));
+                },
+                None => { // never released
+                    let instr = WaitingInstr { p: *p, index: wait.acquire, process: procs[wait.acquire].clone() };
+                    res.push(Deadlock { cycle: vec![instr], exhausted_links: vec![(*p, wait.partner)] });
+                },
+            }
+        }
+    }
+
+    for comp in scc(g.graph_ref()) {
+        if let Some(cycle) = find_cycle(&g, &comp) {
+            res.push(as_deadlock(&g, cycle, &resource_edges));
+        }
+    }
+
+    res
+}
+
+fn located_processes(s: &System) -> Vec<&LocProc> {
+    match s {
+        System::Located(proc) => vec![proc],
+        System::Composition(ss) => ss.iter().flat_map(located_processes).collect(),
+    }
+}
+
+/// `genEnt` at the instruction `acquire` waits for the instruction `release` to free a communication qubit.
+struct ResourceWait {
+    acquire: usize,
+    release: Option<usize>,
+    partner: ParticipantId,
+}
+
+fn resource_waits(
+    p: ParticipantId,
+    procs: &[Process],
+    config: &Configuration,
+) -> Vec<ResourceWait> {
+    let qubits = config.comm_qubits(p.to_usize());
+    // whether a communication qubit serves both partners
+    let share = |a: ParticipantId, b: ParticipantId| qubits.iter().any(|q| q.contains(&a.to_u32()) && q.contains(&b.to_u32()));
+    // (instruction index, partner, the index of the instruction which releases the qubit)
+    let mut acquisitions: Vec<(usize, ParticipantId, Option<usize>)> = Vec::new();
+    let mut holding: HashMap<String, usize> = HashMap::new();
+    for (i, proc) in procs.iter().enumerate() {
+        for x in proc.consumed_variables() {
+            if let Some(acq) = holding.remove(&x) {
+                acquisitions[acq].2 = Some(i);
+            }
+        }
+        gen_ents(proc).into_iter().for_each(|GenEntProc { x, p: partner, label: _ }| {
+            holding.insert(x.clone(), acquisitions.len());
+            acquisitions.push((i, *partner, None));
+        });
+    }
+
+    let mut blocked = HashSet::new();
+    let mut res = Vec::new();
+    for (acq, &(i, partner, _)) in acquisitions.iter().enumerate() {
+        let held: Vec<_> = acquisitions[..acq].iter().enumerate()
+            .filter(|(other, (j, _, release))| *j < i && release.is_none_or(|r| r >= i) && !blocked.contains(other))
+            .map(|(_, &(_, q, release))| (q, release))
+            .collect();
+        let partners: Vec<_> = held.iter().map(|(q, _)| q.to_u32()).chain([partner.to_u32()]).collect();
+        if !config.can_hold(p.to_usize(), &partners) {
+            blocked.insert(acq);
+            // the qubits which this `genEnt` can wait for
+            let release = held.into_iter().filter(|&(q, _)| share(q, partner)).filter_map(|(_, r)| r).min();
+            res.push(ResourceWait { acquire: i, release, partner });
+        }
+    }
+    res
+}
+
+fn gen_ents(proc: &Process) -> Vec<&GenEntProc> {
+    match proc {
+        Process::GenEnt(proc) => vec![proc],
+        Process::Parallel(procs) => procs.iter().flat_map(gen_ents).collect(),
+        _ => vec![],
+    }
+}
+
+/// Finds a shortest cycle through the first node of a strongly connected component.
+fn find_cycle(g: &DependencyGraph, comp: &[NodeIndex]) -> Option<Vec<EdgeIndex>> {
+    let s = comp[0];
+    let members: HashSet<_> = comp.iter().copied().collect();
+    let mut prev = HashMap::new();
+    let mut que = VecDeque::from([s]);
+    while let Some(v) = que.pop_front() {
+        for &eidx in g.outgoing_edges(v) {
+            let to = g.edge(eidx).target();
+            if to == s {
+                let mut cycle = vec![eidx];
+                let mut cur = v;
+                while cur != s {
+                    let e = prev[&cur];
+                    cycle.push(e);
+                    cur = g.edge(e).source();
+                }
+                cycle.reverse();
+                return Some(cycle);
+            }
+            if members.contains(&to) && !prev.contains_key(&to) {
+                prev.insert(to, eidx);
+                que.push_back(to);
+            }
+        }
+    }
+    None
+}
+
+fn as_deadlock(
+    g: &DependencyGraph,
+    cycle: Vec<EdgeIndex>,
+    resource_edges: &HashMap<EdgeIndex, (ParticipantId, ParticipantId)>
+) -> Deadlock {
+    let mut instrs: Vec<WaitingInstr> = Vec::new();
+    let mut exhausted_links = Vec::new();
+    for eidx in cycle {
+        let v = g.edge(eidx).target();
+        let (p, process) = g.node(v).weight();
+        let index = g.instruction_index(v).unwrap();
+        if !instrs.last().is_some_and(|last| last.p == *p && last.index == index) {
+            instrs.push(WaitingInstr { p: *p, index, process: process.clone() });
+        }
+        if let Some(&link) = resource_edges.get(&eidx) {
+            exhausted_links.push(link);
+        }
+    }
+    Deadlock { cycle: instrs, exhausted_links }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{CommQubits, testing::ConfigBuilder};
+    use inquir::parser::parse;
+
+    fn instrs(d: &Deadlock) -> Vec<(u32, usize)> {
+        let mut res: Vec<_> = d.cycle.iter().map(|instr| (instr.p.to_u32(), instr.index)).collect();
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn no_deadlock() {
+        let s = parse("0 {
+  world = open[0,1];
+  _cq0 = genEnt[1](l0);
+  _m0 = measure _cq0;
+  free _cq0;
+  send[1](world, l1:_m0);
+}
+1 {
+  world = open[0,1];
+  _cq1 = genEnt[0](l0);
+  recv(world, l1:_m1);
+  X[_m1] _cq1;
+}").unwrap();
+        let config = ConfigBuilder::new(&[1, 1]).link(0, 1, 1).build();
+        assert!(detect(&s, &config).is_empty());
+    }
+
+    #[test]
+    fn crossed_communications() {
+        let s = parse("0 {
+  world = open[0,1];
+  recv(world, l0:_m0);
+  send[1](world, l1:1);
+}
+1 {
+  world = open[0,1];
+  recv(world, l1:_m1);
+  send[0](world, l0:1);
+}").unwrap();
+        let config = ConfigBuilder::new(&[1, 1]).link(0, 1, 1).build();
+        let deadlocks = detect(&s, &config);
+        assert_eq!(deadlocks.len(), 1);
+        assert_eq!(instrs(&deadlocks[0]), vec![(0, 1), (0, 2), (1, 1), (1, 2)]);
+        assert!(deadlocks[0].exhausted_links.is_empty());
+    }
+
+    #[test]
+    fn crossed_entanglements() {
+        let s = parse("0 {
+  _cq0 = genEnt[1](l0);
+  _cq1 = genEnt[1](l1);
+}
+1 {
+  _cq2 = genEnt[0](l1);
+  _cq3 = genEnt[0](l0);
+}").unwrap();
+        let config = ConfigBuilder::new(&[1, 1]).link(0, 1, 2).build();
+        let deadlocks = detect(&s, &config);
+        assert_eq!(deadlocks.len(), 1);
+        assert_eq!(instrs(&deadlocks[0]), vec![(0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn exhausted_communication_qubits() {
+        let s = parse("0 {
+  _cq0 = genEnt[1](l0);
+  _cq1 = genEnt[1](l1);
+  free _cq0;
+  free _cq1;
+}
+1 {
+  _cq2 = genEnt[0](l0);
+  free _cq2;
+  _cq3 = genEnt[0](l1);
+  free _cq3;
+}").unwrap();
+        let config = ConfigBuilder::new(&[1, 1]).link(0, 1, 1).build();
+        let deadlocks = detect(&s, &config);
+        assert_eq!(deadlocks.len(), 1);
+        assert_eq!(instrs(&deadlocks[0]), vec![(0, 1), (0, 2)]);
+        assert_eq!(deadlocks[0].exhausted_links, vec![(ParticipantId::new(0), ParticipantId::new(1))]);
+
+        let config = ConfigBuilder::new(&[1, 1]).link(0, 1, 2).build();
+        assert!(detect(&s, &config).is_empty());
+    }
+
+    #[test]
+    fn exhausted_shared_qubits() {
+        // node 1 swaps the pairs with both neighbors
+        let s = parse("0 {
+  _cq0 = genEnt[1](l0);
+  free _cq0;
+}
+1 {
+  _cq1 = genEnt[0](l0);
+  _cq2 = genEnt[2](l1);
+  free _cq1;
+  free _cq2;
+}
+2 {
+  _cq3 = genEnt[1](l1);
+  free _cq3;
+}").unwrap();
+        let config = |cq| ConfigBuilder::new(&[1; 3]).node(1, |n| n.with_comm_qubits(CommQubits::Shared(cq))).links(&[(0, 1, 1), (1, 2, 1)]).build();
+        let deadlocks = detect(&s, &config(1));
+        assert_eq!(deadlocks.len(), 1);
+        assert_eq!(deadlocks[0].exhausted_links, vec![(ParticipantId::new(1), ParticipantId::new(2))]);
+        assert!(detect(&s, &config(2)).is_empty());
+    }
+}
@@ -41,15 +41,29 @@ impl Dependency {
 #[derive(Debug, Clone)]
 pub struct DependencyGraph {
     g: InnerGraph,
+    instr_index: HashMap<NodeIndex, usize>,
+}
+
+impl Default for DependencyGraph {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DependencyGraph {
     pub fn new() -> Self {
         Self {
             g: InnerGraph::new(),
+            instr_index: HashMap::new(),
         }
     }
 
+    /// The index of the instruction in its participant's process list, from which the node is built.
+    /// Nodes inserted after building the graph have no index.
+    pub fn instruction_index(&self, idx: NodeIndex) -> Option<usize> {
+        self.instr_index.get(&idx).copied()
+    }
+
     pub fn remove_node(&mut self, idx: NodeIndex) {
         let e = &mut self.g.node_weight_mut(idx).1;
         // TODO: Currently, this method supports to remove single qubit gates only.
@@ -101,8 +115,8 @@ impl DependencyGraph {
         // Note: Must get eidx3 before update_edge!
         // Note: eidx3 may not exist (if [v] is the endpoint)
         let eidx3_opt = self.find_depends_by_label(v, &label, false);
-        assert!(eidx3_opt != None
-                || self.g.outgoing_edges(v).len() == 0 // single
+        assert!(eidx3_opt.is_some()
+                || self.g.outgoing_edges(v).is_empty() // single
                 || self.g.incoming_edges(v).len() == 2 && self.g.outgoing_edges(v).len() == 1); // CX
         let dep_data = Dependency::new(label.clone());
         self.g.update_edge(eidx1, v, u, dep_data.clone());
@@ -124,11 +138,7 @@ impl DependencyGraph {
             self.g.outgoing_edges(u)
         };
         let index = edges.iter().position(|&eidx| self.g.edge(eidx).weight().label() == label);
-        if let Some(index) = index {
-            Some(edges[index])
-        } else {
-            None
-        }
+        index.map(|index| edges[index])
     }
 
     pub fn propagate_classical_deps(&mut self, u: NodeIndex, v: NodeIndex) {
@@ -293,8 +303,7 @@ impl DependencyGraph {
                     println!("edge {}: {}", idx, self.g.edge(idx).weight().label());
                 });
                 println!("--------------------------------------------");
-                assert!(false);
-                return;
+                panic!("the dependency graph has a cycle");
             }
         }
     }
@@ -335,6 +344,13 @@ pub struct DependencyGraphBuilder {
     sendrecv_pair: HashMap<(SessionId, Label), NodeIndex>, // TODO: unique?
     gen_ent_pair: HashMap<Label, Vec<NodeIndex>>,
     session_node_id: BTreeMap<(SessionId, ParticipantId), NodeIndex>,
+    program_order: bool,
+}
+
+impl Default for DependencyGraphBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DependencyGraphBuilder {
@@ -345,16 +361,24 @@ impl DependencyGraphBuilder {
             sendrecv_pair: HashMap::new(),
             gen_ent_pair: HashMap::new(),
             session_node_id: BTreeMap::new(),
+            program_order: false,
         }
     }
 
+    /// Also adds `__order` edges between consecutive instructions of each participant.
+    /// Participants issue instructions in order, so a cycle in the resulting graph is a deadlock.
+    pub fn with_program_order(mut self) -> Self {
+        self.program_order = true;
+        self
+    }
+
     pub fn build(mut self, s: System) -> DependencyGraph {
         self.add_system(s);
 
         // Add virtual dependency between entanglement generations
         for (_, vec) in self.gen_ent_pair {
             assert!(vec.len() == 2);
-            let nexts = vec![self.g.graph_ref().outgoing_nodes(vec[0]), self.g.graph_ref().outgoing_nodes(vec[1])];
+            let nexts = [self.g.graph_ref().outgoing_nodes(vec[0]), self.g.graph_ref().outgoing_nodes(vec[1])];
             for i in 0..2 {
                 let from = vec[i];
                 nexts[(i + 1) % 2].iter().for_each(|&to| {
@@ -369,7 +393,25 @@ impl DependencyGraphBuilder {
 
     fn add_system(&mut self, s: System) {
         match s {
-            System::Located(LocProc { p, procs }) => procs.into_iter().for_each(|proc| self.add_process(p, proc)),
+            System::Located(LocProc { p, procs }) => {
+                let mut prev_nodes = Vec::new();
+                for (i, proc) in procs.into_iter().enumerate() {
+                    let start = self.g.node_count();
+                    self.add_process(p, proc);
+                    let nodes: Vec<_> = (start..self.g.node_count()).collect();
+                    nodes.iter().for_each(|&id| { self.g.instr_index.insert(id, i); });
+                    if self.program_order {
+                        for &from in &prev_nodes {
+                            for &to in &nodes {
+                                self.g.add_edge(from, to, Dependency::new("__order".to_string()));
+                            }
+                        }
+                    }
+                    if !nodes.is_empty() {
+                        prev_nodes = nodes;
+                    }
+                }
+            },
             System::Composition(ss) => ss.into_iter().for_each(|s| self.add_system(s)),
         }
     }
@@ -507,8 +549,8 @@ impl DependencyGraphBuilder {
             Expr::Var(id) => vec![id.clone()],
             Expr::Not(e) => self.collect_exp_vars(e),
             Expr::BinOp(_, l, r) => {
-                let mut lvars = self.collect_exp_vars(&*l);
-                let mut rvars = self.collect_exp_vars(&*r);
+                let mut lvars = self.collect_exp_vars(l);
+                let mut rvars = self.collect_exp_vars(r);
                 lvars.append(&mut rvars);
                 lvars
             },
@@ -13,6 +13,12 @@ pub enum Expr {
 
     /// `barrier x1, .., xn`
     Barrier(BarrierExpr),
+
+    /// `reset x;`
+    Reset(ResetExpr),
+
+    /// `if (c == n) e`
+    If(IfExpr),
 }
 
 
@@ -34,6 +40,18 @@ impl From<MeasureExpr> for Expr {
     }
 }
 
+impl From<ResetExpr> for Expr {
+    fn from(e: ResetExpr) -> Self {
+        Expr::Reset(e)
+    }
+}
+
+impl From<IfExpr> for Expr {
+    fn from(e: IfExpr) -> Self {
+        Expr::If(e)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct InitExpr {
     pub dst: String,
@@ -45,6 +63,24 @@ pub struct ApplyExpr {
     pub args: Vec<String>,
 }
 
+impl ApplyExpr {
+    /// The gates of at most two qubits implementing this gate.
+    /// A Toffoli is lowered into CX, H, T and Tdg as in `qelib1.inc`.
+    pub fn lower(&self) -> Vec<ApplyExpr> {
+        match (&self.gate, &self.args[..]) {
+            (PrimitiveGate::CCX, [a, b, c]) => {
+                use PrimitiveGate::*;
+                [
+                    (H, vec![c]), (CX, vec![b, c]), (Tdg, vec![c]), (CX, vec![a, c]), (T, vec![c]),
+                    (CX, vec![b, c]), (Tdg, vec![c]), (CX, vec![a, c]), (T, vec![b]), (T, vec![c]),
+                    (H, vec![c]), (CX, vec![a, b]), (T, vec![a]), (Tdg, vec![b]), (CX, vec![a, b]),
+                ].into_iter().map(|(gate, args)| ApplyExpr { gate, args: args.into_iter().cloned().collect() }).collect()
+            },
+            _ => vec![self.clone()],
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MeasureExpr {
     pub kind: MeasureKind,
@@ -57,6 +93,20 @@ pub struct BarrierExpr {
     pub args: Vec<String>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResetExpr {
+    pub arg: String,
+}
+
+/// `body` is executed iff the classical register `bits`, whose first bit is the least significant one, equals `value`.
+/// A bit which has never been measured is `0`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfExpr {
+    pub bits: Vec<String>,
+    pub value: u64,
+    pub body: Box<Expr>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum PrimitiveGate {
     X,
@@ -68,6 +118,8 @@ pub enum PrimitiveGate {
     S,
     CX,
     Rz(f64),
+    /// A Toffoli, which is kept until codegen so that routers can gather its operands or lower it.
+    CCX,
 }
 
 impl From<PrimitiveGate> for inquir::PrimitiveGate {
@@ -82,6 +134,7 @@ impl From<PrimitiveGate> for inquir::PrimitiveGate {
             PrimitiveGate::CX => inquir::PrimitiveGate::CX,
             PrimitiveGate::Rz(theta) => inquir::PrimitiveGate::Rz(theta),
             PrimitiveGate::S => inquir::PrimitiveGate::S,
+            PrimitiveGate::CCX => unreachable!("CCX is lowered by `ApplyExpr::lower`"),
         }
     }
 }
@@ -5,6 +5,8 @@ pub use ast::{
     InitExpr,
     ApplyExpr,
     MeasureExpr,
+    ResetExpr,
+    IfExpr,
     PrimitiveGate,
     MeasureKind
 };
@@ -5,5 +5,7 @@ pub mod metrics;
 pub mod parser;
 pub mod optimizer;
 pub mod dependency_graph;
+pub mod deadlock;
+pub mod verify;
 pub mod simulation;
 pub mod utils;
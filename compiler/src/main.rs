@@ -1,11 +1,14 @@
 use inqcc::{
-    arch::Configuration,
-    parser::qasm2,
-    codegen::codegen,
-    codegen::routing::{Strategy, RemoteOpRouter, TelegateOnly, TeledataOnly},
-    metrics::Metrics,
+    arch::{Configuration, NodeInfo, Topology},
+    hir,
+    parser::{self, qasm2, qasm3, InputFormat, diagnostic::Diagnostic},
+    codegen::{codegen, Layout, decomposer::Decomposer, network::Swapping},
+    codegen::routing::{Strategy, RemoteOpRouter, TelegateOnly, TeledataOnly, Hybrid, Lookahead, Placement, placement, dry_run},
+    metrics::{Metrics, ShotMetrics},
     dependency_graph::DependencyGraphBuilder,
-    simulation::simulator::Simulator,
+    deadlock,
+    verify,
+    simulation::simulator::{Simulator, run_shots},
 };
 use inquir::System;
 
@@ -17,21 +20,38 @@ use clap::Parser;
 
 #[derive(Parser, Debug)]
 #[clap(author, version = "0.0.0", about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    /// Path to the QASM file
-    input: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the QASM or InQuIR file
+    #[clap(required = true)]
+    input: Option<String>,
+
+    /// Input language, which is guessed from the file if omitted
+    #[clap(arg_enum, long)]
+    input_format: Option<InputFormat>,
 
     /// Path to the output file
     #[clap(short, long)]
     output: Option<String>,
 
     /// Path to the configuration file
-    #[clap(short, long)]
-    config: String,
+    #[clap(short, long, required = true)]
+    config: Option<String>,
 
-    /// Compilation strategy
+    /// Compilation strategy, which is required for circuits
     #[clap(arg_enum, long)]
-    strategy: Strategy,
+    strategy: Option<Strategy>,
+
+    /// Initial placement of qubits on nodes
+    #[clap(arg_enum, long, default_value = "partition")]
+    placement: Placement,
+
+    /// Schedule of entanglement swapping along paths
+    #[clap(arg_enum, long, default_value = "chain")]
+    swapping: Swapping,
 
     #[clap(long)]
     metrics: Option<String>,
@@ -47,6 +67,92 @@ struct Args {
     /// Whether output an timestamps of issues.
     #[clap(long)]
     timestamp: Option<String>,
+
+    /// Check that the output implements the input circuit by simulation.
+    #[clap(long)]
+    verify: bool,
+
+    /// The number of additional simulations with --metrics, where Bell pairs are generated with the success probabilities of the links
+    #[clap(long, requires = "metrics", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    shots: Option<usize>,
+
+    /// The seed of the first shot
+    #[clap(long, default_value = "0")]
+    seed: u64,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Generate a configuration of a network
+    Generate(GenerateArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct GenerateArgs {
+    #[clap(subcommand)]
+    topology: Topology,
+
+    /// Path to the output file, which is the standard output if omitted
+    #[clap(short, long, global = true)]
+    output: Option<String>,
+
+    /// The number of data qubits of each node
+    #[clap(long, global = true, default_value = "2")]
+    data_qubits: u32,
+
+    /// The number of communication qubits at both ends of each link
+    #[clap(long, global = true, default_value = "1")]
+    link_capacity: u32,
+
+    /// Latency of single-qubit gates [ns]; every latency omitted is the default
+    #[clap(long, global = true)]
+    single_gate_cost: Option<u64>,
+
+    /// Latency of local CX gates [ns]
+    #[clap(long, global = true)]
+    local_cx_cost: Option<u64>,
+
+    /// Latency of generating a Bell pair over a link [ns]
+    #[clap(long, global = true)]
+    gen_ent_cost: Option<u64>,
+
+    /// Latency of measurements [ns]
+    #[clap(long, global = true)]
+    measure_cost: Option<u64>,
+
+    /// Latency of classical messages [ns]
+    #[clap(long, global = true)]
+    classical_comm_cost: Option<u64>,
+}
+
+fn generate(args: GenerateArgs) {
+    let mut node = NodeInfo::new(args.data_qubits);
+    if let Some(cost) = args.single_gate_cost {
+        node = node.with_single_gate_cost(cost);
+    }
+    if let Some(cost) = args.local_cx_cost {
+        node = node.with_local_cx_cost(cost);
+    }
+    if let Some(cost) = args.gen_ent_cost {
+        node = node.with_gen_ent_cost(cost);
+    }
+    if let Some(cost) = args.measure_cost {
+        node = node.with_measure_cost(cost);
+    }
+    if let Some(cost) = args.classical_comm_cost {
+        node = node.with_classical_comm_cost(cost);
+    }
+    let config = match inqcc::arch::generate(&args.topology, &node, args.link_capacity) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        },
+    };
+    match args.output {
+        Some(path) => fs::write(path, config.to_json()).unwrap(),
+        None => print!("{}", config.to_json()),
+    }
 }
 
 fn output_to_inquir_file(filename: &String, program: &System) -> Result<(), std::io::Error> {
@@ -60,28 +166,108 @@ fn output_metrics(filename: &String, metrics: &Metrics) -> Result<(), std::io::E
     write!(file, "{}", serialized)
 }
 
-fn main() {
-    let args = Args::parse();
-    println!("config: {}", args.config);
-    let config = Configuration::from_json(args.config);
-    //println!("{:?}", config);
+/// Parses and checks a program given in InQuIR,
+/// and decomposes its remote operations as the compiled programs are.
+fn load_inquir(input: &str, source: &str) -> System {
+    let s = match parser::inquir(source) {
+        Ok(s) => s,
+        Err(e) => {
+            eprint!("{}", Diagnostic::new(input, source, &e));
+            std::process::exit(1);
+        },
+    };
+    if let Err(diags) = inquir::checker::check(&s) {
+        diags.iter().for_each(|d| eprintln!("error: {}", d));
+        std::process::exit(1);
+    }
+    Decomposer::new().decompose(s)
+}
 
-    let source = fs::read_to_string(&args.input).unwrap();
-    let hir_exps = qasm2::parse(&source).unwrap();
+/// Compiles a circuit into a distributed program.
+fn compile(args: &Args, input: &str, source: &str, format: InputFormat, config: &Configuration) -> (System, Vec<hir::Expr>, Layout) {
+    let parsed = match format {
+        InputFormat::Qasm2 => qasm2::parse(source).map_err(|e| Diagnostic::new(input, source, &e)),
+        InputFormat::Qasm3 => qasm3::parse(source).map_err(|e| Diagnostic::new(input, source, &e)),
+        InputFormat::Inquir => unreachable!("InQuIR is not compiled"),
+    };
+    let hir_exps = match parsed {
+        Ok(exps) => exps,
+        Err(diag) => {
+            eprint!("{}", diag);
+            std::process::exit(1);
+        },
+    };
     //println!("Finished parse.");
+    let num_qubits = hir_exps.iter().filter(|e| matches!(e, hir::Expr::Init(_))).count();
+    if let Err(e) = config.check_qubits(num_qubits) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+    let Some(strategy) = &args.strategy else {
+        eprintln!("error: --strategy is required to compile a circuit");
+        std::process::exit(1);
+    };
 
-    let router: Box<dyn RemoteOpRouter> = match args.strategy {
-        Strategy::TeledataOnly => Box::new(TeledataOnly::new(&hir_exps, &config)),
-        Strategy::TelegateOnly => Box::new(TelegateOnly::new(&hir_exps, &config)),
+    let initial_map = placement::initial_map(&hir_exps, config, args.placement);
+    let router: Box<dyn RemoteOpRouter> = match strategy {
+        Strategy::TeledataOnly => Box::new(TeledataOnly::from_initial_map(initial_map, config)),
+        Strategy::TelegateOnly => Box::new(TelegateOnly::from_initial_map(initial_map)),
+        Strategy::Hybrid => Box::new(Hybrid::from_initial_map(initial_map, config)),
+        Strategy::Lookahead => {
+            let ours = dry_run(&mut Lookahead::from_initial_map(initial_map.clone(), config), &hir_exps, config);
+            let greedy = dry_run(&mut TeledataOnly::from_initial_map(initial_map.clone(), config), &hir_exps, config);
+            println!(
+                "Lookahead routing: {} moves and {} RSwaps, teledata-only: {} moves and {} RSwaps ({} qubit transfers saved)",
+                ours.moves, ours.rswaps, greedy.moves, greedy.rswaps, greedy.transfers() as i64 - ours.transfers() as i64
+            );
+            Box::new(Lookahead::from_initial_map(initial_map, config))
+        },
     };
 
-    let res = codegen(hir_exps, &config, router, args.quasi_para);
-    let output_filename = if let Some(filename) = args.output {
-        filename
+    let (res, layout) = codegen(hir_exps.clone(), config, router, args.swapping, args.quasi_para);
+    (res, hir_exps, layout)
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Some(Command::Generate(args)) = args.command {
+        return generate(args);
+    }
+    let input = args.input.clone().unwrap();
+    let config_path = args.config.clone().unwrap();
+    println!("config: {}", config_path);
+    let config = match Configuration::from_json(config_path.clone()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}: {}", config_path, e);
+            std::process::exit(1);
+        },
+    };
+    //println!("{:?}", config);
+
+    let source = match fs::read_to_string(&input) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("error: cannot read {}: {}", input, e);
+            std::process::exit(1);
+        },
+    };
+    let format = args.input_format.unwrap_or_else(|| InputFormat::detect(Path::new(&input), &source));
+    let (res, circuit) = if format == InputFormat::Inquir {
+        (load_inquir(&input, &source), None)
     } else {
-        Path::new(&args.input).file_stem().unwrap().to_str().unwrap().to_owned() + ".inq"
+        let (res, hir_exps, layout) = compile(&args, &input, &source, format, &config);
+        (res, Some((hir_exps, layout)))
+    };
+    // a program given in InQuIR is not written back over itself
+    let output_filename = match (args.output, &circuit) {
+        (Some(filename), _) => Some(filename),
+        (None, Some(_)) => Some(Path::new(&input).file_stem().unwrap().to_str().unwrap().to_owned() + ".inq"),
+        (None, None) => None,
     };
-    output_to_inquir_file(&output_filename, &res).unwrap();
+    if let Some(output_filename) = output_filename {
+        output_to_inquir_file(&output_filename, &res).unwrap();
+    }
     if let Some(depends_path) = args.depends {
         let mut file = std::fs::File::create(depends_path).unwrap();
         let builder = DependencyGraphBuilder::new();
@@ -89,16 +275,47 @@ fn main() {
         write!(file, "{}", graphviz).unwrap();
     };
 
+    if args.verify {
+        let Some((hir_exps, layout)) = circuit else {
+            eprintln!("error: --verify needs a circuit to compare with");
+            std::process::exit(1);
+        };
+        let backend = verify::choose_backend(&hir_exps);
+        let trials = 16;
+        if let Err(e) = verify::verify(&hir_exps, &res, &layout, backend, trials, 0) {
+            eprintln!("verification failed: {}", e);
+            std::process::exit(1);
+        }
+        println!("Verified with the {} backend ({} trials).", backend, trials);
+    }
+
     if let Some(met_path) = args.metrics {
+        if let Err(diags) = inquir::checker::check(&res) {
+            diags.iter().for_each(|d| eprintln!("error: {}", d));
+            std::process::exit(1);
+        }
+        let deadlocks = deadlock::detect(&res, &config);
+        if !deadlocks.is_empty() {
+            deadlocks.iter().for_each(|d| eprint!("{}", d));
+            std::process::exit(1);
+        }
         let mut simulator = Simulator::new(&res, &config);
         let cost = simulator.run();
-        let metrics = Metrics::new(&res, cost);
+        let mut metrics = Metrics::new(&res, cost);
         println!("Metrics:");
         println!("  E-depth: {}", metrics.e_depth());
         println!("  E-count: {}", metrics.e_count());
         println!("  C-depth: {}", metrics.c_depth());
         println!("  C-count: {}", metrics.c_count());
         println!("  Total time: {}", metrics.total_time());
+        if let Some(shots) = args.shots {
+            let dist = ShotMetrics::new(&run_shots(&res, &config, shots, args.seed));
+            println!("Over {} shots:", shots);
+            println!("  Total time: {}", dist.total_time());
+            println!("  Gen-ent time: {}", dist.gen_ent_time());
+            println!("  E-depth: {}", dist.e_depth());
+            metrics = metrics.with_shots(dist);
+        }
         output_metrics(&met_path, &metrics).unwrap();
 
         if let Some(time_path) = args.timestamp {
@@ -107,7 +324,7 @@ fn main() {
             let mut endline = false;
             for timestamps in timestamps {
                 if endline {
-                    write!(file, "\n").unwrap();
+                    writeln!(file).unwrap();
                 }
                 endline = true;
                 let mut comma = false;
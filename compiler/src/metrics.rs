@@ -6,6 +6,7 @@ use inquir::{
 use crate::simulation::evaluation_cost::EvaluationCost;
 
 use serde::Serialize;
+use std::fmt;
 
 
 #[derive(Serialize, Debug)]
@@ -16,6 +17,92 @@ pub struct Metrics {
     e_count: u64,
     total_time: u64,
     gen_ent_time: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shots: Option<ShotMetrics>,
+}
+
+/// The metrics over shots where the entanglement generation succeeds by chance.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ShotMetrics {
+    total_time: Distribution,
+    gen_ent_time: Distribution,
+    e_depth: Distribution,
+}
+
+impl ShotMetrics {
+    pub fn new(costs: &[EvaluationCost]) -> Self {
+        let dist = |f: fn(&EvaluationCost) -> u64| Distribution::new(costs.iter().map(f).collect());
+        Self {
+            total_time: dist(EvaluationCost::total_time),
+            gen_ent_time: dist(EvaluationCost::gen_ent_time),
+            e_depth: dist(EvaluationCost::e_depth),
+        }
+    }
+
+    pub fn total_time(&self) -> &Distribution {
+        &self.total_time
+    }
+
+    pub fn gen_ent_time(&self) -> &Distribution {
+        &self.gen_ent_time
+    }
+
+    pub fn e_depth(&self) -> &Distribution {
+        &self.e_depth
+    }
+}
+
+/// The samples of a metric and their statistics.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Distribution {
+    mean: f64,
+    std_dev: f64,
+    min: u64,
+    median: u64,
+    max: u64,
+    samples: Vec<u64>,
+}
+
+impl Distribution {
+    pub fn new(samples: Vec<u64>) -> Self {
+        assert!(!samples.is_empty(), "no samples");
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<u64>() as f64 / n;
+        let var = samples.iter().map(|&x| (x as f64 - mean).powi(2)).sum::<f64>() / n;
+        let mut sorted = samples.clone();
+        sorted.sort();
+        Self { mean, std_dev: var.sqrt(), min: sorted[0], median: sorted[sorted.len() / 2], max: sorted[sorted.len() - 1], samples }
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        self.std_dev
+    }
+
+    pub fn min(&self) -> u64 {
+        self.min
+    }
+
+    pub fn median(&self) -> u64 {
+        self.median
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn samples(&self) -> &[u64] {
+        &self.samples
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "mean {:.1}, std {:.1}, min {}, median {}, max {}", self.mean, self.std_dev, self.min, self.median, self.max)
+    }
 }
 
 impl Metrics {
@@ -27,9 +114,19 @@ impl Metrics {
             e_count: calc_e_count(s),
             total_time: eval_cost.total_time(),
             gen_ent_time: eval_cost.gen_ent_time(),
+            shots: None,
         }
     }
 
+    pub fn with_shots(mut self, shots: ShotMetrics) -> Self {
+        self.shots = Some(shots);
+        self
+    }
+
+    pub fn shots(&self) -> Option<&ShotMetrics> {
+        self.shots.as_ref()
+    }
+
     pub fn e_count(&self) -> u64 {
         self.e_count
     }
@@ -57,23 +154,23 @@ impl Metrics {
 
 pub fn calc_e_count(s: &System) -> u64 {
     match s {
-        System::Located(LocProc { p: _, procs }) =>  procs.iter().map(|proc| calc_e_count_proc(proc)).sum(),
-        System::Composition(ss) => ss.iter().map(|t| calc_e_count(t)).sum(),
+        System::Located(LocProc { p: _, procs }) =>  procs.iter().map(calc_e_count_proc).sum(),
+        System::Composition(ss) => ss.iter().map(calc_e_count).sum(),
     }
 }
 
 fn calc_e_count_proc(proc: &Process) -> u64 {
     match proc {
         Process::GenEnt(_) => 1,
-        Process::Parallel(procs) => procs.iter().map(|proc| calc_e_count_proc(proc)).sum(),
+        Process::Parallel(procs) => procs.iter().map(calc_e_count_proc).sum(),
         _ => 0,
     }
 }
 
 fn calc_c_count(s: &System) -> u64 {
     match s {
-        System::Located(LocProc { p: _, procs }) => procs.iter().map(|proc| calc_c_count_proc(proc)).sum(),
-        System::Composition(ss) => ss.iter().map(|s| calc_c_count(s)).sum(),
+        System::Located(LocProc { p: _, procs }) => procs.iter().map(calc_c_count_proc).sum(),
+        System::Composition(ss) => ss.iter().map(calc_c_count).sum(),
     }
 }
 
@@ -82,7 +179,7 @@ fn calc_c_count_proc(e: &Process) -> u64 {
         Process::QSend(_) | Process::QRecv(_) => 1,
         Process::Send(_) | Process::Recv(_) => 1,
         Process::RCXC(_) | Process::RCXT(_) => 1,
-        Process::Parallel(es) => es.iter().map(|e| calc_c_count_proc(e)).sum(),
+        Process::Parallel(es) => es.iter().map(calc_c_count_proc).sum(),
         _ => 0,
     }
 }
@@ -7,6 +7,9 @@ use inquir::{
 use crate::dependency_graph::DependencyGraphBuilder;
 use std::time::Instant;
 
+// The propagation through measurements is disabled temporarily.
+const PROPAGATE_THROUGH_MEASUREMENTS: bool = false;
+
 pub fn standardize(s: System) -> System {
     let builder = DependencyGraphBuilder::new();
     let mut g = builder.build(s);
@@ -34,6 +37,7 @@ pub fn standardize(s: System) -> System {
                     let app1 = e1.as_app().unwrap();
                     let app2 = e2.as_app().unwrap();
                     match (&app1.gate, &app2.gate) {
+                        #[allow(clippy::collapsible_match)] // Keep the other arms from seeing `I`.
                         (_, PrimitiveGate::I) => {
                             if app1.gate != PrimitiveGate::I && app1.args.len() == 1 {
                                 assert!(app1.args == app2.args);
@@ -53,7 +57,7 @@ pub fn standardize(s: System) -> System {
                             updated = true;
                         },
                         (PrimitiveGate::X, PrimitiveGate::CX) => {
-                            assert!(app2.ctrl == None);
+                            assert!(app2.ctrl.is_none());
                             if app1.args[0] == app2.args[0] { // X(1);CX(1,2) = CX(1,2);X(1);X(2)
                                 g.swap_adjacent(node1, node2);
                                 let app2 = ApplyProc {
@@ -69,7 +73,7 @@ pub fn standardize(s: System) -> System {
                             updated = true;
                         },
                         (PrimitiveGate::Z, PrimitiveGate::CX) => {
-                            assert!(app2.ctrl == None);
+                            assert!(app2.ctrl.is_none());
                             if app1.args[0] == app2.args[1] {
                                 g.swap_adjacent(node1, node2);
                                 let app2 = ApplyProc {
@@ -104,12 +108,14 @@ pub fn standardize(s: System) -> System {
                         _ => {}
                     }
                 } else if e1.is_app() && e2.is_measure() {
-                    continue; // Disable temporarily
+                    if !PROPAGATE_THROUGH_MEASUREMENTS {
+                        continue; // Disable temporarily
+                    }
                     let app1 = e1.as_app().unwrap();
                     let meas2 = e2.as_measure().unwrap();
                     // 1. Does not support multi-qubit measurements
                     // 2. Does not propagate immediately before the final measurements
-                    if meas2.args.len() > 1 || g.outgoing_edges(node2).len() == 0 {
+                    if meas2.args.len() > 1 || g.outgoing_edges(node2).is_empty() {
                         continue;
                     }
                     match &app1.gate {
@@ -19,40 +19,31 @@ pub fn vectorize(s: System, config: &Configuration) -> System {
     let mut que = VecDeque::new();
     let mut in_deg: Vec<_> = (0..dep_g.node_count()).map(|i| dep_g.incoming_edges(i).len()).collect();
     in_deg.iter().enumerate().for_each(|(i, &d)| if d == 0 { que.push_back(i); });
-    let mut capacity = vec![vec![0; n]; n];
-    config.connections().edges().iter().for_each(|e| {
-        capacity[e.source()][e.target()] += 1;
-        capacity[e.target()][e.source()] += 1;
-    });
     let mut current_partners = vec![HashMap::new(); n];
     let mut res = vec![Vec::new(); config.node_size()];
-    while que.len() > 0 {
+    while !que.is_empty() {
         let mut processed_count = 0;
         let mut nxt_que = VecDeque::new();
         let mut tmp = vec![Vec::new(); n];
         while let Some(i) = que.pop_front() {
             let v = dep_g.node(i);
             let (p, e) = v.weight();
-            // check the current capacity to generate a new entanglement
+            // check the free communication qubits to generate a new entanglement
             match e {
                 Process::GenEnt(GenEntProc { x, p: another, label: _ }) => {
                     let s = p.to_usize();
-                    let t = another.to_usize();
-                    if capacity[s][t] == 0 { // postpone
+                    let t = another.to_u32();
+                    let partners: Vec<_> = current_partners[s].values().copied().chain([t]).collect();
+                    if !config.can_hold(s, &partners) { // postpone
                         nxt_que.push_back(i);
                         continue;
                     } else {
-                        capacity[s][t] -= 1;
                         current_partners[s].insert(x, t);
                     }
                 },
                 Process::Free(FreeProc { arg }) => {
-                    let s = p.to_usize();
-                    if current_partners[s].contains_key(&arg) { // entanglements
-                        let t = current_partners[s][&arg];
-                        capacity[s][t] += 1;
-                        current_partners[s].remove(arg);
-                    }
+                    // entanglements
+                    current_partners[p.to_usize()].remove(arg);
                 },
                 Process::Parallel(_) => unimplemented!(),
                 _ => {},
@@ -68,14 +59,10 @@ pub fn vectorize(s: System, config: &Configuration) -> System {
         }
 
         tmp.into_iter().enumerate().for_each(|(p, exps)| {
-            // obtain capacities from entanglement consumptions
+            // release communication qubits by entanglement consumptions
             exps.iter().for_each(|e| {
                 match e {
                     Process::EntSwap(EntSwapProc { x1: _, x2: _, arg1, arg2 }) => {
-                        let t1 = current_partners[p][arg1];
-                        let t2 = current_partners[p][arg2];
-                        capacity[p][t1] += 1;
-                        capacity[p][t2] += 1;
                         current_partners[p].remove(arg1);
                         current_partners[p].remove(arg2);
                     },
@@ -83,8 +70,6 @@ pub fn vectorize(s: System, config: &Configuration) -> System {
                     | Process::QRecv(QRecvProc { s: _, label: _, dst: _, ent, uid: _ })
                     | Process::RCXC(RCXCProc { s: _, p: _, label: _, arg: _, ent, uid: _ })
                     | Process::RCXT(RCXTProc { s: _, p: _, label: _, arg: _, ent, uid: _ }) => {
-                        let t = current_partners[p][ent];
-                        capacity[p][t] += 1;
                         current_partners[p].remove(ent);
                     },
                     Process::Parallel(_) => unimplemented!(),
@@ -95,7 +80,7 @@ pub fn vectorize(s: System, config: &Configuration) -> System {
             processed_count += exps.len();
 
             // parallelize
-            if exps.len() > 0 {
+            if !exps.is_empty() {
                 let e = if exps.len() > 1 {
                     Process::Parallel(exps)
                 } else {
@@ -112,7 +97,7 @@ pub fn vectorize(s: System, config: &Configuration) -> System {
     pb.finish_with_message("Done vectorization");
 
     let located_procs: Vec<_> = res.into_iter().enumerate().filter_map(|(p, procs)| {
-        if procs.len() == 0 {
+        if procs.is_empty() {
             None
         } else {
             Some(System::Located(LocProc { p: ParticipantId::new(p as u32), procs }))
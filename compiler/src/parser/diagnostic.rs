@@ -0,0 +1,113 @@
+use nom::error::{VerboseError, VerboseErrorKind};
+use std::fmt;
+
+/// An error of a front end at a position of the source.
+/// The position is the length of the rest of the source from there,
+/// so that parsers can tell it without the whole source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Located<E> {
+    pub pos: usize,
+    pub error: E,
+}
+
+impl<E> Located<E> {
+    pub fn new(pos: usize, error: E) -> Self {
+        Self { pos, error }
+    }
+
+    /// Where the error is as a byte offset from the beginning of `source`.
+    pub fn offset(&self, source: &str) -> usize {
+        source.len().saturating_sub(self.pos)
+    }
+}
+
+/// The position and the message of a nom error.
+/// A context is preferred if any, and the innermost failure otherwise.
+pub fn syntax_error(err: nom::Err<VerboseError<&str>>) -> Located<String> {
+    let errors = match err {
+        nom::Err::Error(e) | nom::Err::Failure(e) => e.errors,
+        nom::Err::Incomplete(_) => vec![],
+    };
+    let context = errors.iter().find(|(_, kind)| matches!(kind, VerboseErrorKind::Context(_)));
+    match context.or(errors.first()) {
+        Some((rest, kind)) => {
+            let message = match kind {
+                VerboseErrorKind::Context(ctx) => ctx.to_string(),
+                VerboseErrorKind::Char(c) => format!("expected `{}`, found {}", c, token(rest)),
+                VerboseErrorKind::Nom(_) => format!("unexpected {}", token(rest)),
+            };
+            Located::new(rest.len(), message)
+        },
+        None => Located::new(0, "unexpected end of input".to_string()),
+    }
+}
+
+/// The first token of the input for messages.
+fn token(input: &str) -> String {
+    let end = input.find(|c: char| c.is_whitespace()).unwrap_or(input.len());
+    match &input[..end] {
+        "" => "end of input".to_string(),
+        tok => {
+            let tok: String = tok.chars().take(16).collect();
+            format!("`{}`", tok)
+        },
+    }
+}
+
+/// An error message pointing to the line and the column of a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: String,
+    /// 1-origin
+    pub line: usize,
+    /// 1-origin, counted in characters
+    pub column: usize,
+    pub message: String,
+    /// The source line containing the error.
+    pub snippet: String,
+}
+
+impl Diagnostic {
+    pub fn new<E: fmt::Display>(file: &str, source: &str, err: &Located<E>) -> Self {
+        let offset = err.offset(source);
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[offset..].find('\n').map_or(source.len(), |i| offset + i);
+        Self {
+            file: file.to_string(),
+            line: source[..offset].matches('\n').count() + 1,
+            column: source[line_start..offset].chars().count() + 1,
+            message: err.error.to_string(),
+            snippet: source[line_start..line_end].trim_end().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.line.to_string().len();
+        writeln!(f, "error: {}", self.message)?;
+        writeln!(f, "{:width$}--> {}:{}:{}", "", self.file, self.line, self.column, width = width)?;
+        writeln!(f, "{:width$} |", "", width = width)?;
+        writeln!(f, "{} | {}", self.line, self.snippet)?;
+        writeln!(f, "{:width$} | {:>column$}", "", "^", width = width, column = self.column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostic() {
+        let source = "qreg q[1];\ncu3(0, 0) q[0];\n";
+        let err = Located::new(source.len() - 11, "undefined gate `cu3`");
+        let d = Diagnostic::new("a.qasm", source, &err);
+        assert_eq!((d.line, d.column), (2, 1));
+        assert_eq!(d.to_string(), "error: undefined gate `cu3`
+ --> a.qasm:2:1
+  |
+2 | cu3(0, 0) q[0];
+  | ^
+");
+    }
+}
@@ -0,0 +1,451 @@
+use crate::hir::ast::{
+    Expr,
+    InitExpr,
+    ApplyExpr,
+    MeasureExpr,
+    BarrierExpr,
+    ResetExpr,
+    IfExpr,
+    MeasureKind,
+    PrimitiveGate,
+};
+use std::fmt;
+use std::collections::HashMap;
+use std::f64::consts::{PI, FRAC_PI_2, FRAC_PI_4};
+
+/// A parameter expression of gates, e.g., `-pi/4`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamExpr {
+    Num(f64),
+    Pi,
+    Var(String),
+    Neg(Box<ParamExpr>),
+    /// One of `+`, `-`, `*`, `/` and `^`.
+    BinOp(char, Box<ParamExpr>, Box<ParamExpr>),
+    /// One of `sin`, `cos`, `tan`, `exp`, `ln` and `sqrt`.
+    Call(String, Box<ParamExpr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    /// All the elements of a register.
+    Register(String),
+    Indexed(String, usize),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Register(name) => write!(f, "{}", name),
+            Operand::Indexed(name, idx) => write!(f, "{}[{}]", name, idx),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GateCall {
+    pub name: String,
+    pub params: Vec<ParamExpr>,
+    pub args: Vec<Operand>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GateDef {
+    pub name: String,
+    pub params: Vec<String>,
+    pub args: Vec<String>,
+    /// `None` for opaque gates. Arguments in the body are `Operand::Register`.
+    pub body: Option<Vec<Stmt>>,
+    /// Whether the gate is defined by an included standard library.
+    pub standard: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    QReg(String, usize),
+    CReg(String, usize),
+    Gate(GateDef),
+    Apply(GateCall),
+    /// `measure q -> c;`, or `measure q;` whose outcomes are discarded.
+    Measure(Operand, Option<Operand>),
+    Barrier(Vec<Operand>),
+    Reset(Operand),
+    /// `if (c == n) stmt` for a register or a bit `c`.
+    If(Operand, u64, Box<Stmt>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    UndefinedGate(String),
+    UndefinedRegister(String),
+    UndefinedParameter(String),
+    UndefinedFunction(String),
+    /// A parameter evaluates to an infinity or NaN, such as `1/0`.
+    NonFiniteParameter,
+    DuplicateDefinition(String),
+    OutOfRange(String, usize),
+    /// The numbers of parameters or arguments are different from the definition.
+    ArityMismatch(String),
+    /// Registers of different sizes are broadcast together.
+    SizeMismatch(Vec<Operand>),
+    /// A qubit is given to a gate application more than once.
+    DuplicateOperand(Operand),
+    OpaqueGate(String),
+    /// Only gate applications can be classically conditioned.
+    UnsupportedCondition(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UndefinedGate(name) => write!(f, "undefined gate `{}`", name),
+            Error::UndefinedRegister(name) => write!(f, "undefined register `{}`", name),
+            Error::UndefinedParameter(name) => write!(f, "undefined parameter `{}`", name),
+            Error::UndefinedFunction(name) => write!(f, "undefined function `{}`", name),
+            Error::NonFiniteParameter => write!(f, "a parameter evaluates to a non-finite number"),
+            Error::DuplicateDefinition(name) => write!(f, "`{}` is defined multiple times", name),
+            Error::OutOfRange(name, idx) => write!(f, "index out of range: `{}[{}]`", name, idx),
+            Error::ArityMismatch(name) => write!(f, "wrong number of parameters or arguments for `{}`", name),
+            Error::SizeMismatch(args) => {
+                let args: Vec<_> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "registers of different sizes: `{}`", args.join(", "))
+            },
+            Error::DuplicateOperand(arg) => write!(f, "qubit `{}` is used multiple times in a gate application", arg),
+            Error::OpaqueGate(name) => write!(f, "opaque gate `{}` cannot be compiled", name),
+            Error::UnsupportedCondition(stmt) => write!(f, "only gate applications can be conditioned, not `{}`", stmt),
+        }
+    }
+}
+
+/// Expands register declarations, broadcasts and gate definitions into HIR expressions
+/// whose gates are primitive. `U` and `CX` are the builtin gates.
+#[derive(Default)]
+pub struct Elaborator {
+    qregs: HashMap<String, usize>,
+    cregs: HashMap<String, usize>,
+    gates: HashMap<String, GateDef>,
+    /// The number of the discarded outcomes so far.
+    discarded: usize,
+}
+
+impl Elaborator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn elaborate(&mut self, stmts: Vec<Stmt>) -> Result<Vec<Expr>, Error> {
+        let mut res = Vec::new();
+        for stmt in stmts {
+            self.elaborate_stmt(stmt, &mut res)?;
+        }
+        Ok(res)
+    }
+
+    pub fn elaborate_stmt(&mut self, stmt: Stmt, res: &mut Vec<Expr>) -> Result<(), Error> {
+        match stmt {
+            Stmt::QReg(name, size) => {
+                self.declare(&name)?;
+                res.extend((0..size).map(|i| Expr::from(InitExpr { dst: qubit_name(&name, i) })));
+                self.qregs.insert(name, size);
+            },
+            Stmt::CReg(name, size) => {
+                self.declare(&name)?;
+                self.cregs.insert(name, size);
+            },
+            Stmt::Gate(def) => {
+                if self.gates.contains_key(&def.name) || def.name == "U" || def.name == "CX" {
+                    return Err(Error::DuplicateDefinition(def.name));
+                }
+                // gates must be defined before use, which also rules out recursion
+                for stmt in def.body.iter().flatten() {
+                    if let Stmt::Apply(call) = stmt {
+                        if !self.gates.contains_key(&call.name) && call.name != "U" && call.name != "CX" {
+                            return Err(Error::UndefinedGate(call.name.clone()));
+                        }
+                        if let Some(j) = duplicate(&call.args) {
+                            return Err(Error::DuplicateOperand(call.args[j].clone()));
+                        }
+                    }
+                }
+                self.gates.insert(def.name.clone(), def);
+            },
+            Stmt::Apply(GateCall { name, params, args }) => {
+                let params = params.iter().map(|e| eval(e, &HashMap::new())).collect::<Result<Vec<_>, _>>()?;
+                for (i, qubits) in self.broadcast(&args, &self.qregs)?.into_iter().enumerate() {
+                    if let Some(j) = duplicate(&qubits) {
+                        return Err(Error::DuplicateOperand(match &args[j] {
+                            Operand::Register(name) => Operand::Indexed(name.clone(), i),
+                            arg => arg.clone(),
+                        }));
+                    }
+                    self.expand(&name, &params, &qubits, res)?;
+                }
+            },
+            Stmt::Measure(arg, dst) => {
+                let args = self.broadcast(std::slice::from_ref(&arg), &self.qregs)?;
+                let dsts = match dst {
+                    Some(dst) => {
+                        let dsts = self.broadcast(std::slice::from_ref(&dst), &self.cregs)?;
+                        if args.len() != dsts.len() {
+                            return Err(Error::SizeMismatch(vec![arg, dst]));
+                        }
+                        dsts
+                    },
+                    None => (0..args.len()).map(|_| {
+                        self.discarded += 1;
+                        vec![format!("_discarded{}", self.discarded - 1)]
+                    }).collect(),
+                };
+                res.extend(args.into_iter().zip(dsts).map(|(args, mut dst)| {
+                    Expr::from(MeasureExpr { kind: MeasureKind::Z, dst: dst.remove(0), args })
+                }));
+            },
+            Stmt::Barrier(args) => {
+                let args = args.iter().map(|arg| self.broadcast(std::slice::from_ref(arg), &self.qregs))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter().flatten().flatten().collect();
+                res.push(Expr::Barrier(BarrierExpr { args }));
+            },
+            Stmt::Reset(arg) => {
+                let args = self.broadcast(std::slice::from_ref(&arg), &self.qregs)?;
+                res.extend(args.into_iter().map(|mut arg| Expr::from(ResetExpr { arg: arg.remove(0) })));
+            },
+            Stmt::If(cond, value, stmt) => {
+                let bits = match cond {
+                    Operand::Register(creg) => {
+                        let size = *self.cregs.get(&creg).ok_or_else(|| Error::UndefinedRegister(creg.clone()))?;
+                        (0..size).map(|i| qubit_name(&creg, i)).collect()
+                    },
+                    bit => self.broadcast(&[bit], &self.cregs)?.remove(0),
+                };
+                match *stmt {
+                    Stmt::Measure(..) => return Err(Error::UnsupportedCondition("measure".to_string())),
+                    Stmt::Reset(_) => return Err(Error::UnsupportedCondition("reset".to_string())),
+                    Stmt::If(..) => return Err(Error::UnsupportedCondition("if".to_string())),
+                    _ => {},
+                }
+                let mut body = Vec::new();
+                self.elaborate_stmt(*stmt, &mut body)?;
+                res.extend(body.into_iter().map(|e| match e {
+                    Expr::Barrier(_) => e,
+                    e => Expr::from(IfExpr { bits: bits.clone(), value, body: Box::new(e) }),
+                }));
+            },
+        }
+        Ok(())
+    }
+
+    fn declare(&self, name: &str) -> Result<(), Error> {
+        if self.qregs.contains_key(name) || self.cregs.contains_key(name) {
+            Err(Error::DuplicateDefinition(name.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// `h q;` is applied to each qubit of `q`, and `cx a,b;` to each pair of `a[i]` and `b[i]`.
+    fn broadcast(&self, args: &[Operand], regs: &HashMap<String, usize>) -> Result<Vec<Vec<String>>, Error> {
+        let mut size = None;
+        for arg in args {
+            match arg {
+                Operand::Register(name) => {
+                    let n = *regs.get(name).ok_or_else(|| Error::UndefinedRegister(name.clone()))?;
+                    if size.is_some_and(|size| size != n) {
+                        return Err(Error::SizeMismatch(args.to_vec()));
+                    }
+                    size = Some(n);
+                },
+                Operand::Indexed(name, idx) => {
+                    let n = *regs.get(name).ok_or_else(|| Error::UndefinedRegister(name.clone()))?;
+                    if *idx >= n {
+                        return Err(Error::OutOfRange(name.clone(), *idx));
+                    }
+                },
+            }
+        }
+        Ok((0..size.unwrap_or(1)).map(|i| args.iter().map(|arg| match arg {
+            Operand::Register(name) => qubit_name(name, i),
+            Operand::Indexed(name, idx) => qubit_name(name, *idx),
+        }).collect()).collect())
+    }
+
+    fn expand(&self, name: &str, params: &[f64], args: &[String], res: &mut Vec<Expr>) -> Result<(), Error> {
+        match (name, params, args) {
+            ("U", &[theta, phi, lambda], [q]) => {
+                res.extend(lower_u(theta, phi, lambda).into_iter().map(|gate| {
+                    Expr::from(ApplyExpr { gate, args: vec![q.clone()] })
+                }));
+                return Ok(());
+            },
+            ("CX", &[], [c, t]) => {
+                res.push(Expr::from(ApplyExpr { gate: PrimitiveGate::CX, args: vec![c.clone(), t.clone()] }));
+                return Ok(());
+            },
+            ("U" | "CX", _, _) => return Err(Error::ArityMismatch(name.to_string())),
+            // the standard Toffoli is routed as a whole
+            ("ccx", &[], [a, b, c]) if self.gates.get(name).is_some_and(|def| def.standard) => {
+                res.push(Expr::from(ApplyExpr { gate: PrimitiveGate::CCX, args: vec![a.clone(), b.clone(), c.clone()] }));
+                return Ok(());
+            },
+            _ => {},
+        }
+
+        let def = self.gates.get(name).ok_or_else(|| Error::UndefinedGate(name.to_string()))?;
+        if def.params.len() != params.len() || def.args.len() != args.len() {
+            return Err(Error::ArityMismatch(name.to_string()));
+        }
+        let body = def.body.as_ref().ok_or_else(|| Error::OpaqueGate(name.to_string()))?;
+        let param_env: HashMap<_, _> = def.params.iter().cloned().zip(params.iter().copied()).collect();
+        let arg_env: HashMap<_, _> = def.args.iter().zip(args).collect();
+        let resolve = |arg: &Operand| match arg {
+            Operand::Register(x) => arg_env.get(x).map(|&q| q.clone()).ok_or_else(|| Error::UndefinedRegister(x.clone())),
+            Operand::Indexed(x, _) => Err(Error::UndefinedRegister(x.clone())),
+        };
+        for stmt in body {
+            match stmt {
+                Stmt::Apply(call) => {
+                    let params = call.params.iter().map(|e| eval(e, &param_env)).collect::<Result<Vec<_>, _>>()?;
+                    let args = call.args.iter().map(resolve).collect::<Result<Vec<_>, _>>()?;
+                    self.expand(&call.name, &params, &args, res)?;
+                },
+                Stmt::Barrier(args) => {
+                    let args = args.iter().map(resolve).collect::<Result<Vec<_>, _>>()?;
+                    res.push(Expr::Barrier(BarrierExpr { args }));
+                },
+                _ => unreachable!("only gate applications and barriers are parsed in gate bodies"),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The index of an element which appears earlier in `xs` too.
+fn duplicate<T: PartialEq>(xs: &[T]) -> Option<usize> {
+    (0..xs.len()).find(|&j| xs[..j].contains(&xs[j]))
+}
+
+fn qubit_name(reg: &str, idx: usize) -> String {
+    reg.to_string() + &idx.to_string()
+}
+
+pub fn eval(e: &ParamExpr, env: &HashMap<String, f64>) -> Result<f64, Error> {
+    let v = match e {
+        ParamExpr::Num(v) => *v,
+        ParamExpr::Pi => PI,
+        ParamExpr::Var(x) => *env.get(x).ok_or_else(|| Error::UndefinedParameter(x.clone()))?,
+        ParamExpr::Neg(e) => -eval(e, env)?,
+        ParamExpr::BinOp(op, l, r) => {
+            let (l, r) = (eval(l, env)?, eval(r, env)?);
+            match op {
+                '+' => l + r,
+                '-' => l - r,
+                '*' => l * r,
+                '/' => l / r,
+                '^' => l.powf(r),
+                _ => unreachable!(),
+            }
+        },
+        ParamExpr::Call(f, e) => {
+            let v = eval(e, env)?;
+            match f.as_str() {
+                "sin" => v.sin(),
+                "cos" => v.cos(),
+                "tan" => v.tan(),
+                "exp" => v.exp(),
+                "ln" => v.ln(),
+                "sqrt" => v.sqrt(),
+                _ => return Err(Error::UndefinedFunction(f.clone())),
+            }
+        },
+    };
+    if v.is_finite() { Ok(v) } else { Err(Error::NonFiniteParameter) }
+}
+
+/// Normalizes an angle into `(-pi, pi]`.
+fn normalize(theta: f64) -> f64 {
+    let theta = theta.rem_euclid(2.0 * PI);
+    if theta > PI { theta - 2.0 * PI } else { theta }
+}
+
+fn approx_eq(theta1: f64, theta2: f64) -> bool {
+    let d = normalize(theta1 - theta2);
+    d.abs() < 1e-9
+}
+
+/// A rotation around the Z axis up to a global phase.
+fn lower_rz(theta: f64) -> Vec<PrimitiveGate> {
+    if approx_eq(theta, 0.0) {
+        vec![]
+    } else if approx_eq(theta, FRAC_PI_4) {
+        vec![PrimitiveGate::T]
+    } else if approx_eq(theta, -FRAC_PI_4) {
+        vec![PrimitiveGate::Tdg]
+    } else if approx_eq(theta, FRAC_PI_2) {
+        vec![PrimitiveGate::S]
+    } else if approx_eq(theta, -FRAC_PI_2) {
+        vec![PrimitiveGate::S, PrimitiveGate::Z]
+    } else if approx_eq(theta, PI) {
+        vec![PrimitiveGate::Z]
+    } else {
+        vec![PrimitiveGate::Rz(normalize(theta))]
+    }
+}
+
+/// Decomposes `U(theta, phi, lambda) = Rz(phi) Ry(theta) Rz(lambda)` into primitive gates up to a global phase,
+/// where `Ry(theta) = S H Rz(theta) H Sdg`.
+pub fn lower_u(theta: f64, phi: f64, lambda: f64) -> Vec<PrimitiveGate> {
+    if approx_eq(theta, 0.0) {
+        lower_rz(phi + lambda)
+    } else if approx_eq(theta, FRAC_PI_2) && approx_eq(phi, 0.0) && approx_eq(lambda, PI) {
+        vec![PrimitiveGate::H]
+    } else if approx_eq(theta, PI) && approx_eq(phi, 0.0) && approx_eq(lambda, PI) {
+        vec![PrimitiveGate::X]
+    } else if approx_eq(theta, PI) && approx_eq(phi, FRAC_PI_2) && approx_eq(lambda, FRAC_PI_2) {
+        vec![PrimitiveGate::Y]
+    } else {
+        let mut res = lower_rz(lambda);
+        res.extend([PrimitiveGate::S, PrimitiveGate::Z, PrimitiveGate::H]);
+        res.extend(lower_rz(theta));
+        res.extend([PrimitiveGate::H, PrimitiveGate::S]);
+        res.extend(lower_rz(phi));
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{
+        semantics::QuantumState,
+        state_vector::{StateVector, Complex, fidelity},
+    };
+
+    /// The output state of `gates` applied to `|b>`.
+    fn column(gates: &[PrimitiveGate], b: bool) -> Vec<Complex> {
+        let mut sv = StateVector::new();
+        let q = sv.add_qubit().unwrap();
+        if b {
+            sv.apply(&inquir::PrimitiveGate::X, &[q]).unwrap();
+        }
+        gates.iter().for_each(|gate| sv.apply(&gate.clone().into(), &[q]).unwrap());
+        sv.amplitudes(&[q])
+    }
+
+    #[test]
+    fn lower_u_test() {
+        for (theta, phi, lambda) in [(0.3, 0.0, 0.0), (1.2, -0.7, 2.5), (PI, 0.4, -1.0), (FRAC_PI_2, 0.0, PI), (0.0, 0.1, 0.2)] {
+            let (c, s) = ((theta / 2.0).cos(), (theta / 2.0).sin());
+            let u = [
+                [Complex::new(c, 0.0), Complex::from_phase(lambda).scale(-s)],
+                [Complex::from_phase(phi).scale(s), Complex::from_phase(phi + lambda).scale(c)],
+            ];
+            let gates = lower_u(theta, phi, lambda);
+            let col0 = column(&gates, false);
+            let col1 = column(&gates, true);
+            // the global phase must be common to the columns
+            let expected = [u[0][0], u[1][0], u[0][1], u[1][1]];
+            let actual = [col0[0], col0[1], col1[0], col1[1]];
+            assert!((fidelity(&expected, &actual) - 4.0).abs() < 1e-9, "U({}, {}, {})", theta, phi, lambda);
+        }
+    }
+}
@@ -1,2 +1,54 @@
+pub mod diagnostic;
+pub mod elaborate;
 pub mod qasm2;
+pub mod qasm3;
 pub mod util;
+
+use std::path::Path;
+use diagnostic::{Located, syntax_error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+pub enum InputFormat {
+    Qasm2,
+    Qasm3,
+    /// A distributed program in the textual InQuIR, which is checked and simulated without compilation
+    Inquir,
+}
+
+impl InputFormat {
+    /// Guesses the format by the extension `.inq` or `.qasm3`, or the version declaration `OPENQASM 3`.
+    pub fn detect(path: &Path, source: &str) -> Self {
+        if path.extension().is_some_and(|ext| ext == "inq") {
+            return InputFormat::Inquir;
+        }
+        let source = qasm2::spaces_and_endlines(source).map_or(source, |(rest, _)| rest);
+        if path.extension().is_some_and(|ext| ext == "qasm3") || source.starts_with("OPENQASM 3") {
+            InputFormat::Qasm3
+        } else {
+            InputFormat::Qasm2
+        }
+    }
+}
+
+/// Parses an InQuIR program with errors located for diagnostics.
+pub fn inquir(source: &str) -> Result<inquir::System, Located<String>> {
+    inquir::parser::parse(source).map_err(|e| match e {
+        inquir::parser::Error::Unexpected(rest) => Located::new(rest.len(), "expected processes located at a participant".to_string()),
+        inquir::parser::Error::Nom(e) => syntax_error(e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_inquir_programs() {
+        assert_eq!(InputFormat::detect(Path::new("a.inq"), "OPENQASM 3;"), InputFormat::Inquir);
+        assert_eq!(InputFormat::detect(Path::new("a.qasm"), "OPENQASM 3;"), InputFormat::Qasm3);
+        let source = "0 {\n  q = init();\n  c = measure q;\n}\n1 { q = init(; }\n";
+        let err = inquir(source).unwrap_err();
+        assert_eq!(err.offset(source), source.find("1 {").unwrap());
+        assert!(inquir(&source[..source.find("1 {").unwrap()]).is_ok());
+    }
+}
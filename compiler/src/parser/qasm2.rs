@@ -1,14 +1,15 @@
-use crate::hir::ast::{
-    Expr,
-    InitExpr,
-    ApplyExpr,
-    MeasureExpr,
-    BarrierExpr,
-    MeasureKind,
-    PrimitiveGate
+use crate::hir::ast::Expr;
+use crate::parser::diagnostic::{Located, syntax_error};
+use crate::parser::elaborate::{
+    self,
+    Elaborator,
+    Stmt,
+    GateDef,
+    GateCall,
+    Operand,
+    ParamExpr,
 };
 
-use anyhow::Result;
 use nom::{
     IResult,
     branch::alt,
@@ -19,201 +20,349 @@ use nom::{
     },
     character::complete::{
         char,
+        digit1,
         alpha1,
-        alphanumeric0,
+        alphanumeric1,
+        one_of,
     },
-    combinator::{opt, map_res},
-    error::VerboseError,
-    multi::{many1, separated_list0, separated_list1},
+    combinator::{opt, map, map_res, peek, recognize},
+    error::{VerboseError, VerboseErrorKind, context},
+    multi::{many0, separated_list0, separated_list1},
     sequence::{
         tuple,
+        pair,
         delimited,
         preceded,
         terminated,
     },
     number::complete::double,
 };
+use std::fmt;
 
-#[derive(Debug)]
-pub enum Error<'a> {
-    Unexpected(&'a str),
-    Nom(nom::Err<VerboseError<&'a str>>),
+/// The standard gate library of OpenQASM 2.0.
+const QELIB1: &str = include_str!("qelib1.inc");
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Syntax(String),
+    Elaborate(elaborate::Error),
 }
 
-impl<'a> From<nom::Err<VerboseError<&'a str>>> for Error<'a> {
-    fn from(err: nom::Err<VerboseError<&'a str>>) -> Self {
-        Error::Nom(err)
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Syntax(msg) => write!(f, "{}", msg),
+            Error::Elaborate(e) => write!(f, "{}", e),
+        }
     }
 }
 
-pub fn parse(input: &str) -> Result<Vec<Expr>, Error> {
-    let (input, _) = parse_header(input)?;
-    let (input, _) = spaces_and_endlines(input)?;
-    let (input, exps) = many1(terminated(
-        parse_stmt,
-        tuple((tag(";"), spaces_and_endlines))))(input)?;
-    let (rest, _) = spaces_and_endlines(input)?;
+fn syntax(err: nom::Err<VerboseError<&str>>) -> Located<Error> {
+    let Located { pos, error } = syntax_error(err);
+    Located::new(pos, Error::Syntax(error))
+}
 
-    if rest == "" {
-        Ok(exps.concat())
-    } else {
-        Err(Error::Unexpected(rest))
+pub fn parse(input: &str) -> Result<Vec<Expr>, Located<Error>> {
+    let (input, _) = parse_header(input).map_err(syntax)?;
+    let (rest, stmts) = parse_stmts(input).map_err(syntax)?;
+    if !rest.is_empty() {
+        // the reason why the next statement cannot be parsed
+        return Err(match parse_stmt(rest) {
+            Err(err) => syntax(err),
+            Ok(_) => Located::new(rest.len(), Error::Syntax("unexpected input".to_string())),
+        });
     }
+    let mut elaborator = Elaborator::new();
+    let mut exps = Vec::new();
+    for (pos, stmt) in stmts {
+        elaborator.elaborate_stmt(stmt, &mut exps).map_err(|e| Located::new(pos, Error::Elaborate(e)))?;
+    }
+    Ok(exps)
 }
 
 pub fn parse_header(input: &str) -> IResult<&str, (), VerboseError<&str>> {
-    let (input, _) = tag("OPENQASM 2.0;")(input)?;
     let (input, _) = spaces_and_endlines(input)?;
-    let (input, _) = opt(parse_include)(input)?;
+    let (input, _) = context(
+        "expected `OPENQASM 2.0;`",
+        tuple((tag("OPENQASM"), spaces_and_endlines, tag("2.0"), spaces_and_endlines, char(';')))
+    )(input)?;
     let (input, _) = spaces_and_endlines(input)?;
     Ok((input, ()))
 }
 
-fn parse_include(input: &str) -> IResult<&str, (), VerboseError<&str>> {
+/// Statements with their positions, which are the lengths of the rest of the input.
+/// The statements of an included file are at the `include` statement.
+pub(crate) fn parse_stmts(input: &str) -> IResult<&str, Vec<(usize, Stmt)>, VerboseError<&str>> {
+    let (input, stmts) = preceded(spaces_and_endlines, many0(terminated(parse_located_stmt, spaces_and_endlines)))(input)?;
+    Ok((input, stmts.concat()))
+}
+
+fn parse_located_stmt(input: &str) -> IResult<&str, Vec<(usize, Stmt)>, VerboseError<&str>> {
+    let pos = input.len();
+    let (input, stmts) = parse_stmt(input)?;
+    Ok((input, stmts.into_iter().map(|stmt| (pos, stmt)).collect()))
+}
+
+fn parse_stmt(input: &str) -> IResult<&str, Vec<Stmt>, VerboseError<&str>> {
+    let (rest, keyword) = parse_variable(input)?;
+    match keyword.as_str() {
+        "include" => parse_include(input),
+        "qreg" | "creg" => map(parse_reg_decl, |decl| vec![decl])(input),
+        "gate" | "opaque" => map(parse_gate_decl, |def| vec![Stmt::Gate(def)])(input),
+        "measure" | "reset" => map(parse_qop, |e| vec![e])(input),
+        "if" => map(parse_if, |e| vec![e])(input),
+        "barrier" => {
+            let (input, args) = delimited(spaces_and_endlines, parse_operands, semicolon)(rest)?;
+            Ok((input, vec![Stmt::Barrier(args)]))
+        },
+        _ => map(terminated(parse_gate_call, semicolon), |call| vec![Stmt::Apply(call)])(input),
+    }
+}
+
+/// Only `qelib1.inc` can be included, which is replaced with the standard gate definitions.
+fn parse_include(input: &str) -> IResult<&str, Vec<Stmt>, VerboseError<&str>> {
     let (input, _) = tag("include")(input)?;
     let (input, _) = spaces_and_endlines(input)?;
-    let (input, _) = preceded(take_until(";"), tag(";"))(input)?; // TODO?
-    let (input, _) = spaces_and_endlines(input)?;
-    Ok((input, ()))
+    let (rest, file) = delimited(char('"'), take_until("\""), char('"'))(input)?;
+    let (rest, _) = semicolon(rest)?;
+    if file != "qelib1.inc" {
+        let errors = vec![(input, VerboseErrorKind::Context("unknown include file"))];
+        return Err(nom::Err::Failure(VerboseError { errors }));
+    }
+    Ok((rest, standard_library(QELIB1)))
 }
 
-fn parse_stmt(input: &str) -> IResult<&str, Vec<Expr>, VerboseError<&str>> {
-    let (input, e) = alt((
-        map_res(parse_reg_decl, |inits| -> Result<_> {
-            Ok(inits.into_iter().map(|e| Expr::from(e)).collect())
-        }),
-        map_res(parse_apply, |e| -> Result<_> { Ok(vec![Expr::from(e)]) }),
-        map_res(parse_measure, |e| -> Result<_> { Ok(vec![Expr::from(e)]) }),
-        map_res(parse_barrier, |e| -> Result<_> { Ok(vec![e]) })
-    ))(input)?;
-
-    Ok((input, e))
+/// The gate definitions of an include file.
+pub fn standard_library(source: &str) -> Vec<Stmt> {
+    let (_, stmts) = parse_stmts(source).expect("standard libraries must be valid");
+    stmts.into_iter().map(|(_, stmt)| match stmt {
+        Stmt::Gate(def) => Stmt::Gate(GateDef { standard: true, ..def }),
+        stmt => stmt,
+    }).collect()
 }
 
-pub fn parse_reg_decl(input: &str) -> IResult<&str, Vec<InitExpr>, VerboseError<&str>> {
+pub fn parse_reg_decl(input: &str) -> IResult<&str, Stmt, VerboseError<&str>> {
     let (input, reg_kind) = alt((tag("qreg"), tag("creg")))(input)?;
-    let (input, (var, size)) = preceded(spaces_and_endlines, parse_indexed_array)(input)?;
+    let (input, (var, size)) = preceded(
+        spaces_and_endlines,
+        map_res(parse_indexed_array, |(var, size)| size.parse::<usize>().map(|size| (var, size)))
+    )(input)?;
+    let (input, _) = semicolon(input)?;
     if reg_kind == "creg" {
-        Ok((input, Vec::new()))
+        Ok((input, Stmt::CReg(var, size)))
     } else {
-        let size = size.parse::<u32>().unwrap();
-        let initializes: Vec<_> = (0..size).map(|i| InitExpr { dst: var.clone() + &i.to_string() }).collect();
-        Ok((input, initializes))
+        Ok((input, Stmt::QReg(var, size)))
     }
 }
 
-pub fn parse_apply(input: &str) -> IResult<&str, ApplyExpr, VerboseError<&str>> {
-    let (input, gate) = parse_gate(input)?;
-    let (input, _) = spaces_and_endlines(input)?;
-    let (input, args) = parse_arguments(input)?;
-    Ok((input, ApplyExpr { gate, args }))
+/// `gate name(params) args { body }` or `opaque name(params) args;`
+pub fn parse_gate_decl(input: &str) -> IResult<&str, GateDef, VerboseError<&str>> {
+    let (input, kind) = alt((tag("gate"), tag("opaque")))(input)?;
+    let (input, name) = preceded(spaces_and_endlines, parse_variable)(input)?;
+    let (input, params) = opt(preceded(
+        spaces_and_endlines,
+        delimited(char('('), separated_list0(comma, delimited(spaces_and_endlines, parse_variable, spaces_and_endlines)), char(')'))
+    ))(input)?;
+    let (input, args) = preceded(spaces_and_endlines, separated_list1(comma, parse_variable))(input)?;
+    let (input, body) = if kind == "gate" {
+        let (input, body) = preceded(
+            spaces_and_endlines,
+            delimited(pair(char('{'), spaces_and_endlines), parse_gate_body, char('}'))
+        )(input)?;
+        (input, Some(body))
+    } else {
+        (semicolon(input)?.0, None)
+    };
+    Ok((input, GateDef { name, params: params.unwrap_or_default(), args, body, standard: false }))
 }
 
-pub fn parse_gate(input: &str) -> IResult<&str, PrimitiveGate, VerboseError<&str>> {
-    alt((parse_gate_cx, parse_gate_rz, parse_gate_u3, parse_other_gates))(input)
+fn parse_gate_body(input: &str) -> IResult<&str, Vec<Stmt>, VerboseError<&str>> {
+    many0(terminated(
+        alt((
+            map(
+                delimited(pair(tag("barrier"), spaces_and_endlines), parse_operands, semicolon),
+                Stmt::Barrier
+            ),
+            map(terminated(parse_gate_call, semicolon), Stmt::Apply),
+        )),
+        spaces_and_endlines
+    ))(input)
 }
 
-pub fn parse_gate_cx(input: &str) -> IResult<&str, PrimitiveGate, VerboseError<&str>> {
-    let (input, _) = tag("cx")(input)?;
-    Ok((input, PrimitiveGate::CX))
+/// `name(params) args`
+pub fn parse_gate_call(input: &str) -> IResult<&str, GateCall, VerboseError<&str>> {
+    let (input, name) = parse_variable(input)?;
+    let (input, params) = opt(preceded(
+        spaces_and_endlines,
+        delimited(char('('), separated_list0(comma, parse_param_expr), char(')'))
+    ))(input)?;
+    let (input, args) = preceded(spaces_and_endlines, parse_operands)(input)?;
+    Ok((input, GateCall { name, params: params.unwrap_or_default(), args }))
 }
 
-pub fn parse_gate_rz(input: &str) -> IResult<&str, PrimitiveGate, VerboseError<&str>> {
-    let (input, _) = alt((tag("rz"), tag("u1")))(input)?;
-    let (input, theta) = delimited(tag("("), double, tag(")"))(input)?;
-    Ok((input, PrimitiveGate::Rz(theta)))
+/// Quantum operations which can be classically conditioned.
+fn parse_qop(input: &str) -> IResult<&str, Stmt, VerboseError<&str>> {
+    alt((
+        terminated(parse_measure, semicolon),
+        terminated(parse_reset, semicolon),
+        map(terminated(parse_gate_call, semicolon), Stmt::Apply),
+    ))(input)
 }
 
-pub fn parse_gate_u3(input: &str) -> IResult<&str, PrimitiveGate, VerboseError<&str>> {
-    let (input, params) = preceded(char('u'),
-                              delimited(char('('),
-                                        separated_list0(char(','), take_while(move |c: char| !",)".contains(c))), // TODO
-                                        char(')')))(input)?;
-    assert!(params.len() == 3);
-    let gate = match (params[0], params[1], params[2]) {
-        ("pi/2", "0", "pi") => PrimitiveGate::H,
-        ("pi", "0", "pi") => PrimitiveGate::X,
-        ("0", "0", "pi/4") => PrimitiveGate::T,
-        ("0", "0", "-pi/4") => PrimitiveGate::Tdg,
-        _ => unimplemented!() // TODO
-    };
-    Ok((input, gate))
+/// `if (c == n) qop`
+pub fn parse_if(input: &str) -> IResult<&str, Stmt, VerboseError<&str>> {
+    let (input, _) = pair(tag("if"), spaces_and_endlines)(input)?;
+    let (input, (creg, _, value)) = delimited(
+        pair(char('('), spaces_and_endlines),
+        tuple((parse_variable, delimited(spaces_and_endlines, tag("=="), spaces_and_endlines), map_res(digit1, str::parse::<u64>))),
+        pair(spaces_and_endlines, char(')'))
+    )(input)?;
+    let (input, stmt) = preceded(spaces_and_endlines, parse_qop)(input)?;
+    Ok((input, Stmt::If(Operand::Register(creg), value, Box::new(stmt))))
 }
 
-fn parse_other_gates(input: &str) -> IResult<&str, PrimitiveGate, VerboseError<&str>> {
-    let (input, name) = alt((
-        tag("x"),
-        tag("z"),
-        tag("h"),
-        tag("tdg"), // Be careful to the order of 'tdg' and 't'.
-        tag("t"),
-        tag("s"),
-    ))(input)?;
-    let gate = match name {
-        "x" => PrimitiveGate::X,
-        "z" => PrimitiveGate::Z,
-        "h" => PrimitiveGate::H,
-        "t" => PrimitiveGate::T,
-        "tdg" => PrimitiveGate::Tdg,
-        "s" => PrimitiveGate::S,
-        _ => unimplemented!()
-    };
-    Ok((input, gate))
+pub fn parse_reset(input: &str) -> IResult<&str, Stmt, VerboseError<&str>> {
+    let (input, _) = tag("reset")(input)?;
+    let (input, arg) = preceded(spaces_and_endlines, parse_operand)(input)?;
+    Ok((input, Stmt::Reset(arg)))
 }
 
-pub fn parse_measure(input: &str) -> IResult<&str, MeasureExpr, VerboseError<&str>> {
+pub fn parse_measure(input: &str) -> IResult<&str, Stmt, VerboseError<&str>> {
     let (input, _) = tag("measure")(input)?;
     let (input, _) = spaces_and_endlines(input)?;
-    let (input, args) = parse_arguments(input)?;
+    let (input, arg) = parse_operand(input)?;
     let (input, _) = delimited(spaces_and_endlines, tag("->"), spaces_and_endlines)(input)?;
-    let (input, dst) = parse_argument(input)?;
-    Ok((input, MeasureExpr { kind: MeasureKind::Z, dst, args }))
+    let (input, dst) = parse_operand(input)?;
+    Ok((input, Stmt::Measure(arg, Some(dst))))
 }
 
-fn parse_barrier(input: &str) -> IResult<&str, Expr, VerboseError<&str>> {
-    let (input, _) = tag("barrier")(input)?;
-    let (input, _) = spaces_and_endlines(input)?;
-    let (input, args) = parse_arguments(input)?;
+/// Arithmetic expressions for gate parameters, where `^` is the right-associative power.
+pub fn parse_param_expr(input: &str) -> IResult<&str, ParamExpr, VerboseError<&str>> {
+    let (input, first) = delimited(spaces_and_endlines, parse_param_term, spaces_and_endlines)(input)?;
+    let (input, rest) = many0(pair(
+        one_of("+-"),
+        delimited(spaces_and_endlines, parse_param_term, spaces_and_endlines)
+    ))(input)?;
+    Ok((input, rest.into_iter().fold(first, |l, (op, r)| ParamExpr::BinOp(op, Box::new(l), Box::new(r)))))
+}
 
-    Ok((input, Expr::Barrier(BarrierExpr { args })))
+fn parse_param_term(input: &str) -> IResult<&str, ParamExpr, VerboseError<&str>> {
+    let (input, first) = parse_param_unary(input)?;
+    let (input, rest) = many0(pair(
+        delimited(spaces_and_endlines, one_of("*/"), spaces_and_endlines),
+        parse_param_unary
+    ))(input)?;
+    Ok((input, rest.into_iter().fold(first, |l, (op, r)| ParamExpr::BinOp(op, Box::new(l), Box::new(r)))))
 }
 
-pub fn parse_argument(input: &str) -> IResult<&str, String, VerboseError<&str>> {
-    let (input, arg) = alt((
-        // Remark: in this order
-        map_res(parse_indexed_array, |(var, idx)| -> Result<String> { Ok(var + &idx) }),
-        parse_variable
+fn parse_param_power(input: &str) -> IResult<&str, ParamExpr, VerboseError<&str>> {
+    let (input, base) = parse_param_primary(input)?;
+    let (input, exp) = opt(preceded(
+        delimited(spaces_and_endlines, char('^'), spaces_and_endlines),
+        parse_param_unary
     ))(input)?;
-    Ok((input, arg))
+    match exp {
+        Some(exp) => Ok((input, ParamExpr::BinOp('^', Box::new(base), Box::new(exp)))),
+        None => Ok((input, base)),
+    }
 }
-pub fn parse_arguments(input: &str) -> IResult<&str, Vec<String>, VerboseError<&str>> {
-    let (input, args) = separated_list1(char(','), parse_argument)(input)?;
-    Ok((input, args))
+
+fn parse_param_unary(input: &str) -> IResult<&str, ParamExpr, VerboseError<&str>> {
+    alt((
+        map(preceded(pair(char('-'), spaces_and_endlines), parse_param_unary), |e| ParamExpr::Neg(Box::new(e))),
+        preceded(pair(char('+'), spaces_and_endlines), parse_param_unary),
+        parse_param_power,
+    ))(input)
+}
+
+fn parse_param_primary(input: &str) -> IResult<&str, ParamExpr, VerboseError<&str>> {
+    alt((
+        map(preceded(peek(one_of("0123456789.")), double), ParamExpr::Num),
+        delimited(char('('), parse_param_expr, char(')')),
+        map(
+            pair(parse_variable, opt(preceded(spaces_and_endlines, delimited(char('('), parse_param_expr, char(')'))))),
+            |(name, arg)| match arg {
+                Some(arg) => ParamExpr::Call(name, Box::new(arg)),
+                None if name == "pi" => ParamExpr::Pi,
+                None => ParamExpr::Var(name),
+            }
+        ),
+    ))(input)
+}
+
+pub fn parse_operand(input: &str) -> IResult<&str, Operand, VerboseError<&str>> {
+    alt((
+        // Remark: in this order
+        map_res(parse_indexed_array, |(var, idx)| -> Result<Operand, std::num::ParseIntError> {
+            Ok(Operand::Indexed(var, idx.parse()?))
+        }),
+        map(parse_variable, Operand::Register),
+    ))(input)
+}
+
+pub fn parse_operands(input: &str) -> IResult<&str, Vec<Operand>, VerboseError<&str>> {
+    separated_list1(comma, parse_operand)(input)
 }
 
 pub fn parse_variable(input: &str) -> IResult<&str, String, VerboseError<&str>> {
-    let (input, (pre, tail)) = tuple((alpha1, alphanumeric0))(input)?;
-    Ok((input, String::from(pre) + tail))
+    let (input, var) = recognize(pair(
+        alt((alpha1, tag("_"))),
+        many0(alt((alphanumeric1, tag("_"))))
+    ))(input)?;
+    Ok((input, var.to_string()))
 }
 
 pub fn parse_indexed_array(input: &str) -> IResult<&str, (String, String), VerboseError<&str>> {
     let (input, var) = parse_variable(input)?;
-    let (input, idx) = delimited(char('['), alphanumeric0, char(']'))(input)?;
+    let (input, idx) = delimited(
+        pair(spaces_and_endlines, char('[')),
+        delimited(spaces_and_endlines, digit1, spaces_and_endlines),
+        char(']')
+    )(input)?;
     Ok((input, (var, idx.to_string())))
 }
 
+pub(crate) fn comma(input: &str) -> IResult<&str, char, VerboseError<&str>> {
+    delimited(spaces_and_endlines, char(','), spaces_and_endlines)(input)
+}
 
+pub(crate) fn semicolon(input: &str) -> IResult<&str, char, VerboseError<&str>> {
+    preceded(spaces_and_endlines, char(';'))(input)
+}
+
+/// Skips whitespaces and comments.
 pub fn spaces_and_endlines(input: &str) -> IResult<&str, (), VerboseError<&str>> {
     let skipped = " \t\r\n";
-    let (input, _) = take_while(move |c: char| skipped.contains(c))(input)?;
-    Ok((input, ()))
+    let mut input = input;
+    loop {
+        let (rest, _) = take_while(move |c: char| skipped.contains(c))(input)?;
+        input = rest;
+        if let Some(rest) = input.strip_prefix("//") {
+            input = rest.find('\n').map_or("", |i| &rest[i..]);
+        } else if let Some(rest) = input.strip_prefix("/*") {
+            input = rest.find("*/").map_or("", |i| &rest[i + 2..]);
+        } else {
+            return Ok((input, ()));
+        }
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::hir::PrimitiveGate;
+    use crate::hir::{ApplyExpr, InitExpr, MeasureExpr, MeasureKind, PrimitiveGate, ResetExpr, IfExpr};
+    use crate::hir::ast::BarrierExpr;
+    use crate::parser::diagnostic::Diagnostic;
+
+    fn parse_body(body: &str) -> Vec<Expr> {
+        let input = format!("OPENQASM 2.0;\ninclude \"qelib1.inc\";\n{}", body);
+        parse(&input).unwrap()
+    }
+
+    fn apply(gate: PrimitiveGate, args: &[&str]) -> Expr {
+        Expr::from(ApplyExpr { gate, args: args.iter().map(|x| x.to_string()).collect() })
+    }
 
     #[test]
     fn parse_indexed_array_test() {
@@ -225,55 +374,174 @@ mod tests {
 
     #[test]
     fn parse_qreg_decl_test() {
-        let input = "qreg q[2]";
-        let (_, decls) = parse_reg_decl(input).unwrap();
-        assert_eq!(decls.len(), 2);
-        assert_eq!(decls[0].dst, "q0");
-        assert_eq!(decls[1].dst, "q1");
+        let input = "qreg q[2];";
+        let (_, decl) = parse_reg_decl(input).unwrap();
+        assert_eq!(decl, Stmt::QReg("q".to_string(), 2));
+        let exps = parse_body(input);
+        assert_eq!(exps, vec![
+            Expr::from(InitExpr { dst: "q0".to_string() }),
+            Expr::from(InitExpr { dst: "q1".to_string() }),
+        ]);
     }
 
     macro_rules! check_gate {
         ($input:expr, $expected:expr) => {
-            let (_, gate) = parse_gate($input).unwrap();
-            assert_eq!(gate, $expected);
+            let exps = parse_body(&format!("qreg q[2];\n{};", $input));
+            assert_eq!(exps[2..], $expected);
         }
     }
 
     #[test]
     fn parse_gates() {
-        check_gate!("u(pi/2,0,pi)", PrimitiveGate::H);
-        check_gate!("u(0,0,pi/4)", PrimitiveGate::T);
-        check_gate!("u(0,0,-pi/4)", PrimitiveGate::Tdg);
-        check_gate!("u(pi,0,pi)", PrimitiveGate::X);
-        check_gate!("cx", PrimitiveGate::CX);
-        check_gate!("tdg", PrimitiveGate::Tdg);
+        use PrimitiveGate::*;
+        check_gate!("u(pi/2,0,pi) q[0]", [apply(H, &["q0"])]);
+        check_gate!("u(0,0,pi/4) q[0]", [apply(T, &["q0"])]);
+        check_gate!("u(0,0,-pi/4) q[0]", [apply(Tdg, &["q0"])]);
+        check_gate!("u(pi,0,pi) q[0]", [apply(X, &["q0"])]);
+        check_gate!("cx q[0],q[1]", [apply(CX, &["q0", "q1"])]);
+        check_gate!("tdg q[0]", [apply(Tdg, &["q0"])]);
+        check_gate!("y q[0]", [apply(Y, &["q0"])]);
+        check_gate!("sdg q[0]", [apply(S, &["q0"]), apply(Z, &["q0"])]);
+        check_gate!("u1(2*pi - pi/4) q[0]", [apply(Tdg, &["q0"])]);
+        check_gate!("id q[0]", []);
+        check_gate!("cz q[0],q[1]", [apply(H, &["q1"]), apply(CX, &["q0", "q1"]), apply(H, &["q1"])]);
+        check_gate!("swap q[0],q[1]", [apply(CX, &["q0", "q1"]), apply(CX, &["q1", "q0"]), apply(CX, &["q0", "q1"])]);
     }
 
     #[test]
     fn parse_rz_test() {
-        let input = "rz(-0.15)";
-        let (_, gate) = parse_gate(input).unwrap();
-        match gate {
-            PrimitiveGate::Rz(theta) => {
-                assert!((theta - (-0.15)).abs() < 1e9);
+        let exps = parse_body("qreg q[1];\nrz(-0.15) q[0];");
+        match &exps[1] {
+            Expr::Apply(ApplyExpr { gate: PrimitiveGate::Rz(theta), .. }) => {
+                assert!((theta - (-0.15)).abs() < 1e-9);
             },
-            _ => assert!(false)
+            _ => panic!("expected an Rz gate")
         }
     }
 
+    #[test]
+    fn parse_param_expr_test() {
+        let env = Default::default();
+        let eval = |input: &str| elaborate::eval(&parse_param_expr(input).unwrap().1, &env).unwrap();
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("-2^2"), -4.0);
+        assert_eq!(eval("2^3^2"), 512.0);
+        assert_eq!(eval("2^-1"), 0.5);
+        assert_eq!(eval("(1 - 2) - 3"), -4.0);
+        assert_eq!(eval("sqrt(4.0) / 1e1"), 0.2);
+        assert!((eval("-2*pi/3") + 2.0 * std::f64::consts::PI / 3.0).abs() < 1e-12);
+        assert!(eval("cos(pi)") + 1.0 < 1e-12);
+    }
+
     #[test]
     fn parse_measure_test() {
         let input = "measure q[0] -> c[0]";
         let (_, e) = parse_measure(input).unwrap();
-        assert_eq!(e.args.len(), 1);
-        assert_eq!(e.dst, "c0");
-        assert_eq!(e.args[0], "q0");
+        assert_eq!(e, Stmt::Measure(Operand::Indexed("q".to_string(), 0), Some(Operand::Indexed("c".to_string(), 0))));
+        let exps = parse_body("qreg q[2];\ncreg c[2];\nmeasure q -> c;");
+        assert_eq!(exps[2..], [
+            Expr::from(MeasureExpr { kind: MeasureKind::Z, dst: "c0".to_string(), args: vec!["q0".to_string()] }),
+            Expr::from(MeasureExpr { kind: MeasureKind::Z, dst: "c1".to_string(), args: vec!["q1".to_string()] }),
+        ]);
     }
 
     #[test]
     fn parse_header_test() {
         let input = "OPENQASM 2.0;\ninclude \"qelib1.inc\";\n";
         let (input, _) = parse_header(input).unwrap();
-        assert_eq!(input, "");
+        let (input, stmts) = parse_include(input).unwrap();
+        assert_eq!(input, "\n");
+        assert!(stmts.iter().any(|stmt| matches!(stmt, Stmt::Gate(GateDef { name, .. }) if name == "ccx")));
+        assert!(parse_include("include \"other.inc\";").is_err());
+    }
+
+    #[test]
+    fn standard_toffoli() {
+        use PrimitiveGate::*;
+        let apply = |gate, args: &[&str]| Expr::from(ApplyExpr { gate, args: args.iter().map(|s| s.to_string()).collect() });
+        let exps = parse_body("qreg q[3];\nccx q[0], q[1], q[2];\ncswap q[0], q[1], q[2];");
+        assert_eq!(exps[3..], [
+            apply(CCX, &["q0", "q1", "q2"]),
+            apply(CX, &["q2", "q1"]),
+            apply(CCX, &["q0", "q1", "q2"]),
+            apply(CX, &["q2", "q1"]),
+        ]);
+        // a Toffoli defined by the user is not recognized
+        let exps = parse("OPENQASM 2.0;\ngate ccx a, b, c { CX a, c; CX b, c; }\nqreg q[3];\nccx q[0], q[1], q[2];").unwrap();
+        assert_eq!(exps[3..], [apply(CX, &["q0", "q2"]), apply(CX, &["q1", "q2"])]);
+    }
+
+    #[test]
+    fn gate_definitions_and_broadcast() {
+        let exps = parse_body("// user-defined gates
+gate foo(theta) a, b {
+  rz(theta/2) a;
+  barrier a, b;
+  cx a, b;
+}
+opaque bar a;
+qreg q[2];
+qreg r[2];
+foo(pi/2) q, r;
+h q;
+");
+        use PrimitiveGate::*;
+        assert_eq!(exps[4..], [
+            apply(T, &["q0"]),
+            Expr::Barrier(BarrierExpr { args: vec!["q0".to_string(), "r0".to_string()] }),
+            apply(CX, &["q0", "r0"]),
+            apply(T, &["q1"]),
+            Expr::Barrier(BarrierExpr { args: vec!["q1".to_string(), "r1".to_string()] }),
+            apply(CX, &["q1", "r1"]),
+            apply(H, &["q0"]),
+            apply(H, &["q1"]),
+        ]);
+
+        let input = "OPENQASM 2.0;\nopaque bar a;\nqreg q[1];\nbar q[0];";
+        assert!(matches!(parse(input), Err(Located { error: Error::Elaborate(elaborate::Error::OpaqueGate(_)), .. })));
+        let input = "OPENQASM 2.0;\nqreg q[1];\nh q[0];";
+        assert!(matches!(parse(input), Err(Located { error: Error::Elaborate(elaborate::Error::UndefinedGate(_)), .. })));
+        let input = "OPENQASM 2.0;\nqreg q[1];\nqreg r[2];\nCX q, r;";
+        assert!(matches!(parse(input), Err(Located { error: Error::Elaborate(elaborate::Error::SizeMismatch(_)), .. })));
+    }
+
+    #[test]
+    fn reset_and_if() {
+        let (_, stmt) = parse_if("if (c == 2) measure q[0] -> c[1];").unwrap();
+        assert_eq!(stmt, Stmt::If(Operand::Register("c".to_string()), 2, Box::new(
+            Stmt::Measure(Operand::Indexed("q".to_string(), 0), Some(Operand::Indexed("c".to_string(), 1)))
+        )));
+
+        let exps = parse_body("qreg q[2];\ncreg c[2];\nreset q;\nif(c==1) cx q[0],q[1];");
+        let bits = vec!["c0".to_string(), "c1".to_string()];
+        assert_eq!(exps[2..], [
+            Expr::from(ResetExpr { arg: "q0".to_string() }),
+            Expr::from(ResetExpr { arg: "q1".to_string() }),
+            Expr::from(IfExpr { bits, value: 1, body: Box::new(apply(PrimitiveGate::CX, &["q0", "q1"])) }),
+        ]);
+
+        let input = "OPENQASM 2.0;\nqreg q[1];\ncreg c[1];\nif(c==1) reset q[0];";
+        assert!(matches!(parse(input), Err(Located { error: Error::Elaborate(elaborate::Error::UnsupportedCondition(_)), .. })));
+    }
+
+    #[test]
+    fn error_positions() {
+        let at = |input: &str| {
+            let err = parse(input).unwrap_err();
+            let d = Diagnostic::new("a.qasm", input, &err);
+            (d.line, d.column, d.message)
+        };
+        assert_eq!(at("OPENQASM 2.0;\nqreg q[2];\nCX q[0] q[1];\n"), (3, 9, "expected `;`, found `q[1];`".to_string()));
+        assert_eq!(at("OPENQASM 2.0;\nqreg q[1];\n  U(0, 0, 0) r[0];\n"), (3, 3, "undefined register `r`".to_string()));
+        assert_eq!(at("qreg q[1];"), (1, 1, "expected `OPENQASM 2.0;`".to_string()));
+        let message = |q: &str| format!("qubit `{}` is used multiple times in a gate application", q);
+        assert_eq!(at("OPENQASM 2.0;\nqreg q[2];\n CX q[1], q[1];\n"), (3, 2, message("q[1]")));
+        assert_eq!(at("OPENQASM 2.0;\nqreg q[2];\nCX q, q;\n"), (3, 1, message("q[0]")));
+        assert_eq!(at("OPENQASM 2.0;\nqreg q[2];\nCX q, q[1];\n"), (3, 1, message("q[1]")));
+        assert_eq!(at("OPENQASM 2.0;\ngate g a, b { CX a, a; }\n"), (2, 1, message("a")));
+        assert_eq!(at("OPENQASM 2.0;\nqreg q[1];\nU(foo(1), 0, 0) q[0];\n"), (3, 1, "undefined function `foo`".to_string()));
+        let non_finite = "a parameter evaluates to a non-finite number".to_string();
+        assert_eq!(at("OPENQASM 2.0;\nqreg q[1];\nU(1/0, 0, 0) q[0];\n"), (3, 1, non_finite.clone()));
+        assert_eq!(at("OPENQASM 2.0;\ngate g(t) a { U(ln(t), 0, 0) a; }\nqreg q[1];\ng(-1) q[0];\n"), (4, 1, non_finite));
     }
 }
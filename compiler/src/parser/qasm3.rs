@@ -0,0 +1,556 @@
+use crate::hir::ast::Expr;
+use crate::parser::diagnostic::{Located, syntax_error};
+use crate::parser::elaborate::{self, Elaborator, ParamExpr};
+use crate::parser::qasm2::{
+    self,
+    spaces_and_endlines,
+    parse_variable,
+    parse_param_expr,
+    comma,
+    semicolon,
+};
+
+use nom::{
+    IResult,
+    branch::alt,
+    bytes::complete::{tag, take_until},
+    character::complete::{char, digit1},
+    combinator::{opt, map, map_res},
+    error::{VerboseError, VerboseErrorKind},
+    multi::{many0, separated_list0, separated_list1},
+    sequence::{tuple, pair, delimited, preceded, terminated},
+};
+use std::collections::HashMap;
+use std::fmt;
+
+/// The standard gate library of OpenQASM 3.
+const STDGATES: &str = include_str!("stdgates.inc");
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Syntax(String),
+    Elaborate(elaborate::Error),
+    /// An index, a condition or a loop range is not a non-negative integer.
+    NotInteger(f64),
+    /// `else` and conditions without `==` need a single bit.
+    NotBit(String),
+    /// The step of a loop range is zero.
+    ZeroStep,
+}
+
+impl From<elaborate::Error> for Error {
+    fn from(err: elaborate::Error) -> Self {
+        Error::Elaborate(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Syntax(msg) => write!(f, "{}", msg),
+            Error::Elaborate(e) => write!(f, "{}", e),
+            Error::NotInteger(x) => write!(f, "expected a non-negative integer, found {}", x),
+            Error::NotBit(name) => write!(f, "`{}` is not a single bit", name),
+            Error::ZeroStep => write!(f, "the step of a range must not be zero"),
+        }
+    }
+}
+
+fn syntax(err: nom::Err<VerboseError<&str>>) -> Located<Error> {
+    let Located { pos, error } = syntax_error(err);
+    Located::new(pos, Error::Syntax(error))
+}
+
+/// An operand whose index can refer to loop variables.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Register(String),
+    Indexed(String, ParamExpr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// `c == n`
+    Eq(Operand, ParamExpr),
+    /// `c` or `!c` for a single bit `c`.
+    Bit(Operand, bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Range {
+    /// `[start:stop]` or `[start:step:stop]`, where `stop` is inclusive.
+    Slice(ParamExpr, Option<ParamExpr>, ParamExpr),
+    /// `{a, b, ..}`
+    Set(Vec<ParamExpr>),
+}
+
+/// Statements before loops are unrolled.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    /// OpenQASM 2 declarations and gate definitions, which do not refer to constants.
+    Plain(elaborate::Stmt),
+    /// `qubit[n] q;`
+    Qubit(String, ParamExpr),
+    /// `bit[n] c;`
+    Bit(String, ParamExpr),
+    /// `const int n = e;`
+    Const(String, ParamExpr),
+    Apply(String, Vec<ParamExpr>, Vec<Operand>),
+    /// `measure q -> c;`, `c = measure q;` or `measure q;`
+    Measure(Operand, Option<Operand>),
+    Reset(Operand),
+    Barrier(Vec<Operand>),
+    /// `if (cond) { .. } else { .. }`
+    If(Condition, Vec<(usize, Stmt)>, Vec<(usize, Stmt)>),
+    /// `for int i in range { .. }`
+    For(String, Range, Vec<(usize, Stmt)>),
+}
+
+pub fn parse(input: &str) -> Result<Vec<Expr>, Located<Error>> {
+    let (input, _) = parse_header(input).map_err(syntax)?;
+    let (rest, stmts) = parse_stmts(input).map_err(syntax)?;
+    if !rest.is_empty() {
+        // the reason why the next statement cannot be parsed
+        return Err(match parse_stmt(rest) {
+            Err(err) => syntax(err),
+            Ok(_) => Located::new(rest.len(), Error::Syntax("unexpected input".to_string())),
+        });
+    }
+    let mut unrolled = Vec::new();
+    Unroller::default().unroll(stmts, &HashMap::new(), &mut unrolled)?;
+    let mut elaborator = Elaborator::new();
+    let mut exps = Vec::new();
+    for (pos, stmt) in unrolled {
+        elaborator.elaborate_stmt(stmt, &mut exps).map_err(|e| Located::new(pos, Error::Elaborate(e)))?;
+    }
+    Ok(exps)
+}
+
+/// The version declaration is optional in OpenQASM 3.
+pub fn parse_header(input: &str) -> IResult<&str, (), VerboseError<&str>> {
+    let (input, _) = spaces_and_endlines(input)?;
+    let (input, _) = opt(tuple((
+        tag("OPENQASM"),
+        spaces_and_endlines,
+        alt((tag("3.0"), tag("3"))),
+        semicolon,
+    )))(input)?;
+    let (input, _) = spaces_and_endlines(input)?;
+    Ok((input, ()))
+}
+
+/// Statements with their positions as in `qasm2::parse_stmts`.
+fn parse_stmts(input: &str) -> IResult<&str, Vec<(usize, Stmt)>, VerboseError<&str>> {
+    let (input, stmts) = many0(terminated(parse_located_stmt, spaces_and_endlines))(input)?;
+    Ok((input, stmts.concat()))
+}
+
+fn parse_located_stmt(input: &str) -> IResult<&str, Vec<(usize, Stmt)>, VerboseError<&str>> {
+    let pos = input.len();
+    let (input, stmts) = parse_stmt(input)?;
+    Ok((input, stmts.into_iter().map(|stmt| (pos, stmt)).collect()))
+}
+
+/// `{ stmts }` or a single statement.
+fn parse_block(input: &str) -> IResult<&str, Vec<(usize, Stmt)>, VerboseError<&str>> {
+    alt((
+        delimited(pair(char('{'), spaces_and_endlines), parse_stmts, char('}')),
+        parse_located_stmt,
+    ))(input)
+}
+
+fn parse_stmt(input: &str) -> IResult<&str, Vec<Stmt>, VerboseError<&str>> {
+    let (rest, keyword) = parse_variable(input)?;
+    let single = |stmt| vec![stmt];
+    match keyword.as_str() {
+        "include" => parse_include(input),
+        "qubit" | "bit" => map(parse_decl, single)(input),
+        "qreg" | "creg" => map(qasm2::parse_reg_decl, |decl| vec![Stmt::Plain(decl)])(input),
+        "gate" => map(qasm2::parse_gate_decl, |def| vec![Stmt::Plain(elaborate::Stmt::Gate(def))])(input),
+        "const" => map(parse_const, single)(input),
+        "measure" => map(terminated(parse_measure, semicolon), single)(input),
+        "reset" => {
+            let (input, arg) = delimited(spaces_and_endlines, parse_operand, semicolon)(rest)?;
+            Ok((input, vec![Stmt::Reset(arg)]))
+        },
+        "barrier" => {
+            let (input, args) = delimited(spaces_and_endlines, separated_list0(comma, parse_operand), semicolon)(rest)?;
+            Ok((input, vec![Stmt::Barrier(args)]))
+        },
+        "if" => map(parse_if, single)(input),
+        "for" => map(parse_for, single)(input),
+        _ => map(alt((parse_measure_assign, parse_gate_call)), single)(input),
+    }
+}
+
+/// `stdgates.inc` is replaced with the standard gate definitions.
+fn parse_include(input: &str) -> IResult<&str, Vec<Stmt>, VerboseError<&str>> {
+    let (input, _) = pair(tag("include"), spaces_and_endlines)(input)?;
+    let (rest, file) = delimited(char('"'), take_until("\""), char('"'))(input)?;
+    let (rest, _) = semicolon(rest)?;
+    if file != "stdgates.inc" {
+        let errors = vec![(input, VerboseErrorKind::Context("unknown include file"))];
+        return Err(nom::Err::Failure(VerboseError { errors }));
+    }
+    Ok((rest, qasm2::standard_library(STDGATES).into_iter().map(Stmt::Plain).collect()))
+}
+
+/// `qubit[n] q;` or `bit c;`
+pub fn parse_decl(input: &str) -> IResult<&str, Stmt, VerboseError<&str>> {
+    let (input, kind) = alt((tag("qubit"), tag("bit")))(input)?;
+    let (input, size) = opt(preceded(
+        spaces_and_endlines,
+        delimited(char('['), parse_param_expr, char(']'))
+    ))(input)?;
+    let (input, name) = delimited(spaces_and_endlines, parse_variable, semicolon)(input)?;
+    let size = size.unwrap_or(ParamExpr::Num(1.0));
+    if kind == "qubit" {
+        Ok((input, Stmt::Qubit(name, size)))
+    } else {
+        Ok((input, Stmt::Bit(name, size)))
+    }
+}
+
+/// `const int n = e;`, where the type is ignored.
+fn parse_const(input: &str) -> IResult<&str, Stmt, VerboseError<&str>> {
+    let (input, _) = pair(tag("const"), spaces_and_endlines)(input)?;
+    let (input, _) = parse_type(input)?;
+    let (input, name) = parse_variable(input)?;
+    let (input, value) = preceded(pair(spaces_and_endlines, char('=')), parse_param_expr)(input)?;
+    let (input, _) = semicolon(input)?;
+    Ok((input, Stmt::Const(name, value)))
+}
+
+/// Classical types such as `int`, `uint[32]` and `float[64]`.
+fn parse_type(input: &str) -> IResult<&str, (), VerboseError<&str>> {
+    let (input, _) = alt((tag("uint"), tag("int"), tag("float"), tag("angle")))(input)?;
+    let (input, _) = opt(preceded(
+        spaces_and_endlines,
+        delimited(char('['), delimited(spaces_and_endlines, digit1, spaces_and_endlines), char(']'))
+    ))(input)?;
+    let (input, _) = spaces_and_endlines(input)?;
+    Ok((input, ()))
+}
+
+fn parse_gate_call(input: &str) -> IResult<&str, Stmt, VerboseError<&str>> {
+    let (input, name) = parse_variable(input)?;
+    let (input, params) = opt(preceded(
+        spaces_and_endlines,
+        delimited(char('('), separated_list0(comma, parse_param_expr), char(')'))
+    ))(input)?;
+    let (input, args) = preceded(spaces_and_endlines, separated_list1(comma, parse_operand))(input)?;
+    let (input, _) = semicolon(input)?;
+    Ok((input, Stmt::Apply(name, params.unwrap_or_default(), args)))
+}
+
+/// `measure q -> c`, or `measure q` discarding the outcome
+fn parse_measure(input: &str) -> IResult<&str, Stmt, VerboseError<&str>> {
+    let (input, _) = pair(tag("measure"), spaces_and_endlines)(input)?;
+    let (input, arg) = parse_operand(input)?;
+    let (input, dst) = opt(preceded(
+        delimited(spaces_and_endlines, tag("->"), spaces_and_endlines),
+        parse_operand
+    ))(input)?;
+    Ok((input, Stmt::Measure(arg, dst)))
+}
+
+/// `c = measure q;`
+fn parse_measure_assign(input: &str) -> IResult<&str, Stmt, VerboseError<&str>> {
+    let (input, dst) = parse_operand(input)?;
+    let (input, _) = tuple((spaces_and_endlines, char('='), spaces_and_endlines, tag("measure"), spaces_and_endlines))(input)?;
+    let (input, arg) = parse_operand(input)?;
+    let (input, _) = semicolon(input)?;
+    Ok((input, Stmt::Measure(arg, Some(dst))))
+}
+
+/// `if (cond) block` optionally followed by `else block`.
+pub fn parse_if(input: &str) -> IResult<&str, Stmt, VerboseError<&str>> {
+    let (input, _) = pair(tag("if"), spaces_and_endlines)(input)?;
+    let (input, cond) = delimited(
+        pair(char('('), spaces_and_endlines),
+        parse_condition,
+        pair(spaces_and_endlines, char(')'))
+    )(input)?;
+    let (input, then) = preceded(spaces_and_endlines, parse_block)(input)?;
+    let (input, els) = opt(preceded(
+        tuple((spaces_and_endlines, tag("else"), spaces_and_endlines)),
+        parse_block
+    ))(input)?;
+    Ok((input, Stmt::If(cond, then, els.unwrap_or_default())))
+}
+
+fn parse_condition(input: &str) -> IResult<&str, Condition, VerboseError<&str>> {
+    alt((
+        map(
+            tuple((parse_operand, delimited(spaces_and_endlines, tag("=="), spaces_and_endlines), parse_param_expr)),
+            |(c, _, value)| Condition::Eq(c, value)
+        ),
+        map(preceded(pair(char('!'), spaces_and_endlines), parse_operand), |c| Condition::Bit(c, false)),
+        map(parse_operand, |c| Condition::Bit(c, true)),
+    ))(input)
+}
+
+/// `for int i in [a:b] block`, where the type of the loop variable is optional.
+pub fn parse_for(input: &str) -> IResult<&str, Stmt, VerboseError<&str>> {
+    let (input, _) = pair(tag("for"), spaces_and_endlines)(input)?;
+    let (input, _) = opt(parse_type)(input)?;
+    let (input, var) = parse_variable(input)?;
+    let (input, _) = tuple((spaces_and_endlines, tag("in"), spaces_and_endlines))(input)?;
+    let (input, range) = alt((
+        map_res(
+            delimited(char('['), separated_list1(char(':'), parse_param_expr), char(']')),
+            |mut es| match es.len() {
+                2 => Ok(Range::Slice(es.remove(0), None, es.remove(0))),
+                3 => Ok(Range::Slice(es.remove(0), Some(es.remove(0)), es.remove(0))),
+                _ => Err("a range needs two or three components"),
+            }
+        ),
+        map(delimited(char('{'), separated_list1(comma, parse_param_expr), char('}')), Range::Set),
+    ))(input)?;
+    let (input, body) = preceded(spaces_and_endlines, parse_block)(input)?;
+    Ok((input, Stmt::For(var, range, body)))
+}
+
+/// `q` or `q[e]`
+pub fn parse_operand(input: &str) -> IResult<&str, Operand, VerboseError<&str>> {
+    let (input, name) = parse_variable(input)?;
+    let (input, idx) = opt(preceded(
+        spaces_and_endlines,
+        delimited(char('['), parse_param_expr, char(']'))
+    ))(input)?;
+    match idx {
+        Some(idx) => Ok((input, Operand::Indexed(name, idx))),
+        None => Ok((input, Operand::Register(name))),
+    }
+}
+
+/// Unrolls loops and substitutes loop variables and constants.
+#[derive(Default)]
+struct Unroller {
+    cregs: HashMap<String, usize>,
+}
+
+impl Unroller {
+    fn unroll(
+        &mut self,
+        stmts: Vec<(usize, Stmt)>,
+        env: &HashMap<String, f64>,
+        res: &mut Vec<(usize, elaborate::Stmt)>
+    ) -> Result<(), Located<Error>> {
+        let mut env = env.clone();
+        for (pos, stmt) in stmts {
+            let at = |e: Error| Located::new(pos, e);
+            let eval = |e: &ParamExpr, env: &HashMap<String, f64>| elaborate::eval(e, env).map_err(|e| at(e.into()));
+            match stmt {
+                Stmt::Plain(stmt) => {
+                    if let elaborate::Stmt::CReg(name, size) = &stmt {
+                        self.cregs.insert(name.clone(), *size);
+                    }
+                    res.push((pos, stmt));
+                },
+                Stmt::Qubit(name, size) => {
+                    let size = integer(eval(&size, &env)?).map_err(at)?;
+                    res.push((pos, elaborate::Stmt::QReg(name, size)));
+                },
+                Stmt::Bit(name, size) => {
+                    let size = integer(eval(&size, &env)?).map_err(at)?;
+                    self.cregs.insert(name.clone(), size);
+                    res.push((pos, elaborate::Stmt::CReg(name, size)));
+                },
+                Stmt::Const(name, value) => {
+                    let value = eval(&value, &env)?;
+                    env.insert(name, value);
+                },
+                Stmt::Apply(name, params, args) => {
+                    let params = params.iter().map(|e| eval(e, &env).map(ParamExpr::Num)).collect::<Result<_, _>>()?;
+                    let args = args.iter().map(|arg| operand(arg, &env)).collect::<Result<_, _>>().map_err(at)?;
+                    res.push((pos, elaborate::Stmt::Apply(elaborate::GateCall { name, params, args })));
+                },
+                Stmt::Measure(arg, dst) => {
+                    let arg = operand(&arg, &env).map_err(at)?;
+                    let dst = dst.map(|dst| operand(&dst, &env)).transpose().map_err(at)?;
+                    res.push((pos, elaborate::Stmt::Measure(arg, dst)));
+                },
+                Stmt::Reset(arg) => res.push((pos, elaborate::Stmt::Reset(operand(&arg, &env).map_err(at)?))),
+                Stmt::Barrier(args) => {
+                    let args = args.iter().map(|arg| operand(arg, &env)).collect::<Result<_, _>>().map_err(at)?;
+                    res.push((pos, elaborate::Stmt::Barrier(args)));
+                },
+                Stmt::If(cond, then, els) => {
+                    let (c, value) = match cond {
+                        Condition::Eq(c, value) => {
+                            let value = integer(eval(&value, &env)?).map_err(at)? as u64;
+                            (operand(&c, &env).map_err(at)?, value)
+                        },
+                        Condition::Bit(c, positive) => {
+                            let c = operand(&c, &env).and_then(|c| self.bit(c)).map_err(at)?;
+                            (c, positive as u64)
+                        },
+                    };
+                    let mut body = Vec::new();
+                    self.unroll(then, &env, &mut body)?;
+                    res.extend(body.into_iter().map(|(pos, stmt)| (pos, elaborate::Stmt::If(c.clone(), value, Box::new(stmt)))));
+                    if !els.is_empty() {
+                        let c = self.bit(c).map_err(at)?;
+                        let mut body = Vec::new();
+                        self.unroll(els, &env, &mut body)?;
+                        match value {
+                            0 | 1 => res.extend(body.into_iter().map(|(pos, stmt)| (pos, elaborate::Stmt::If(c.clone(), 1 - value, Box::new(stmt))))),
+                            _ => res.extend(body),
+                        }
+                    }
+                },
+                Stmt::For(var, range, body) => {
+                    let values = match range {
+                        Range::Slice(start, step, stop) => {
+                            let start = integer(eval(&start, &env)?).map_err(at)? as i64;
+                            let step = match step {
+                                Some(step) => eval(&step, &env)?,
+                                None => 1.0,
+                            };
+                            if step.fract() != 0.0 {
+                                return Err(at(Error::NotInteger(step)));
+                            } else if step == 0.0 {
+                                return Err(at(Error::ZeroStep));
+                            }
+                            let stop = eval(&stop, &env)?;
+                            let step = step as i64;
+                            let count = ((stop - start as f64) / step as f64).floor() as i64 + 1;
+                            (0..count.max(0)).map(|i| (start + i * step) as f64).collect()
+                        },
+                        Range::Set(es) => es.iter().map(|e| eval(e, &env)).collect::<Result<Vec<_>, _>>()?,
+                    };
+                    for value in values {
+                        let mut env = env.clone();
+                        env.insert(var.clone(), value);
+                        self.unroll(body.clone(), &env, res)?;
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `c` is a single bit.
+    fn bit(&self, c: elaborate::Operand) -> Result<elaborate::Operand, Error> {
+        match &c {
+            elaborate::Operand::Register(name) if self.cregs.get(name) != Some(&1) => Err(Error::NotBit(name.clone())),
+            _ => Ok(c),
+        }
+    }
+}
+
+fn operand(arg: &Operand, env: &HashMap<String, f64>) -> Result<elaborate::Operand, Error> {
+    match arg {
+        Operand::Register(name) => Ok(elaborate::Operand::Register(name.clone())),
+        Operand::Indexed(name, idx) => {
+            let idx = integer(elaborate::eval(idx, env)?)?;
+            Ok(elaborate::Operand::Indexed(name.clone(), idx))
+        },
+    }
+}
+
+fn integer(x: f64) -> Result<usize, Error> {
+    if x.fract() == 0.0 && x >= 0.0 {
+        Ok(x as usize)
+    } else {
+        Err(Error::NotInteger(x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hir::{ApplyExpr, InitExpr, MeasureExpr, MeasureKind, PrimitiveGate, ResetExpr, IfExpr};
+
+    fn apply(gate: PrimitiveGate, args: &[&str]) -> Expr {
+        Expr::from(ApplyExpr { gate, args: args.iter().map(|x| x.to_string()).collect() })
+    }
+
+    fn measure(q: &str, c: &str) -> Expr {
+        Expr::from(MeasureExpr { kind: MeasureKind::Z, dst: c.to_string(), args: vec![q.to_string()] })
+    }
+
+    #[test]
+    fn declarations_and_measurements() {
+        let input = "OPENQASM 3.0;
+include \"stdgates.inc\";
+qubit[2] q;
+qubit r;
+bit[2] c;
+h q;
+/* block
+   comment */
+cx q[0], r;
+c[0] = measure q[0];
+measure q[1] -> c[1];
+measure q[0];
+reset r;
+barrier;
+";
+        use PrimitiveGate::*;
+        assert_eq!(parse(input).unwrap(), vec![
+            Expr::from(InitExpr { dst: "q0".to_string() }),
+            Expr::from(InitExpr { dst: "q1".to_string() }),
+            Expr::from(InitExpr { dst: "r0".to_string() }),
+            apply(H, &["q0"]),
+            apply(H, &["q1"]),
+            apply(CX, &["q0", "r0"]),
+            measure("q0", "c0"),
+            measure("q1", "c1"),
+            measure("q0", "_discarded0"),
+            Expr::from(ResetExpr { arg: "r0".to_string() }),
+            Expr::Barrier(crate::hir::ast::BarrierExpr { args: vec![] }),
+        ]);
+    }
+
+    #[test]
+    fn for_loops() {
+        let input = "include \"stdgates.inc\";
+const int n = 4;
+qubit[n] q;
+for uint i in [0:n-2] {
+    cx q[i], q[i+1];
+}
+for i in [3:-2:0] rz(i * pi / 4) q[i];
+for int i in {0, 2} { t q[i]; }
+";
+        use PrimitiveGate::*;
+        assert_eq!(parse(input).unwrap()[4..], [
+            apply(CX, &["q0", "q1"]),
+            apply(CX, &["q1", "q2"]),
+            apply(CX, &["q2", "q3"]),
+            apply(Rz(3.0 * std::f64::consts::PI / 4.0), &["q3"]),
+            apply(T, &["q1"]),
+            apply(T, &["q0"]),
+            apply(T, &["q2"]),
+        ]);
+        assert!(matches!(parse("qubit[2] q;\nfor i in [0:1:2] U(0, 0, 0) q[i / 2];"), Err(Located { error: Error::NotInteger(_), .. })));
+    }
+
+    #[test]
+    fn conditions() {
+        let input = "include \"stdgates.inc\";
+qubit[2] q;
+bit[2] c;
+bit b;
+if (c == 3) x q[0];
+if (c[1]) { z q[1]; } else { x q[1]; }
+if (!b) y q[0];
+";
+        let cond = |bits: &[&str], value: u64, body: Expr| Expr::from(IfExpr {
+            bits: bits.iter().map(|x| x.to_string()).collect(),
+            value,
+            body: Box::new(body),
+        });
+        use PrimitiveGate::*;
+        assert_eq!(parse(input).unwrap()[2..], [
+            cond(&["c0", "c1"], 3, apply(X, &["q0"])),
+            cond(&["c1"], 1, apply(Z, &["q1"])),
+            cond(&["c1"], 0, apply(X, &["q1"])),
+            cond(&["b0"], 0, apply(Y, &["q0"])),
+        ]);
+        let input = "qubit q;\nbit[2] c;\nif (c) U(0, 0, 0) q;";
+        assert!(matches!(parse(input), Err(Located { error: Error::NotBit(_), .. })));
+    }
+}
@@ -26,7 +26,7 @@ impl SendData {
     }
 
     pub fn cost(&self) -> EvaluationCost {
-        self.cost.clone()
+        self.cost
     }
 
     pub fn value(&self) -> &Value {
@@ -39,6 +39,12 @@ pub struct CommBuffer {
     que: VecDeque<SendData>,
 }
 
+impl Default for CommBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CommBuffer {
     pub fn new() -> Self {
         Self {
@@ -57,7 +57,7 @@ pub fn collect_cost(costs: Vec<EvaluationCost>) -> EvaluationCost {
     let mut c_depth = 0;
     costs.into_iter().for_each(|cost| {
         total_time = u64::max(total_time, cost.total_time());
-        gen_ent_time = u64::max(total_time, cost.gen_ent_time);
+        gen_ent_time = u64::max(gen_ent_time, cost.gen_ent_time());
         e_depth = u64::max(e_depth, cost.e_depth());
         c_depth = u64::max(c_depth, cost.c_depth());
     });
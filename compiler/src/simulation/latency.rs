@@ -1,26 +1,52 @@
 use inquir::{
+    Label,
     Process,
     PrimitiveGate,
 };
-use crate::arch::NodeInfo;
+use crate::arch::{Configuration, NodeInfo};
+use rand::{Rng, SeedableRng, rngs::StdRng};
 
 pub struct Latency {
     node_info: NodeInfo,
+    /// The time of an attempt to generate a Bell pair with each node, the success probability,
+    /// and the expected number of pairs generated for each pair of the program because of failed purification.
+    gen_ent: Vec<(u64, f64, f64)>,
+    /// The propagation delay of classical messages to each node.
+    message: Vec<u64>,
+    /// The seed of the numbers of attempts, which are the expected numbers if `None`.
+    seed: Option<u64>,
 }
 
 impl Latency {
-    pub fn new(node_info: NodeInfo) -> Self {
+    pub fn new(config: &Configuration, id: usize) -> Self {
         Self {
-            node_info,
+            node_info: config.node_info_ref(id).clone(),
+            gen_ent: (0..config.node_size())
+                .map(|v| (config.gen_ent_time(id, v), config.success_probability(id, v), config.purification_overhead(id, v)))
+                .collect(),
+            message: config.message_latencies(id),
+            seed: None,
         }
     }
 
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
     pub fn latency(&self, proc: &Process) -> u64 {
         match proc {
-            Process::GenEnt(_) => self.node_info.gen_ent_cost(),
+            Process::GenEnt(proc) => {
+                let (time, p, overhead) = self.gen_ent[proc.p.to_usize()];
+                // the failed purification rounds are charged by their expected number even with `seed`
+                let time = match self.seed {
+                    Some(seed) => (time * attempts(p, seed, &proc.label)) as f64,
+                    None => time as f64 / p,
+                };
+                (time * overhead).round() as u64
+            },
             Process::Open(_) => self.node_info.classical_comm_cost(),
             Process::EntSwap(_) => self.node_info.single_gate_cost(),
-            Process::Send(_) => self.node_info.classical_comm_cost(),
+            Process::Send(p) => self.node_info.classical_comm_cost() + self.message[p.dst.to_usize()],
             Process::Recv(_) => self.node_info.classical_comm_cost(),
             Process::Apply(p) => self.latency_gate(&p.gate),
             Process::Measure(_) => self.node_info.measure_cost(),
@@ -41,3 +67,17 @@ impl Latency {
         }
     }
 }
+
+/// Draws the number of attempts until one succeeds with probability `p`.
+/// It depends only on `seed` and the label, so that both ends of the Bell pair draw the same number.
+fn attempts(p: f64, seed: u64, l: &Label) -> u64 {
+    if p >= 1.0 {
+        return 1;
+    }
+    // FNV-1a, which does not depend on the platform
+    let hash = l.to_string().bytes().fold(0xcbf29ce484222325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+    let mut rng = StdRng::seed_from_u64(seed ^ hash);
+    // the inverse transform of the geometric distribution
+    let u: f64 = 1.0 - rng.gen::<f64>();
+    1 + (u.ln() / (1.0 - p).ln()).floor() as u64
+}
@@ -4,3 +4,6 @@ pub mod comm_buffer;
 pub mod simulator;
 pub mod shared_memory;
 pub mod latency;
+pub mod semantics;
+pub mod state_vector;
+pub mod stabilizer;
@@ -18,40 +18,31 @@ use std::cmp::Reverse;
 #[derive(Debug, Clone)]
 struct Registers {
     q: BinaryHeap<Reverse<(EvaluationCost, Qubit)>>,
-    cq: HashMap<ParticipantId, BinaryHeap<Reverse<(EvaluationCost, Qubit)>>>,
-    q_to_partner: HashMap<u32, ParticipantId>,
+    /// The free communication qubits.
+    cq: Vec<(EvaluationCost, Qubit)>,
+    /// The partners which each communication qubit serves.
+    partners: HashMap<u32, Vec<ParticipantId>>,
     used_q: HashSet<u32>,
-    used_cq: HashSet<u32>,
+    /// The partner of the Bell pair held by each used communication qubit.
+    held: HashMap<u32, ParticipantId>,
 }
 
 impl Registers {
-    pub fn new(num_q: usize, num_cq: HashMap<ParticipantId, u32>) -> Self {
+    pub fn new(num_q: usize, comm: Vec<Vec<ParticipantId>>) -> Self {
         let q = (0..num_q).fold(BinaryHeap::new(), |mut h, i| {
             let q = Qubit::new(QubitKind::Data, i as u32);
             let initial_cost = Default::default();
             h.push(Reverse((initial_cost, q)));
             h
         });
-        let mut cq = HashMap::new();
-        let mut counter = 0;
-        let mut q_to_partner = HashMap::new();
-        num_cq.iter().for_each(|(partner, num)| {
-            let mut h = BinaryHeap::new();
-            (0..*num).into_iter().for_each(|_| {
-                let cq = Qubit::new(QubitKind::Comm, counter);
-                q_to_partner.insert(cq.id(), partner.clone());
-                let initial_cost = Default::default();
-                h.push(Reverse((initial_cost, cq)));
-                counter += 1;
-            });
-            cq.insert(partner.clone(), h);
-        });
+        let cq = (0..comm.len()).map(|i| (Default::default(), Qubit::new(QubitKind::Comm, i as u32))).collect();
+        let partners = comm.into_iter().enumerate().map(|(i, ps)| (i as u32, ps)).collect();
         Self {
             q,
             cq,
-            q_to_partner,
+            partners,
             used_q: HashSet::new(),
-            used_cq: HashSet::new(),
+            held: HashMap::new(),
         }
     }
 
@@ -64,13 +55,48 @@ impl Registers {
         }
     }
 
-    pub fn init_comm_qubit(&mut self, partner: ParticipantId) -> Option<(Qubit, EvaluationCost)> {
-        if let Some(Reverse((cost, q))) = self.cq.get_mut(&partner).unwrap().pop() {
-            self.used_cq.insert(q.id());
-            Some((q, cost))
-        } else {
-            None
+    /// Takes the earliest free communication qubit with which the held Bell pairs and a new one with `partner`
+    /// can be matched to distinct qubits serving their partners, as `Configuration::can_hold` assumes,
+    /// preferring the one serving the fewest partners to leave the others to the other partners.
+    /// The held pairs are moved along the matching, which is returned as the map from their old qubits to the new ones.
+    pub fn init_comm_qubit(&mut self, partner: ParticipantId) -> Option<(Qubit, EvaluationCost, HashMap<u32, u32>)> {
+        let partners = &self.partners;
+        assert!(partners.values().any(|ps| ps.contains(&partner)), "no communication qubit serves participant {}", partner);
+        let mut candidates: Vec<_> = (0..self.cq.len()).collect();
+        candidates.sort_by_key(|&i| (self.cq[i].0, partners[&self.cq[i].1.id()].len(), self.cq[i].1.id()));
+        for i in candidates {
+            let qubits: Vec<_> = self.held.keys().copied().chain([self.cq[i].1.id()]).collect();
+            // the old qubit of the pair held by each qubit, where `None` is the new pair
+            let mut pair_of: HashMap<u32, (Option<u32>, ParticipantId)> = self.held.iter().map(|(&q, &p)| (q, (Some(q), p))).collect();
+            fn augment(
+                pair: (Option<u32>, ParticipantId),
+                qubits: &[u32],
+                partners: &HashMap<u32, Vec<ParticipantId>>,
+                pair_of: &mut HashMap<u32, (Option<u32>, ParticipantId)>,
+                visited: &mut HashSet<u32>,
+            ) -> bool {
+                for &q in qubits {
+                    if visited.contains(&q) || !partners[&q].contains(&pair.1) {
+                        continue;
+                    }
+                    visited.insert(q);
+                    if pair_of.get(&q).copied().is_none_or(|other| augment(other, qubits, partners, pair_of, visited)) {
+                        pair_of.insert(q, pair);
+                        return true;
+                    }
+                }
+                false
+            }
+            if !augment((None, partner), &qubits, partners, &mut pair_of, &mut HashSet::new()) {
+                continue;
+            }
+            let (cost, _) = self.cq.swap_remove(i);
+            let q = pair_of.iter().find_map(|(&q, &(old, _))| old.is_none().then_some(q)).unwrap();
+            let moves = pair_of.iter().filter_map(|(&q, &(old, _))| old.filter(|&old| old != q).map(|old| (old, q))).collect();
+            self.held = pair_of.into_iter().map(|(q, (_, p))| (q, p)).collect();
+            return Some((Qubit::new(QubitKind::Comm, q), cost, moves));
         }
+        None
     }
 
     pub fn free_qubit(&mut self, q: Qubit, cost: EvaluationCost) {
@@ -81,10 +107,9 @@ impl Registers {
                 self.q.push(Reverse((cost, q)));
             },
             QubitKind::Comm => {
-                assert!(self.used_cq.contains(&q.id()));
-                self.used_cq.remove(&q.id());
-                let partner = self.q_to_partner[&q.id()];
-                self.cq.get_mut(&partner).unwrap().push(Reverse((cost, q)));
+                assert!(self.held.contains_key(&q.id()));
+                self.held.remove(&q.id());
+                self.cq.push((cost, q));
             },
         }
     }
@@ -93,7 +118,7 @@ impl Registers {
         println!("{:?}", self.q);
         println!("{:?}", self.cq);
         println!("{:?}", self.used_q);
-        println!("{:?}", self.used_cq);
+        println!("{:?}", self.held);
     }
 }
 
@@ -111,10 +136,10 @@ pub struct Participant {
 }
 
 impl Participant {
-    pub fn new(id: ParticipantId, num_q: usize, num_cq: HashMap<ParticipantId, u32>, mem: Rc<RefCell<SharedMemory>>, latency: Latency) -> Self {
+    pub fn new(id: ParticipantId, num_q: usize, comm: Vec<Vec<ParticipantId>>, mem: Rc<RefCell<SharedMemory>>, latency: Latency) -> Self {
         Self {
             id,
-            reg: Registers::new(num_q, num_cq),
+            reg: Registers::new(num_q, comm),
             processes: Vec::new(),
             current_proc_idx: 0,
             shared_memory: mem,
@@ -145,6 +170,11 @@ impl Participant {
         self.processes = process;
     }
 
+    /// Draws the number of attempts of each `genEnt` with `seed`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.latency.set_seed(seed);
+    }
+
     pub fn advance(&mut self) -> u32 {
         let start = self.current_proc_idx;
         while self.current_proc_idx < self.processes.len() {
@@ -187,7 +217,10 @@ impl Participant {
             Process::GenEnt(proc) => {
                 let x = proc.x.clone();
                 let mut mem = self.shared_memory.borrow_mut();
-                if let Some((q, cost)) = self.reg.init_comm_qubit(proc.p) {
+                if let Some((q, cost, moves)) = self.reg.init_comm_qubit(proc.p) {
+                    self.var_to_qubit.values_mut()
+                        .filter(|q| q.kind() == QubitKind::Comm)
+                        .for_each(|q| if let Some(&to) = moves.get(&q.id()) { *q = Qubit::new(QubitKind::Comm, to) });
                     mem.request_ent(proc.p, cost, proc.label.clone());
                     if let Some(cost2) = mem.check_ent(self.id, proc.label) {
                         let mut cost = collect_cost(vec![cost, cost2]);
@@ -276,7 +309,7 @@ impl Participant {
             Process::Parallel(_) => unimplemented!(),
         };
 
-        issued_time.map_or(false, |t| {
+        issued_time.is_some_and(|t| {
             self.issue_timestamp.push((t, self.current_proc_idx));
             true
         })
@@ -287,7 +320,7 @@ impl Participant {
     }
 
     pub fn evaluation_cost(&self) -> EvaluationCost {
-        let costs = self.cost_when_finished.iter().map(|(_, cost)| *cost).collect();
+        let costs = self.cost_when_finished.values().copied().collect();
         collect_cost(costs)
     }
 
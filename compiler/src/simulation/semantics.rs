@@ -0,0 +1,393 @@
+use crate::codegen::decomposer::Decomposer;
+use inquir::{
+    SessionId, ParticipantId, Label,
+    Process, PrimitiveGate,
+    Expr, BinOp,
+    System, LocProc,
+};
+use rand::{SeedableRng, rngs::StdRng};
+use std::fmt;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// A quantum state which an `Interpreter` runs a system on.
+pub trait QuantumState {
+    /// Adds a qubit in `|0>` and returns its index.
+    fn add_qubit(&mut self) -> Result<usize, Error>;
+
+    fn apply(&mut self, gate: &PrimitiveGate, qs: &[usize]) -> Result<(), Error>;
+
+    /// Measures the parity of the given qubits in the computational basis.
+    fn measure(&mut self, qs: &[usize], rng: &mut StdRng) -> bool;
+
+    /// Measures like `measure` but the outcome is forced. Returns `false` if it has probability zero.
+    fn postselect(&mut self, qs: &[usize], outcome: bool) -> bool;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// No participant can proceed. Each entry is a participant and the instruction it waits on.
+    Stuck(Vec<(ParticipantId, Process)>),
+    UnsupportedGate(PrimitiveGate),
+    Unbound(ParticipantId, String),
+    /// The state cannot hold more qubits than the limit.
+    TooManyQubits(usize),
+    /// A measurement cannot have the outcome given by `Interpreter::with_outcomes`.
+    Impossible(ParticipantId, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Stuck(waiting) => {
+                write!(f, "no participant can proceed:")?;
+                for (p, proc) in waiting {
+                    write!(f, " [participant {}: {}]", p, proc)?;
+                }
+                Ok(())
+            },
+            Error::UnsupportedGate(gate) => write!(f, "unsupported gate: {}", gate),
+            Error::Unbound(p, x) => write!(f, "unbound variable on participant {}: {}", p, x),
+            Error::TooManyQubits(limit) => write!(f, "more than {} qubits are alive at the same time", limit),
+            Error::Impossible(p, x) => write!(f, "measurement {} on participant {} cannot have the given outcome", x, p),
+        }
+    }
+}
+
+struct Thread {
+    p: ParticipantId,
+    procs: Vec<Process>,
+    pc: usize,
+    qubits: HashMap<String, usize>,
+    bits: HashMap<String, bool>,
+    /// The qubit allocated for the `genEnt` which waits for the partner.
+    pending_ent: Option<usize>,
+}
+
+/// Executes a system faithfully, including its quantum state.
+/// `qsend`, `qrecv`, `rcxc` and `rcxt` are run through their decompositions.
+pub struct Interpreter<S> {
+    state: S,
+    rng: StdRng,
+    threads: Vec<Thread>,
+    free_qubits: Vec<usize>,
+    mailbox: HashMap<(SessionId, ParticipantId, Label), VecDeque<bool>>,
+    /// `(p, q, l) -> x` means that `p` has requested `genEnt[q](l)` with the qubit `x`.
+    ent_requests: HashMap<(ParticipantId, ParticipantId, Label), usize>,
+    established: HashSet<(ParticipantId, Label)>,
+    /// The outcomes which the measurements of each variable are forced to have in order.
+    forced: HashMap<String, VecDeque<bool>>,
+    outcomes: HashMap<String, Vec<bool>>,
+}
+
+impl<S: QuantumState> Interpreter<S> {
+    pub fn new(s: &System, state: S, seed: u64) -> Self {
+        let s = Decomposer::new().decompose(flatten(s.clone()));
+        let threads = located_processes(&s).into_iter().map(|LocProc { p, procs }| Thread {
+            p: *p,
+            procs: procs.clone(),
+            pc: 0,
+            qubits: HashMap::new(),
+            bits: HashMap::new(),
+            pending_ent: None,
+        }).collect();
+        Self {
+            state,
+            rng: StdRng::seed_from_u64(seed),
+            threads,
+            free_qubits: Vec::new(),
+            mailbox: HashMap::new(),
+            ent_requests: HashMap::new(),
+            established: HashSet::new(),
+            forced: HashMap::new(),
+            outcomes: HashMap::new(),
+        }
+    }
+
+    /// Forces the outcomes of the measurements `x = measure(..)` for each variable `x`.
+    pub fn with_outcomes(mut self, outcomes: HashMap<String, Vec<bool>>) -> Self {
+        self.forced = outcomes.into_iter().map(|(x, ms)| (x, ms.into())).collect();
+        self
+    }
+
+    pub fn run(&mut self) -> Result<(), Error> {
+        while self.threads.iter().any(|t| t.pc < t.procs.len()) {
+            let mut progress = false;
+            for i in 0..self.threads.len() {
+                while self.threads[i].pc < self.threads[i].procs.len() && self.step(i)? {
+                    self.threads[i].pc += 1;
+                    progress = true;
+                }
+            }
+            if !progress {
+                let waiting = self.threads.iter()
+                    .filter(|t| t.pc < t.procs.len())
+                    .map(|t| (t.p, t.procs[t.pc].clone()))
+                    .collect();
+                return Err(Error::Stuck(waiting));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Qubits which are not freed, sorted by participants and variables.
+    pub fn live_qubits(&self) -> Vec<(ParticipantId, String, usize)> {
+        let mut res: Vec<_> = self.threads.iter()
+            .flat_map(|t| t.qubits.iter().map(|(x, &q)| (t.p, x.clone(), q)))
+            .collect();
+        res.sort();
+        res
+    }
+
+    /// The qubit bound to `x` on the participant `p`.
+    pub fn qubit_of(&self, p: ParticipantId, x: &str) -> Option<usize> {
+        self.threads.iter().find(|t| t.p == p).and_then(|t| t.qubits.get(x).copied())
+    }
+
+    pub fn bit(&self, p: ParticipantId, x: &str) -> Option<bool> {
+        self.threads.iter().find(|t| t.p == p).and_then(|t| t.bits.get(x).copied())
+    }
+
+    /// The outcomes of the measurements `x = measure(..)` for each variable `x` in order.
+    pub fn outcomes(&self) -> &HashMap<String, Vec<bool>> {
+        &self.outcomes
+    }
+
+    /// Executes the current instruction of the `i`th thread. Returns `false` if it has to wait.
+    fn step(&mut self, i: usize) -> Result<bool, Error> {
+        let p = self.threads[i].p;
+        match self.threads[i].procs[self.threads[i].pc].clone() {
+            Process::Open(_) => {},
+            Process::Init(proc) => {
+                let q = self.alloc()?;
+                self.threads[i].qubits.insert(proc.dst, q);
+            },
+            Process::Free(proc) => {
+                let q = self.qubit(i, &proc.arg)?;
+                self.threads[i].qubits.remove(&proc.arg);
+                self.release(q)?;
+            },
+            Process::GenEnt(proc) => {
+                let q = if let Some(q) = self.threads[i].pending_ent {
+                    if !self.established.remove(&(p, proc.label)) {
+                        return Ok(false);
+                    }
+                    self.threads[i].pending_ent = None;
+                    q
+                } else if let Some(partner) = self.ent_requests.remove(&(proc.p, p, proc.label.clone())) {
+                    let q = self.alloc()?;
+                    self.state.apply(&PrimitiveGate::H, &[partner])?;
+                    self.state.apply(&PrimitiveGate::CX, &[partner, q])?;
+                    self.established.insert((proc.p, proc.label));
+                    q
+                } else {
+                    let q = self.alloc()?;
+                    self.ent_requests.insert((p, proc.p, proc.label), q);
+                    self.threads[i].pending_ent = Some(q);
+                    return Ok(false);
+                };
+                self.threads[i].qubits.insert(proc.x, q);
+            },
+            Process::EntSwap(proc) => {
+                let q1 = self.qubit(i, &proc.arg1)?;
+                let q2 = self.qubit(i, &proc.arg2)?;
+                self.state.apply(&PrimitiveGate::CX, &[q1, q2])?;
+                self.state.apply(&PrimitiveGate::H, &[q1])?;
+                let m1 = self.state.measure(&[q1], &mut self.rng);
+                let m2 = self.state.measure(&[q2], &mut self.rng);
+                let t = &mut self.threads[i];
+                t.qubits.remove(&proc.arg1);
+                t.qubits.remove(&proc.arg2);
+                t.bits.insert(proc.x1, m1);
+                t.bits.insert(proc.x2, m2);
+                self.release(q1)?;
+                self.release(q2)?;
+            },
+            Process::Send(proc) => {
+                let (l, e) = proc.data;
+                let v = eval(&e, &self.threads[i])?;
+                self.mailbox.entry((proc.s, proc.dst, l)).or_default().push_back(v);
+            },
+            Process::Recv(proc) => {
+                let (l, x) = proc.data;
+                let v = self.mailbox.get_mut(&(proc.s, p, l)).and_then(|que| que.pop_front());
+                match v {
+                    Some(v) => { self.threads[i].bits.insert(x, v); },
+                    None => return Ok(false),
+                }
+            },
+            Process::Apply(proc) => {
+                let enabled = match &proc.ctrl {
+                    Some(e) => eval(e, &self.threads[i])?,
+                    None => true,
+                };
+                if enabled {
+                    let qs = proc.args.iter().map(|x| self.qubit(i, x)).collect::<Result<Vec<_>, _>>()?;
+                    self.state.apply(&proc.gate, &qs)?;
+                }
+            },
+            Process::Measure(proc) => {
+                let qs = proc.args.iter().map(|x| self.qubit(i, x)).collect::<Result<Vec<_>, _>>()?;
+                let m = match self.forced.get_mut(&proc.dst).and_then(|ms| ms.pop_front()) {
+                    Some(m) if self.state.postselect(&qs, m) => m,
+                    Some(_) => return Err(Error::Impossible(p, proc.dst)),
+                    None => self.state.measure(&qs, &mut self.rng),
+                };
+                self.outcomes.entry(proc.dst.clone()).or_default().push(m);
+                self.threads[i].bits.insert(proc.dst, m);
+            },
+            Process::QSend(_) | Process::QRecv(_) | Process::RCXC(_) | Process::RCXT(_) | Process::Parallel(_)
+                => unreachable!("decomposed in advance"),
+        }
+        Ok(true)
+    }
+
+    fn qubit(&self, i: usize, x: &str) -> Result<usize, Error> {
+        let t = &self.threads[i];
+        t.qubits.get(x).copied().ok_or_else(|| Error::Unbound(t.p, x.to_string()))
+    }
+
+    fn alloc(&mut self) -> Result<usize, Error> {
+        match self.free_qubits.pop() {
+            Some(q) => Ok(q),
+            None => self.state.add_qubit(),
+        }
+    }
+
+    /// Resets the qubit to `|0>` so that it can be reused.
+    fn release(&mut self, q: usize) -> Result<(), Error> {
+        if self.state.measure(&[q], &mut self.rng) {
+            self.state.apply(&PrimitiveGate::X, &[q])?;
+        }
+        self.free_qubits.push(q);
+        Ok(())
+    }
+}
+
+fn eval(e: &Expr, t: &Thread) -> Result<bool, Error> {
+    match e {
+        Expr::BLit(b) => Ok(*b),
+        Expr::Var(x) => t.bits.get(x).copied().ok_or_else(|| Error::Unbound(t.p, x.clone())),
+        Expr::Not(e) => Ok(!eval(e, t)?),
+        Expr::BinOp(op, l, r) => {
+            let (l, r) = (eval(l, t)?, eval(r, t)?);
+            Ok(match op {
+                BinOp::And => l && r,
+                BinOp::Or => l || r,
+                BinOp::Xor => l ^ r,
+            })
+        },
+    }
+}
+
+/// Sequentializes parallel compositions of local instructions.
+fn flatten(s: System) -> System {
+    fn flatten_proc(proc: Process) -> Vec<Process> {
+        match proc {
+            Process::Parallel(procs) => procs.into_iter().flat_map(flatten_proc).collect(),
+            proc => vec![proc],
+        }
+    }
+    match s {
+        System::Located(LocProc { p, procs }) => {
+            System::Located(LocProc { p, procs: procs.into_iter().flat_map(flatten_proc).collect() })
+        },
+        System::Composition(ss) => System::Composition(ss.into_iter().map(flatten).collect()),
+    }
+}
+
+fn located_processes(s: &System) -> Vec<&LocProc> {
+    match s {
+        System::Located(proc) => vec![proc],
+        System::Composition(ss) => ss.iter().flat_map(located_processes).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::state_vector::{StateVector, Complex, fidelity};
+    use inquir::parser::parse;
+
+    fn final_state(interp: &Interpreter<StateVector>) -> Vec<Complex> {
+        let qs: Vec<_> = interp.live_qubits().into_iter().map(|(_, _, q)| q).collect();
+        interp.state().amplitudes(&qs)
+    }
+
+    #[test]
+    fn teleportation() {
+        // teleports H|0> over an entanglement swapping chain 0 - 1 - 2
+        let s = parse("0 {
+  world = open[0,1,2];
+  q = init();
+  H q;
+  _cq0 = genEnt[1](l0);
+  recv(world, l2:_m0);
+  Z[_m0] _cq0;
+  qsend[2](world, l4, q, _cq0);
+}
+1 {
+  world = open[0,1,2];
+  _cq1 = genEnt[0](l0);
+  _cq2 = genEnt[2](l1);
+  (_m1, _m2) = entSwap(_cq1, _cq2);
+  send[0](world, l2:_m1);
+  send[2](world, l3:_m2);
+}
+2 {
+  world = open[0,1,2];
+  _cq3 = genEnt[1](l1);
+  recv(world, l3:_m3);
+  X[_m3] _cq3;
+  r = qrecv(world, l4, _cq3);
+}").unwrap();
+        let plus = [Complex::new(std::f64::consts::FRAC_1_SQRT_2, 0.0); 2];
+        for seed in 0..8 {
+            let mut interp = Interpreter::new(&s, StateVector::new(), seed);
+            interp.run().unwrap();
+            let live = interp.live_qubits();
+            assert_eq!(live.len(), 1);
+            assert_eq!((live[0].0, live[0].1.as_str()), (ParticipantId::new(2), "r"));
+            assert!((fidelity(&final_state(&interp), &plus) - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn remote_cx() {
+        let s = parse("0 {
+  world = open[0,1];
+  a = init();
+  X a;
+  _cq0 = genEnt[1](l0);
+  rcxc[1](world, l1, a, _cq0);
+}
+1 {
+  world = open[0,1];
+  b = init();
+  _cq1 = genEnt[0](l0);
+  rcxt[0](world, l1, b, _cq1);
+  m = measure b;
+}").unwrap();
+        for seed in 0..8 {
+            let mut interp = Interpreter::new(&s, StateVector::new(), seed);
+            interp.run().unwrap();
+            assert_eq!(interp.bit(ParticipantId::new(1), "m"), Some(true));
+        }
+    }
+
+    #[test]
+    fn stuck() {
+        let s = parse("0 {
+  world = open[0,1];
+  recv(world, l0:x);
+}
+1 {
+  world = open[0,1];
+}").unwrap();
+        let mut interp = Interpreter::new(&s, StateVector::new(), 0);
+        assert!(matches!(interp.run(), Err(Error::Stuck(waiting)) if waiting.len() == 1));
+    }
+}
@@ -12,6 +12,12 @@ pub struct SharedMemory {
     heap: HashMap<SessionId, Vec<CommBuffer>>,
 }
 
+impl Default for SharedMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SharedMemory {
     pub fn new() -> Self {
         Self {
@@ -21,9 +27,7 @@ impl SharedMemory {
     }
 
     pub fn open_session(&mut self, s: SessionId) {
-        if !self.heap.contains_key(&s) {
-            self.heap.insert(s, Vec::new());
-        }
+        self.heap.entry(s).or_default();
     }
 
     pub fn request_ent(&mut self, partner: ParticipantId, cost: EvaluationCost, l: Label) {
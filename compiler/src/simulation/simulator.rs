@@ -11,8 +11,7 @@ use inquir::{
 };
 use std::rc::Rc;
 use std::cell::RefCell;
-use std::collections::HashMap;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 
 /// Note: We do not calculate the quantum state because of its computational cost.
 pub struct Simulator {
@@ -28,23 +27,13 @@ impl Simulator {
         let shared_memory = Rc::new(RefCell::new(SharedMemory::new()));
         let participants: Vec<_> = (0..config.node_size()).map(|i| {
             let id = ParticipantId::new(i as u32);
-            let g = config.connections();
-            let num_q = config.node_info_ref(i).num_of_qubits() as usize;
-            let mut num_cq = HashMap::new();
-            g.outgoing_edges(i).iter().for_each(|&eidx| {
-                let e = g.edge(eidx);
-                let p = ParticipantId::new(e.target() as u32);
-                num_cq.insert(p, *e.weight());
-            });
-            // TODO
-            g.incoming_edges(i).iter().for_each(|&eidx| {
-                let e = g.edge(eidx);
-                let p = ParticipantId::new(e.source() as u32);
-                num_cq.insert(p, *e.weight());
-            });
+            let num_q = config.node_info_ref(i).num_of_data_qubits() as usize;
+            let comm = config.comm_qubits(i).into_iter()
+                .map(|ps| ps.into_iter().map(ParticipantId::new).collect())
+                .collect();
             let process = inquir::system::projection(s, id).unwrap();
-            let latency = Latency::new(config.node_info_ref(id.to_usize()).clone());
-            let mut p = Participant::new(id, num_q, num_cq, Rc::clone(&shared_memory), latency);
+            let latency = Latency::new(config, i);
+            let mut p = Participant::new(id, num_q, comm, Rc::clone(&shared_memory), latency);
             p.add_process(process);
             p
         }).collect();
@@ -68,8 +57,19 @@ impl Simulator {
         }
     }
 
+    /// Simulates the entanglement generation which succeeds by chance,
+    /// drawing the number of attempts with `seed` instead of taking the expected number.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.participants.iter_mut().for_each(|p| p.set_seed(seed));
+        self
+    }
+
     pub fn run(&mut self) -> EvaluationCost {
         println!("Start simulation.");
+        self.simulate()
+    }
+
+    fn simulate(&mut self) -> EvaluationCost {
         while self.participants.iter().any(|p| !p.is_completed()) {
             let steps: Vec<_> = self.participants.iter_mut().map(|p| {
                 let steps = p.advance();
@@ -99,3 +99,99 @@ impl Simulator {
     }
 }
 
+/// Runs `shots` simulations of `s` where the entanglement generation succeeds by chance,
+/// with the seeds from `seed`.
+pub fn run_shots(s: &System, config: &Configuration, shots: usize, seed: u64) -> Vec<EvaluationCost> {
+    (0..shots as u64).map(|i| {
+        let mut simulator = Simulator::new(s, config).with_seed(seed.wrapping_add(i));
+        simulator.mp.set_draw_target(ProgressDrawTarget::hidden());
+        simulator.simulate()
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{CommQubits, LinkInfo, testing::ConfigBuilder};
+    use inquir::parser::parse;
+
+    #[test]
+    fn retries_entanglement_generation() {
+        let s = parse("0 {
+  _cq0 = genEnt[1](l0);
+  free _cq0;
+}
+1 {
+  _cq1 = genEnt[0](l0);
+  free _cq1;
+}").unwrap();
+        let load = |p| ConfigBuilder::new(&[1, 1]).link_with(0, 1, LinkInfo::new(1).with_gen_ent_time(100).with_success_probability(p)).build();
+        let config = load(0.5);
+        // the expected number of attempts
+        assert_eq!(Simulator::new(&s, &config).run().total_time(), 200);
+
+        let costs = run_shots(&s, &config, 32, 0);
+        assert!(costs.iter().all(|c| c.total_time() % 100 == 0 && c.total_time() == c.gen_ent_time()));
+        assert!(costs.iter().any(|c| c.total_time() == 100) && costs.iter().any(|c| c.total_time() > 100));
+        assert_eq!(costs, run_shots(&s, &config, 32, 0));
+
+        assert!(run_shots(&s, &load(1.0), 8, 0).iter().all(|c| c.total_time() == 100));
+    }
+
+    #[test]
+    fn charges_failed_purification() {
+        let s = parse("0 {
+  _cq0 = genEnt[1](l0);
+  free _cq0;
+}
+1 {
+  _cq1 = genEnt[0](l0);
+  free _cq1;
+}").unwrap();
+        let load = |fidelity| ConfigBuilder::new(&[1, 1])
+            .node(0, |n| n.with_purification_rounds(1))
+            .link_with(0, 1, LinkInfo::new(2).with_gen_ent_time(100).with_fidelity(fidelity))
+            .build();
+        assert_eq!(Simulator::new(&s, &load(1.0)).run().total_time(), 100);
+        let config = load(0.9);
+        let expected = (100.0 * config.purification_overhead(0, 1)).round() as u64;
+        assert!(expected > 100);
+        assert_eq!(Simulator::new(&s, &config).run().total_time(), expected);
+    }
+
+    #[test]
+    fn moves_pairs_between_comm_qubits() {
+        // The pair `a` takes the qubit serving only node 1 and is freed last, so `c` comes
+        // first to the qubit shared with node 2 unless `c` and `d` are matched to the qubits.
+        let s = parse("0 {
+  a = genEnt[1](la);
+  b = genEnt[1](lb);
+  X a;
+  free b;
+  free a;
+  c = genEnt[1](lc);
+  d = genEnt[2](ld);
+  free c;
+  free d;
+}
+1 {
+  a = genEnt[0](la);
+  b = genEnt[0](lb);
+  free a;
+  free b;
+  c = genEnt[0](lc);
+  free c;
+}
+2 {
+  d = genEnt[0](ld);
+  free d;
+}").unwrap();
+        let config = ConfigBuilder::new(&[1, 1, 1])
+            .node(0, |n| n.with_comm_qubits(CommQubits::Dedicated(vec![vec![1, 2], vec![1]])))
+            .node(1, |n| n.with_comm_qubits(CommQubits::Shared(2)))
+            .links(&[(0, 1, 1), (0, 2, 1)])
+            .build();
+        assert!(config.can_hold(0, &[1, 2]));
+        assert_eq!(Simulator::new(&s, &config).run().e_depth(), 2);
+    }
+}
@@ -0,0 +1,333 @@
+use crate::simulation::semantics::{QuantumState, Error};
+use inquir::PrimitiveGate;
+use rand::{Rng, rngs::StdRng};
+use std::f64::consts::FRAC_PI_2;
+
+/// The tolerance of angles of `Rz` regarded as multiples of `pi/2`.
+const ANGLE_EPSILON: f64 = 1e-9;
+
+/// The `k` such that `Rz(theta) = S^k` up to a global phase, if `theta` is a multiple of `pi/2` within a tolerance.
+pub fn clifford_rz(theta: f64) -> Option<usize> {
+    let k = (theta / FRAC_PI_2).round();
+    if (theta - k * FRAC_PI_2).abs() <= ANGLE_EPSILON {
+        Some(k.rem_euclid(4.0) as usize)
+    } else {
+        None
+    }
+}
+
+/// A Pauli operator with a sign `(-1)^r`, where `x = z = 1` stands for `Y`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PauliRow {
+    x: Vec<bool>,
+    z: Vec<bool>,
+    r: bool,
+}
+
+impl PauliRow {
+    fn identity(n: usize) -> Self {
+        Self { x: vec![false; n], z: vec![false; n], r: false }
+    }
+
+    /// Multiplies `other` from the left, keeping track of the sign.
+    fn mul_assign(&mut self, other: &PauliRow) {
+        // the exponent of `i` in the product (Aaronson and Gottesman, 2004)
+        let g = |x1: bool, z1: bool, x2: bool, z2: bool| -> i32 {
+            match (x1, z1) {
+                (false, false) => 0,
+                (true, true) => z2 as i32 - x2 as i32,
+                (true, false) => z2 as i32 * (2 * x2 as i32 - 1),
+                (false, true) => x2 as i32 * (1 - 2 * z2 as i32),
+            }
+        };
+        let sum = (0..self.x.len()).fold(2 * self.r as i32 + 2 * other.r as i32, |acc, j| {
+            acc + g(other.x[j], other.z[j], self.x[j], self.z[j])
+        });
+        self.r = sum.rem_euclid(4) == 2;
+        for j in 0..self.x.len() {
+            self.x[j] ^= other.x[j];
+            self.z[j] ^= other.z[j];
+        }
+    }
+
+    fn anticommutes(&self, other: &PauliRow) -> bool {
+        (0..self.x.len()).filter(|&j| (self.x[j] && other.z[j]) ^ (self.z[j] && other.x[j])).count() % 2 == 1
+    }
+
+    /// Whether it anticommutes with `Z_{q1} .. Z_{qn}`.
+    fn anticommutes_with_z(&self, qs: &[usize]) -> bool {
+        qs.iter().filter(|&&q| self.x[q]).count() % 2 == 1
+    }
+}
+
+/// A stabilizer state in the tableau representation, which simulates Clifford circuits in polynomial time.
+#[derive(Debug, Clone)]
+pub struct StabilizerState {
+    destabilizers: Vec<PauliRow>,
+    stabilizers: Vec<PauliRow>,
+    skip_non_clifford: bool,
+    skipped: usize,
+}
+
+impl Default for StabilizerState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StabilizerState {
+    pub fn new() -> Self {
+        Self {
+            destabilizers: Vec::new(),
+            stabilizers: Vec::new(),
+            skip_non_clifford: false,
+            skipped: 0,
+        }
+    }
+
+    /// Skips non-Clifford gates instead of rejecting them. The number of skipped gates is counted.
+    pub fn skip_non_clifford(mut self) -> Self {
+        self.skip_non_clifford = true;
+        self
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.stabilizers.len()
+    }
+
+    pub fn skipped_gates(&self) -> usize {
+        self.skipped
+    }
+
+    /// The stabilizer generators, e.g., `-XZI` for `-X_0 Z_1`.
+    pub fn generators(&self) -> Vec<String> {
+        self.stabilizers.iter().map(|row| {
+            let paulis: String = row.x.iter().zip(&row.z).map(|(&x, &z)| match (x, z) {
+                (false, false) => 'I',
+                (true, false) => 'X',
+                (false, true) => 'Z',
+                (true, true) => 'Y',
+            }).collect();
+            format!("{}{}", if row.r { '-' } else { '+' }, paulis)
+        }).collect()
+    }
+
+    /// Whether the state is stabilized by the Pauli operator `pauli` in the format of `generators`,
+    /// whose `i`th factor acts on the qubit `qs[i]`.
+    pub fn is_stabilized_by(&self, pauli: &str, qs: &[usize]) -> bool {
+        let mut target = PauliRow::identity(self.num_qubits());
+        target.r = pauli.starts_with('-');
+        for (c, &q) in pauli.trim_start_matches(['+', '-']).chars().zip(qs) {
+            target.x[q] = c == 'X' || c == 'Y';
+            target.z[q] = c == 'Z' || c == 'Y';
+        }
+        if self.stabilizers.iter().any(|row| row.anticommutes(&target)) {
+            return false;
+        }
+        let mut product = PauliRow::identity(self.num_qubits());
+        for (destab, stab) in self.destabilizers.iter().zip(&self.stabilizers) {
+            if destab.anticommutes(&target) {
+                product.mul_assign(stab);
+            }
+        }
+        product == target
+    }
+
+    fn rows_mut(&mut self) -> impl Iterator<Item = &mut PauliRow> {
+        self.destabilizers.iter_mut().chain(self.stabilizers.iter_mut())
+    }
+
+    fn h(&mut self, a: usize) {
+        self.rows_mut().for_each(|row| {
+            row.r ^= row.x[a] && row.z[a];
+            std::mem::swap(&mut row.x[a], &mut row.z[a]);
+        });
+    }
+
+    fn s(&mut self, a: usize) {
+        self.rows_mut().for_each(|row| {
+            row.r ^= row.x[a] && row.z[a];
+            row.z[a] ^= row.x[a];
+        });
+    }
+
+    fn cx(&mut self, a: usize, b: usize) {
+        self.rows_mut().for_each(|row| {
+            row.r ^= row.x[a] && row.z[b] && !(row.x[b] ^ row.z[a]);
+            row.x[b] ^= row.x[a];
+            row.z[a] ^= row.z[b];
+        });
+    }
+
+    fn pauli(&mut self, a: usize, x: bool, z: bool) {
+        // `X` flips the signs of the rows containing `Z` and vice versa
+        self.rows_mut().for_each(|row| row.r ^= (x && row.z[a]) ^ (z && row.x[a]));
+    }
+
+    /// Measures the parity of the qubits, where `choose` decides the outcome if it is random.
+    fn collapse(&mut self, qs: &[usize], choose: impl FnOnce() -> bool) -> bool {
+        let n = self.num_qubits();
+        if let Some(p) = self.stabilizers.iter().position(|row| row.anticommutes_with_z(qs)) {
+            // the outcome is random
+            let pivot = self.stabilizers[p].clone();
+            for i in 0..2 * n {
+                let row = if i < n { &mut self.destabilizers[i] } else { &mut self.stabilizers[i - n] };
+                if i != n + p && row.anticommutes_with_z(qs) {
+                    row.mul_assign(&pivot);
+                }
+            }
+            let outcome = choose();
+            let mut observable = PauliRow::identity(n);
+            qs.iter().for_each(|&q| observable.z[q] = true);
+            observable.r = outcome;
+            self.destabilizers[p] = pivot;
+            self.stabilizers[p] = observable;
+            outcome
+        } else {
+            // the outcome is determined by the product of the stabilizers
+            let mut product = PauliRow::identity(n);
+            for i in 0..n {
+                if self.destabilizers[i].anticommutes_with_z(qs) {
+                    product.mul_assign(&self.stabilizers[i]);
+                }
+            }
+            product.r
+        }
+    }
+}
+
+impl QuantumState for StabilizerState {
+    fn add_qubit(&mut self) -> Result<usize, Error> {
+        let n = self.num_qubits();
+        self.rows_mut().for_each(|row| {
+            row.x.push(false);
+            row.z.push(false);
+        });
+        let mut destab = PauliRow::identity(n + 1);
+        destab.x[n] = true;
+        let mut stab = PauliRow::identity(n + 1);
+        stab.z[n] = true;
+        self.destabilizers.push(destab);
+        self.stabilizers.push(stab);
+        Ok(n)
+    }
+
+    fn apply(&mut self, gate: &PrimitiveGate, qs: &[usize]) -> Result<(), Error> {
+        match (gate, qs) {
+            (PrimitiveGate::I, &[_]) => {},
+            (PrimitiveGate::X, &[a]) => self.pauli(a, true, false),
+            (PrimitiveGate::Y, &[a]) => self.pauli(a, true, true),
+            (PrimitiveGate::Z, &[a]) => self.pauli(a, false, true),
+            (PrimitiveGate::H, &[a]) => self.h(a),
+            (PrimitiveGate::S, &[a]) => self.s(a),
+            (PrimitiveGate::CX, &[a, b]) => self.cx(a, b),
+            (PrimitiveGate::Rz(theta), &[a]) if clifford_rz(*theta).is_some() => {
+                (0..clifford_rz(*theta).unwrap()).for_each(|_| self.s(a));
+            },
+            (PrimitiveGate::T | PrimitiveGate::Tdg | PrimitiveGate::Rz(_), &[_]) if self.skip_non_clifford => {
+                self.skipped += 1;
+            },
+            (gate, _) => return Err(Error::UnsupportedGate(gate.clone())),
+        }
+        Ok(())
+    }
+
+    fn measure(&mut self, qs: &[usize], rng: &mut StdRng) -> bool {
+        self.collapse(qs, || rng.gen::<bool>())
+    }
+
+    fn postselect(&mut self, qs: &[usize], outcome: bool) -> bool {
+        self.collapse(qs, || outcome) == outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::semantics::Interpreter;
+    use inquir::{ParticipantId, parser::parse};
+    use rand::SeedableRng;
+
+    #[test]
+    fn bell_state() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut st = StabilizerState::new();
+        let q0 = st.add_qubit().unwrap();
+        let q1 = st.add_qubit().unwrap();
+        st.apply(&PrimitiveGate::H, &[q0]).unwrap();
+        st.apply(&PrimitiveGate::CX, &[q0, q1]).unwrap();
+        st.apply(&PrimitiveGate::Y, &[q1]).unwrap();
+        let mut gens = st.generators();
+        gens.sort();
+        assert_eq!(gens, vec!["-XX", "-ZZ"]);
+        assert!(st.measure(&[q0, q1], &mut rng));
+        let m0 = st.measure(&[q0], &mut rng);
+        let m1 = st.measure(&[q1], &mut rng);
+        assert_ne!(m0, m1);
+        assert!(st.is_stabilized_by(if m0 { "-Z" } else { "+Z" }, &[q0]));
+        assert!(!st.is_stabilized_by("+X", &[q1]));
+
+        assert_eq!(st.apply(&PrimitiveGate::T, &[q0]), Err(Error::UnsupportedGate(PrimitiveGate::T)));
+        let mut st = st.skip_non_clifford();
+        st.apply(&PrimitiveGate::T, &[q0]).unwrap();
+        assert_eq!(st.skipped_gates(), 1);
+    }
+
+    #[test]
+    fn rz_multiples_of_half_pi() {
+        assert_eq!(clifford_rz(0.0), Some(0));
+        assert_eq!(clifford_rz(3.0 * FRAC_PI_2), Some(3));
+        assert_eq!(clifford_rz(-FRAC_PI_2), Some(3));
+        // (0.1 + 0.2) / 0.3 is not exactly 1
+        let rounded = (0.1 + 0.2) / 0.3 * FRAC_PI_2;
+        assert_ne!((rounded / FRAC_PI_2).fract(), 0.0);
+        assert_eq!(clifford_rz(rounded), Some(1));
+        assert_eq!(clifford_rz(-3.0 * rounded), Some(1));
+        assert_eq!(clifford_rz(std::f64::consts::FRAC_PI_4), None);
+
+        let mut st = StabilizerState::new();
+        let q = st.add_qubit().unwrap();
+        st.apply(&PrimitiveGate::H, &[q]).unwrap();
+        st.apply(&PrimitiveGate::Rz(rounded), &[q]).unwrap();
+        assert!(st.is_stabilized_by("+Y", &[q]));
+    }
+
+    #[test]
+    fn long_entanglement_swapping_chain() {
+        // participants 0, .., n-1 share a Bell pair between 0 and n-1 by entanglement swapping
+        let n = 100;
+        let mut src = String::new();
+        for i in 0..n {
+            src += &format!("{} {{\n  world = open[0,{}];\n", i, n - 1);
+            if i > 0 {
+                src += &format!("  a{} = genEnt[{}](g{});\n", i, i - 1, i - 1);
+            }
+            if i + 1 < n {
+                src += &format!("  b{} = genEnt[{}](g{});\n", i, i + 1, i);
+            }
+            if i > 0 && i + 1 < n {
+                if i > 1 {
+                    src += &format!("  recv(world, x{}:mx{});\n  X[mx{}] a{};\n", i, i, i, i);
+                }
+                src += &format!("  (z{}, x{}) = entSwap(a{}, b{});\n", i, i, i, i);
+                src += &format!("  send[0](world, z{}:z{});\n  send[{}](world, x{}:x{});\n", i, i, i + 1, i + 1, i);
+            }
+            if i == 0 {
+                (1..n - 1).for_each(|j| src += &format!("  recv(world, z{}:mz{});\n  Z[mz{}] b0;\n", j, j, j));
+                src += "  H b0;\n  r = measure b0;\n";
+            }
+            if i == n - 1 {
+                src += &format!("  recv(world, x{}:mx{});\n  X[mx{}] a{};\n  H a{};\n  r = measure a{};\n", i, i, i, i, i, i);
+            }
+            src += "}\n";
+        }
+        let s = parse(&src).unwrap();
+        for seed in 0..4 {
+            let mut interp = Interpreter::new(&s, StabilizerState::new(), seed);
+            interp.run().unwrap();
+            let r0 = interp.bit(ParticipantId::new(0), "r").unwrap();
+            let r1 = interp.bit(ParticipantId::new(n - 1), "r").unwrap();
+            assert_eq!(r0, r1);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use crate::simulation::semantics::{QuantumState, Error};
+use inquir::PrimitiveGate;
+use rand::{Rng, rngs::StdRng};
+use std::ops::{Add, Mul, Sub};
+use std::f64::consts::FRAC_1_SQRT_2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub const ZERO: Complex = Complex { re: 0.0, im: 0.0 };
+    pub const ONE: Complex = Complex { re: 1.0, im: 0.0 };
+
+    pub fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    /// `e^{i theta}`
+    pub fn from_phase(theta: f64) -> Self {
+        Self { re: theta.cos(), im: theta.sin() }
+    }
+
+    pub fn conj(self) -> Self {
+        Self { re: self.re, im: -self.im }
+    }
+
+    pub fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    pub fn scale(self, r: f64) -> Self {
+        Self { re: self.re * r, im: self.im * r }
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, rhs: Complex) -> Complex {
+        Complex { re: self.re + rhs.re, im: self.im + rhs.im }
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex { re: self.re - rhs.re, im: self.im - rhs.im }
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex {
+            re: self.re * rhs.re - self.im * rhs.im,
+            im: self.re * rhs.im + self.im * rhs.re,
+        }
+    }
+}
+
+/// The state vector of all the qubits in a system.
+/// The `i`th qubit corresponds to the `i`th bit of an index of `amps`.
+#[derive(Debug, Clone)]
+pub struct StateVector {
+    amps: Vec<Complex>,
+    num_qubits: usize,
+    max_qubits: usize,
+}
+
+impl Default for StateVector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateVector {
+    pub fn new() -> Self {
+        Self {
+            amps: vec![Complex::ONE],
+            num_qubits: 0,
+            max_qubits: 24,
+        }
+    }
+
+    /// Sets the maximum number of qubits, which is 24 by default.
+    pub fn with_max_qubits(mut self, n: usize) -> Self {
+        self.max_qubits = n;
+        self
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    /// The amplitudes of the given qubits, where `qs[0]` is the least significant bit.
+    /// The other qubits must be in `|0>`.
+    pub fn amplitudes(&self, qs: &[usize]) -> Vec<Complex> {
+        (0..1usize << qs.len()).map(|i| {
+            let idx = qs.iter().enumerate()
+                .filter(|&(j, _)| i >> j & 1 == 1)
+                .fold(0, |idx, (_, &q)| idx | 1 << q);
+            self.amps[idx]
+        }).collect()
+    }
+
+    fn parity(i: usize, qs: &[usize]) -> bool {
+        qs.iter().filter(|&&q| i >> q & 1 == 1).count() % 2 == 1
+    }
+
+    /// The probability that the parity of the qubits is odd.
+    fn probability(&self, qs: &[usize]) -> f64 {
+        self.amps.iter().enumerate()
+            .filter(|&(i, _)| Self::parity(i, qs))
+            .map(|(_, a)| a.norm_sqr())
+            .sum()
+    }
+
+    fn collapse(&mut self, qs: &[usize], outcome: bool) {
+        let p1 = self.probability(qs);
+        let norm = if outcome { p1 } else { 1.0 - p1 }.sqrt();
+        self.amps.iter_mut().enumerate().for_each(|(i, a)| {
+            *a = if Self::parity(i, qs) == outcome { a.scale(1.0 / norm) } else { Complex::ZERO };
+        });
+    }
+
+    fn apply_1q(&mut self, m: [[Complex; 2]; 2], t: usize, ctrl: Option<usize>) {
+        for i in 0..self.amps.len() {
+            if i >> t & 1 == 1 || ctrl.is_some_and(|c| i >> c & 1 == 0) {
+                continue;
+            }
+            let j = i | 1 << t;
+            let (a0, a1) = (self.amps[i], self.amps[j]);
+            self.amps[i] = m[0][0] * a0 + m[0][1] * a1;
+            self.amps[j] = m[1][0] * a0 + m[1][1] * a1;
+        }
+    }
+}
+
+fn matrix(gate: &PrimitiveGate) -> Option<[[Complex; 2]; 2]> {
+    let c = Complex::new;
+    let (o, l) = (Complex::ZERO, Complex::ONE);
+    let h = c(FRAC_1_SQRT_2, 0.0);
+    let m = match gate {
+        PrimitiveGate::I => [[l, o], [o, l]],
+        PrimitiveGate::X => [[o, l], [l, o]],
+        PrimitiveGate::Y => [[o, c(0.0, -1.0)], [c(0.0, 1.0), o]],
+        PrimitiveGate::Z => [[l, o], [o, c(-1.0, 0.0)]],
+        PrimitiveGate::H => [[h, h], [h, c(-FRAC_1_SQRT_2, 0.0)]],
+        PrimitiveGate::S => [[l, o], [o, c(0.0, 1.0)]],
+        PrimitiveGate::T => [[l, o], [o, Complex::from_phase(std::f64::consts::FRAC_PI_4)]],
+        PrimitiveGate::Tdg => [[l, o], [o, Complex::from_phase(-std::f64::consts::FRAC_PI_4)]],
+        PrimitiveGate::Rz(theta) => [[Complex::from_phase(-theta / 2.0), o], [o, Complex::from_phase(theta / 2.0)]],
+        PrimitiveGate::CX | PrimitiveGate::RCX => return None,
+    };
+    Some(m)
+}
+
+impl QuantumState for StateVector {
+    fn add_qubit(&mut self) -> Result<usize, Error> {
+        if self.num_qubits >= self.max_qubits {
+            return Err(Error::TooManyQubits(self.max_qubits));
+        }
+        self.amps.resize(self.amps.len() * 2, Complex::ZERO);
+        self.num_qubits += 1;
+        Ok(self.num_qubits - 1)
+    }
+
+    fn apply(&mut self, gate: &PrimitiveGate, qs: &[usize]) -> Result<(), Error> {
+        match (gate, qs) {
+            (PrimitiveGate::CX, &[c, t]) => {
+                self.apply_1q(matrix(&PrimitiveGate::X).unwrap(), t, Some(c));
+                Ok(())
+            },
+            (gate, &[t]) if matrix(gate).is_some() => {
+                self.apply_1q(matrix(gate).unwrap(), t, None);
+                Ok(())
+            },
+            (gate, _) => Err(Error::UnsupportedGate(gate.clone())),
+        }
+    }
+
+    fn measure(&mut self, qs: &[usize], rng: &mut StdRng) -> bool {
+        let outcome = rng.gen::<f64>() < self.probability(qs);
+        self.collapse(qs, outcome);
+        outcome
+    }
+
+    fn postselect(&mut self, qs: &[usize], outcome: bool) -> bool {
+        let p1 = self.probability(qs);
+        if (if outcome { p1 } else { 1.0 - p1 }) < 1e-9 {
+            return false;
+        }
+        self.collapse(qs, outcome);
+        true
+    }
+}
+
+/// `|<a|b>|^2`, which is 1 iff two states are equal up to a global phase.
+pub fn fidelity(a: &[Complex], b: &[Complex]) -> f64 {
+    a.iter().zip(b).fold(Complex::ZERO, |acc, (&x, &y)| acc + x.conj() * y).norm_sqr()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn bell_state() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut sv = StateVector::new();
+        let q0 = sv.add_qubit().unwrap();
+        let q1 = sv.add_qubit().unwrap();
+        sv.apply(&PrimitiveGate::H, &[q0]).unwrap();
+        sv.apply(&PrimitiveGate::CX, &[q0, q1]).unwrap();
+        let bell = [Complex::new(FRAC_1_SQRT_2, 0.0), Complex::ZERO, Complex::ZERO, Complex::new(FRAC_1_SQRT_2, 0.0)];
+        assert!((fidelity(&sv.amplitudes(&[q0, q1]), &bell) - 1.0).abs() < 1e-9);
+
+        // the parity of a Bell pair is always even
+        assert!(!sv.measure(&[q0, q1], &mut rng));
+        let m0 = sv.measure(&[q0], &mut rng);
+        let m1 = sv.measure(&[q1], &mut rng);
+        assert_eq!(m0, m1);
+
+        let mut sv = sv.with_max_qubits(2);
+        assert_eq!(sv.add_qubit(), Err(Error::TooManyQubits(2)));
+    }
+}
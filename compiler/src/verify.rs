@@ -0,0 +1,492 @@
+use crate::{
+    hir,
+    codegen::Layout,
+    simulation::{
+        semantics::{self, Interpreter, QuantumState},
+        state_vector::{StateVector, fidelity},
+        stabilizer::{StabilizerState, clifford_rz},
+    },
+};
+use inquir::{
+    ParticipantId,
+    Process, InitProc, ApplyProc, MeasureProc,
+    PrimitiveGate,
+    Expr, BinOp,
+    System, LocProc,
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::fmt;
+use std::collections::{HashMap, HashSet};
+
+/// A single-qubit stabilizer state given to a qubit of the source circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputState {
+    Zero,
+    One,
+    Plus,
+    Minus,
+    PlusI,
+    MinusI,
+}
+
+impl InputState {
+    const ALL: [InputState; 6] = [
+        InputState::Zero, InputState::One,
+        InputState::Plus, InputState::Minus,
+        InputState::PlusI, InputState::MinusI,
+    ];
+
+    fn preparation(self) -> Vec<PrimitiveGate> {
+        match self {
+            InputState::Zero => vec![],
+            InputState::One => vec![PrimitiveGate::X],
+            InputState::Plus => vec![PrimitiveGate::H],
+            InputState::Minus => vec![PrimitiveGate::X, PrimitiveGate::H],
+            InputState::PlusI => vec![PrimitiveGate::H, PrimitiveGate::S],
+            InputState::MinusI => vec![PrimitiveGate::X, PrimitiveGate::H, PrimitiveGate::S],
+        }
+    }
+}
+
+impl fmt::Display for InputState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputState::Zero => write!(f, "|0>"),
+            InputState::One => write!(f, "|1>"),
+            InputState::Plus => write!(f, "|+>"),
+            InputState::Minus => write!(f, "|->"),
+            InputState::PlusI => write!(f, "|+i>"),
+            InputState::MinusI => write!(f, "|-i>"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    StateVector,
+    Stabilizer,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Backend::StateVector => write!(f, "state vector"),
+            Backend::Stabilizer => write!(f, "stabilizer"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Simulation(semantics::Error),
+    /// A qubit which is not in the layout is left unfreed.
+    Leaked(ParticipantId, String),
+    /// The output states differ for the input.
+    Mismatch(Vec<(String, InputState)>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Simulation(e) => write!(f, "{}", e),
+            Error::Leaked(p, x) => write!(f, "qubit {} on participant {} is not freed", x, p),
+            Error::Mismatch(input) => {
+                let input: Vec<_> = input.iter().map(|(q, st)| format!("{}={}", q, st)).collect();
+                write!(f, "the output states differ for the input {}", input.join(", "))
+            },
+        }
+    }
+}
+
+impl From<semantics::Error> for Error {
+    fn from(e: semantics::Error) -> Self {
+        Error::Simulation(e)
+    }
+}
+
+/// The stabilizer backend is used if the circuit consists of Clifford gates.
+pub fn choose_backend(exps: &[hir::Expr]) -> Backend {
+    let clifford = exps.iter().all(|e| match e {
+        hir::Expr::Apply(hir::ApplyExpr { gate: hir::PrimitiveGate::T | hir::PrimitiveGate::Tdg | hir::PrimitiveGate::CCX, .. }) => false,
+        hir::Expr::Apply(hir::ApplyExpr { gate: hir::PrimitiveGate::Rz(theta), .. }) => clifford_rz(*theta).is_some(),
+        _ => true,
+    });
+    if clifford { Backend::Stabilizer } else { Backend::StateVector }
+}
+
+/// Checks that the compiled system `s` implements the source circuit `exps`.
+///
+/// Each trial gives random single-qubit stabilizer states to the qubits,
+/// and compares the output states of the circuit and `s` relabelled by `layout`.
+/// The measurements of the source circuit are removed from both sides in advance
+/// since equal states before them result in equal measurement distributions.
+/// If the circuit has resets or conditions, the measurements are kept instead
+/// and `s` is run with the same outcomes as the circuit.
+pub fn verify(exps: &[hir::Expr], s: &System, layout: &Layout, backend: Backend, trials: usize, seed: u64) -> Result<(), Error> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let qubits: Vec<_> = exps.iter().filter_map(|e| match e {
+        hir::Expr::Init(e) => Some(e.dst.clone()),
+        _ => None,
+    }).collect();
+    let measured: HashSet<_> = exps.iter().filter_map(|e| match e {
+        hir::Expr::Measure(e) => Some(e.dst.clone()),
+        _ => None,
+    }).collect();
+    let dynamic = exps.iter().any(|e| matches!(e, hir::Expr::Reset(_) | hir::Expr::If(_)));
+    let source = source_system(exps, dynamic);
+    let s = if dynamic { s.clone() } else { remove_measurements(s.clone(), &measured) };
+
+    for trial in 0..trials {
+        let input: Vec<_> = qubits.iter().map(|q| (q.clone(), InputState::ALL[rng.gen_range(0..6)])).collect();
+        let source = prepare(source.clone(), &input);
+        let compiled = prepare(s.clone(), &input);
+        let equivalent = match backend {
+            Backend::StateVector => {
+                let expected = run(&source, StateVector::new(), trial as u64)?;
+                let compiled = Interpreter::new(&compiled, StateVector::new(), trial as u64).with_outcomes(source_outcomes(&expected, &measured));
+                run_compiled(compiled, layout, &qubits).map(|(actual, qs)| {
+                    let src_qs: Vec<_> = (0..qubits.len()).collect();
+                    let f = fidelity(&expected.state().amplitudes(&src_qs), &actual.state().amplitudes(&qs));
+                    (f - 1.0).abs() < 1e-6
+                })
+            },
+            Backend::Stabilizer => {
+                let expected = run(&source, StabilizerState::new(), trial as u64)?;
+                let compiled = Interpreter::new(&compiled, StabilizerState::new(), trial as u64).with_outcomes(source_outcomes(&expected, &measured));
+                run_compiled(compiled, layout, &qubits).map(|(actual, qs)| {
+                    expected.state().generators().iter().all(|g| actual.state().is_stabilized_by(g, &qs))
+                })
+            },
+        };
+        match equivalent {
+            Ok(true) => {},
+            Ok(false) | Err(Error::Simulation(semantics::Error::Impossible(..))) => return Err(Error::Mismatch(input)),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// The source circuit as a system of a single participant, whose `i`th qubit is the `i`th initialized one.
+fn source_system(exps: &[hir::Expr], keep_measurements: bool) -> System {
+    let mut measured = HashSet::new();
+    let mut procs = Vec::new();
+    for (i, e) in exps.iter().enumerate() {
+        let (e, ctrl) = match e {
+            hir::Expr::If(hir::IfExpr { bits, value, body }) => {
+                let ctrl = bits.iter().enumerate().map(|(j, c)| {
+                    let expected = value.checked_shr(j as u32).unwrap_or(0) & 1 == 1;
+                    match (measured.contains(c), expected) {
+                        (false, _) => Expr::BLit(!expected),
+                        (true, true) => Expr::Var(c.clone()),
+                        (true, false) => Expr::Not(Box::new(Expr::Var(c.clone()))),
+                    }
+                }).fold(Expr::BLit(bits.len() >= 64 || value >> bits.len() == 0), |l, r| Expr::BinOp(BinOp::And, Box::new(l), Box::new(r)));
+                (body.as_ref(), Some(ctrl))
+            },
+            e => (e, None),
+        };
+        match e {
+            hir::Expr::Init(e) => procs.push(Process::Init(InitProc { dst: e.dst.clone() })),
+            hir::Expr::Apply(e) => procs.extend(e.lower().into_iter().map(|e| {
+                Process::Apply(ApplyProc { gate: e.gate.into(), args: e.args, ctrl: ctrl.clone() })
+            })),
+            hir::Expr::Measure(e) if keep_measurements => {
+                measured.insert(e.dst.clone());
+                procs.push(Process::Measure(MeasureProc { dst: e.dst.clone(), args: e.args.clone() }));
+            },
+            hir::Expr::Reset(e) => {
+                let x = format!("_reset{}", i);
+                procs.push(Process::Measure(MeasureProc { dst: x.clone(), args: vec![e.arg.clone()] }));
+                procs.push(Process::Apply(ApplyProc { gate: PrimitiveGate::X, args: vec![e.arg.clone()], ctrl: Some(Expr::Var(x)) }));
+            },
+            _ => {},
+        }
+    }
+    System::Located(LocProc { p: ParticipantId::new(0), procs })
+}
+
+fn remove_measurements(s: System, measured: &HashSet<String>) -> System {
+    match s {
+        System::Located(LocProc { p, procs }) => {
+            let procs = procs.into_iter()
+                .filter(|proc| !matches!(proc, Process::Measure(proc) if measured.contains(&proc.dst)))
+                .collect();
+            System::Located(LocProc { p, procs })
+        },
+        System::Composition(ss) => System::Composition(ss.into_iter().map(|s| remove_measurements(s, measured)).collect()),
+    }
+}
+
+/// Inserts the preparation of the input states right after the initializations.
+fn prepare(s: System, input: &[(String, InputState)]) -> System {
+    match s {
+        System::Located(LocProc { p, procs }) => {
+            let procs = procs.into_iter().flat_map(|proc| {
+                let mut res = vec![proc.clone()];
+                if let Process::Init(InitProc { dst }) = &proc {
+                    if let Some((_, st)) = input.iter().find(|(q, _)| q == dst) {
+                        res.extend(st.preparation().into_iter().map(|gate| {
+                            Process::Apply(ApplyProc { gate, args: vec![dst.clone()], ctrl: None })
+                        }));
+                    }
+                }
+                res
+            }).collect();
+            System::Located(LocProc { p, procs })
+        },
+        System::Composition(ss) => System::Composition(ss.into_iter().map(|s| prepare(s, input)).collect()),
+    }
+}
+
+/// The outcomes of the measurements in the source circuit, not including those for resets.
+fn source_outcomes<S: QuantumState>(interp: &Interpreter<S>, measured: &HashSet<String>) -> HashMap<String, Vec<bool>> {
+    interp.outcomes().iter()
+        .filter(|(x, _)| measured.contains(*x))
+        .map(|(x, ms)| (x.clone(), ms.clone()))
+        .collect()
+}
+
+fn run<S: QuantumState>(s: &System, state: S, seed: u64) -> Result<Interpreter<S>, Error> {
+    let mut interp = Interpreter::new(s, state, seed);
+    interp.run()?;
+    Ok(interp)
+}
+
+/// Runs the compiled system and returns the qubits where the source qubits are.
+fn run_compiled<S: QuantumState>(
+    mut interp: Interpreter<S>,
+    layout: &Layout,
+    qubits: &[String],
+) -> Result<(Interpreter<S>, Vec<usize>), Error> {
+    interp.run()?;
+    let outputs: HashSet<_> = layout.values().cloned().collect();
+    if let Some((p, x, _)) = interp.live_qubits().into_iter().find(|(p, x, _)| !outputs.contains(&(*p, x.clone()))) {
+        return Err(Error::Leaked(p, x));
+    }
+    let qs = qubits.iter().map(|q| {
+        let (p, x) = &layout[q];
+        interp.qubit_of(*p, x).ok_or_else(|| semantics::Error::Unbound(*p, x.clone()).into())
+    }).collect::<Result<_, Error>>()?;
+    Ok((interp, qs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arch::{Configuration, testing::ConfigBuilder},
+        codegen::{codegen, network::Swapping, routing::{Placement, placement::initial_map, RemoteOpRouter, TelegateOnly, TeledataOnly, Hybrid, Lookahead}},
+        deadlock,
+        metrics::{Metrics, calc_e_count},
+        parser::qasm2,
+        simulation::simulator::Simulator,
+    };
+    use std::collections::BTreeMap;
+
+    const SOURCE: &str = "OPENQASM 2.0;
+include \"qelib1.inc\";
+qreg q[3];
+creg c[3];
+h q[0];
+cx q[0],q[2];
+s q[2];
+cx q[2],q[1];
+h q[1];
+cx q[1],q[0];
+measure q[0] -> c[0];
+";
+
+    const TELEPORTATION: &str = "OPENQASM 2.0;
+include \"qelib1.inc\";
+qreg q[3];
+creg a[1];
+creg b[1];
+h q[1];
+cx q[1],q[2];
+cx q[0],q[1];
+h q[0];
+measure q[0] -> a[0];
+measure q[1] -> b[0];
+if(b==1) x q[2];
+if(a==1) z q[2];
+reset q[0];
+";
+
+    const FANOUT: &str = "OPENQASM 2.0;
+include \"qelib1.inc\";
+qreg q[2];
+qreg r[2];
+qreg s[2];
+h q[0];
+cx q[0],s[0];
+cx q[0],s[1];
+cx q[0],r[0];
+s s[1];
+cx q[0],s[1];
+cx q[1],s[0];
+";
+
+    const TOFFOLI: &str = "OPENQASM 2.0;
+include \"qelib1.inc\";
+qreg q[2];
+qreg r[2];
+qreg s[2];
+x q[0];
+h r[0];
+ccx q[0],r[0],s[0];
+cswap s[0],q[0],r[0];
+";
+
+    fn config() -> Configuration {
+        ConfigBuilder::new(&[2; 3]).links(&[(0, 1, 1), (1, 2, 1)]).build()
+    }
+
+    /// The line of `config` where node 0 purifies the pairs by `rounds` rounds.
+    fn purified(rounds: u32) -> Configuration {
+        ConfigBuilder::new(&[2; 3]).node(0, |n| n.with_purification_rounds(rounds)).links(&[(0, 1, 2), (1, 2, 2)]).build()
+    }
+
+    /// Every router starting from `map`.
+    fn all_routers(map: &BTreeMap<String, u32>, config: &Configuration) -> Vec<Box<dyn RemoteOpRouter>> {
+        vec![
+            Box::new(TelegateOnly::from_initial_map(map.clone())),
+            Box::new(TeledataOnly::from_initial_map(map.clone(), config)),
+            Box::new(Hybrid::from_initial_map(map.clone(), config)),
+            Box::new(Lookahead::from_initial_map(map.clone(), config)),
+        ]
+    }
+
+    #[test]
+    fn compiled_programs() {
+        let exps = qasm2::parse(SOURCE).unwrap();
+        let config = config();
+        for router in all_routers(&initial_map(&exps, &config, Placement::default()), &config) {
+            let (s, layout) = codegen(exps.clone(), &config, router, Swapping::Chain, false);
+            assert_eq!(choose_backend(&exps), Backend::Stabilizer);
+            assert_eq!(verify(&exps, &s, &layout, Backend::Stabilizer, 16, 0), Ok(()));
+            assert_eq!(verify(&exps, &s, &layout, Backend::StateVector, 16, 0), Ok(()));
+        }
+    }
+
+    #[test]
+    fn fanout_by_cat_entanglement() {
+        let exps = qasm2::parse(FANOUT).unwrap();
+        let config = config();
+        let map = ["q0", "q1", "r0", "r1", "s0", "s1"].iter().enumerate().map(|(i, q)| (q.to_string(), i as u32 / 2)).collect();
+        let router = Box::new(TelegateOnly::from_initial_map(map));
+        let (s, layout) = codegen(exps.clone(), &config, router, Swapping::Chain, false);
+        assert_eq!(verify(&exps, &s, &layout, Backend::Stabilizer, 16, 0), Ok(()));
+        assert_eq!(verify(&exps, &s, &layout, Backend::StateVector, 16, 0), Ok(()));
+        // the first two gates share a Bell pair over two links, and the others use one each
+        assert_eq!(calc_e_count(&s), 2 * (2 + 1 + 2 + 2));
+    }
+
+    #[test]
+    fn operands_on_three_nodes() {
+        let exps = qasm2::parse(TOFFOLI).unwrap();
+        let map: BTreeMap<_, _> = ["q0", "q1", "r0", "r1", "s0", "s1"].iter().enumerate().map(|(i, q)| (q.to_string(), i as u32 / 2)).collect();
+        // the Toffolis are lowered on the full nodes, and the teledata routers gather the operands otherwise
+        let roomy = ConfigBuilder::new(&[4; 3]).links(&[(0, 1, 1), (1, 2, 1)]).build();
+        for config in [config(), roomy] {
+            for router in all_routers(&map, &config) {
+                let (s, layout) = codegen(exps.clone(), &config, router, Swapping::Chain, false);
+                assert_eq!(verify(&exps, &s, &layout, Backend::StateVector, 16, 0), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn swapping_schedules() {
+        let exps = qasm2::parse("OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[5];\nh q[0];\ncx q[0],q[4];\ns q[4];\ncx q[4],q[1];\n").unwrap();
+        let config = ConfigBuilder::new(&[1; 5]).links(&[(0, 1, 1), (1, 2, 1), (2, 3, 1), (3, 4, 1)]).build();
+        let c_depth = |swapping| {
+            // the operands are at the ends of the line
+            let router = Box::new(TelegateOnly::from_initial_map(initial_map(&exps, &config, Placement::Sequential)));
+            let (s, layout) = codegen(exps.clone(), &config, router, swapping, false);
+            assert_eq!(verify(&exps, &s, &layout, Backend::Stabilizer, 16, 0), Ok(()));
+            Metrics::new(&s, Simulator::new(&s, &config).run()).c_depth()
+        };
+        assert!(c_depth(Swapping::Tree) < c_depth(Swapping::Chain));
+    }
+
+    #[test]
+    fn purified_pairs() {
+        let exps = qasm2::parse(SOURCE).unwrap();
+        let e_count = |rounds: u32| {
+            let config = purified(rounds);
+            let router = Box::new(TelegateOnly::from_initial_map(initial_map(&exps, &config, Placement::Sequential)));
+            let (s, layout) = codegen(exps.clone(), &config, router, Swapping::Chain, false);
+            assert_eq!(verify(&exps, &s, &layout, Backend::Stabilizer, 16, 0), Ok(()));
+            assert!(deadlock::detect(&s, &config).is_empty());
+            calc_e_count(&s)
+        };
+        // both remote CX gates use the link between nodes 0 and 1, and each round sacrifices a pair
+        assert_eq!(e_count(0), 2 * 2);
+        assert_eq!(e_count(2), 2 * 2 * (1 + 2));
+    }
+
+    #[test]
+    fn failed_purification() {
+        let exps = qasm2::parse(SOURCE).unwrap();
+        let config = purified(1);
+        let router = Box::new(TelegateOnly::from_initial_map(initial_map(&exps, &config, Placement::Sequential)));
+        let (s, _) = codegen(exps.clone(), &config, router, Swapping::Chain, false);
+        let System::Composition(mut ss) = s else { unreachable!() };
+        // whether the outcomes of the first round agree at nodes 0 and 1
+        let succeeds = |ss: &[System]| {
+            let outcome = |p: usize| {
+                let System::Located(LocProc { procs, .. }) = &ss[p] else { unreachable!() };
+                procs.iter().find_map(|proc| match proc {
+                    Process::Measure(m) => Some(m.dst.clone()),
+                    _ => None,
+                }).unwrap()
+            };
+            let interp = run(&System::Composition(ss.to_vec()), StabilizerState::new(), 0).unwrap();
+            interp.outcomes()[&outcome(0)] == interp.outcomes()[&outcome(1)]
+        };
+        assert!(succeeds(&ss));
+        // flips the half on node 0 of the first Bell pair
+        let System::Located(LocProc { procs, .. }) = &mut ss[0] else { unreachable!() };
+        let i = procs.iter().position(|proc| matches!(proc, Process::GenEnt(_))).unwrap();
+        let Process::GenEnt(e) = &procs[i] else { unreachable!() };
+        let flip = Process::Apply(ApplyProc { gate: PrimitiveGate::X, args: vec![e.x.clone()], ctrl: None });
+        procs.insert(i + 1, flip);
+        assert!(!succeeds(&ss));
+    }
+
+    #[test]
+    fn wrong_program() {
+        let exps = qasm2::parse(SOURCE).unwrap();
+        let config = config();
+        let router = Box::new(TelegateOnly::new(&exps, &config));
+        let (s, layout) = codegen(exps.clone(), &config, router, Swapping::Chain, false);
+        let (p, x) = layout["q1"].clone();
+        let s = match s {
+            System::Composition(mut ss) => {
+                if let System::Located(LocProc { procs, .. }) = &mut ss[p.to_usize()] {
+                    procs.push(Process::Apply(ApplyProc { gate: PrimitiveGate::H, args: vec![x], ctrl: None }));
+                }
+                System::Composition(ss)
+            },
+            s => s,
+        };
+        assert!(matches!(verify(&exps, &s, &layout, Backend::Stabilizer, 16, 0), Err(Error::Mismatch(_))));
+        assert!(matches!(verify(&exps, &s, &layout, Backend::StateVector, 16, 0), Err(Error::Mismatch(_))));
+    }
+
+    #[test]
+    fn feed_forward() {
+        let exps = qasm2::parse(TELEPORTATION).unwrap();
+        let wrong = qasm2::parse(&TELEPORTATION.replace("if(b==1)", "if(b==0)")).unwrap();
+        let config = config();
+        for router in all_routers(&initial_map(&exps, &config, Placement::default()), &config) {
+            let (s, layout) = codegen(exps.clone(), &config, router, Swapping::Chain, false);
+            assert_eq!(verify(&exps, &s, &layout, Backend::Stabilizer, 16, 0), Ok(()));
+            assert_eq!(verify(&exps, &s, &layout, Backend::StateVector, 16, 0), Ok(()));
+        }
+        let router = Box::new(TelegateOnly::new(&wrong, &config));
+        let (s, layout) = codegen(wrong, &config, router, Swapping::Chain, false);
+        assert!(matches!(verify(&exps, &s, &layout, Backend::Stabilizer, 16, 0), Err(Error::Mismatch(_))));
+    }
+}
@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// A directory of its own for the files of `test`.
+fn workdir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("inqcc-{}-{}", test, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn simulates_remote_operations_in_inquir() {
+    let dir = workdir("rcxc");
+    fs::write(dir.join("rcxc.inq"), "0 {
+  world = open[0,1];
+  q0 = init();
+  H q0;
+  _cq0 = genEnt[1](l0);
+  rcxc[1](world, l1, q0, _cq0);
+  _m0 = measure q0;
+}
+1 {
+  world = open[0,1];
+  q1 = init();
+  _cq1 = genEnt[0](l0);
+  rcxt[0](world, l1, q1, _cq1);
+  _m1 = measure q1;
+}").unwrap();
+    fs::write(dir.join("config.json"), r#"{
+  "nodes": [{ "data_qubits": 1 }, { "data_qubits": 1 }],
+  "connections": [{ "ends": [0, 1] }]
+}"#).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_inqcc"))
+        .current_dir(&dir)
+        .args(["rcxc.inq", "-c", "config.json", "--metrics", "metrics.json", "--depends", "depends.dot"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let metrics: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("metrics.json")).unwrap()).unwrap();
+    // a Bell pair is counted at both ends
+    assert_eq!(metrics["e_count"], 2);
+    // the messages of the cat-entangler and the cat-disentangler, counted at both ends
+    assert_eq!(metrics["c_count"], 4);
+    assert!(fs::read_to_string(dir.join("depends.dot")).unwrap().contains("digraph"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rejects_zero_shots() {
+    let output = Command::new(env!("CARGO_BIN_EXE_inqcc"))
+        .args(["a.inq", "-c", "config.json", "--metrics", "metrics.json", "--shots", "0"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--shots"));
+}
@@ -17,15 +17,15 @@ pub fn dijkstra<N: Clone, E: Clone, ET: EdgeType, F>(
     let mut que = BinaryHeap::new();
     que.push((0, s));
     dist[s] = Some(0);
-    while que.len() > 0 {
+    while !que.is_empty() {
         let (d, v) = que.pop().unwrap();
-        if dist[v].map_or(false, |d2| d > d2) {
+        if dist[v].is_some_and(|d2| d > d2) {
             continue;
         }
         for &eidx in g.outgoing_edges(v) {
             let to = g.edge(eidx).target();
             let nd = d + weight(eidx);
-            if dist[to].map_or(true, |d2| nd < d2) {
+            if dist[to].is_none_or(|d2| nd < d2) {
                 dist[to] = Some(nd);
                 prev[to] = Some(v);
                 que.push((nd, to));
@@ -36,7 +36,7 @@ pub fn dijkstra<N: Clone, E: Clone, ET: EdgeType, F>(
             for &eidx in g.incoming_edges(v) {
                 let to = g.edge(eidx).source(); // reverse direction
                 let nd = d + weight(eidx);
-                if dist[to].map_or(true, |d2| nd < d2) {
+                if dist[to].is_none_or(|d2| nd < d2) {
                     dist[to] = Some(nd);
                     prev[to] = Some(v);
                     que.push((nd, to));
@@ -1,5 +1,7 @@
 pub mod dijkstra;
+pub mod scc;
 pub mod toposort;
 
 pub use dijkstra::dijkstra;
+pub use scc::scc;
 pub use toposort::toposort;
@@ -0,0 +1,80 @@
+use crate::graph::{DiGraph, NodeIndex};
+
+/// Returns the strongly connected components of the given directed graph
+/// by Kosaraju's algorithm. Components are listed in a topological order.
+pub fn scc<N: Clone, E: Clone>(g: &DiGraph<N, E>) -> Vec<Vec<NodeIndex>> {
+    let n = g.node_count();
+
+    // 1st pass: post-order on the original graph (iterative to avoid stack overflows)
+    let mut visited = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+    for s in 0..n {
+        if visited[s] {
+            continue;
+        }
+        visited[s] = true;
+        let mut stack = vec![(s, 0)];
+        while let Some((v, i)) = stack.pop() {
+            let outgoing = g.outgoing_edges(v);
+            if i < outgoing.len() {
+                stack.push((v, i + 1));
+                let to = g.edge(outgoing[i]).target();
+                if !visited[to] {
+                    visited[to] = true;
+                    stack.push((to, 0));
+                }
+            } else {
+                order.push(v);
+            }
+        }
+    }
+
+    // 2nd pass: collect components on the reversed graph
+    let mut comp = vec![None; n];
+    let mut res = Vec::new();
+    for &s in order.iter().rev() {
+        if comp[s].is_some() {
+            continue;
+        }
+        let id = res.len();
+        comp[s] = Some(id);
+        let mut members = vec![s];
+        let mut stack = vec![s];
+        while let Some(v) = stack.pop() {
+            for &eidx in g.incoming_edges(v) {
+                let from = g.edge(eidx).source();
+                if comp[from].is_none() {
+                    comp[from] = Some(id);
+                    members.push(from);
+                    stack.push(from);
+                }
+            }
+        }
+        res.push(members);
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::DiGraph;
+    use super::scc;
+
+    #[test]
+    fn test_scc() {
+        let mut g = DiGraph::new();
+        (0..6).for_each(|_| { g.add_node(()); });
+        g.add_edge(0, 1, ());
+        g.add_edge(1, 2, ());
+        g.add_edge(2, 0, ());
+        g.add_edge(2, 3, ());
+        g.add_edge(3, 4, ());
+        g.add_edge(4, 3, ());
+        g.add_edge(4, 5, ());
+        let comps: Vec<_> = scc(&g).into_iter().map(|mut c| { c.sort(); c }).collect();
+        assert_eq!(comps[0], vec![0, 1, 2]);
+        assert_eq!(comps[1], vec![3, 4]);
+        assert_eq!(comps[2], vec![5]);
+    }
+}
@@ -105,6 +105,12 @@ pub struct Graph<N: Clone, E: Clone, ET: EdgeType> {
     _ety: PhantomData<fn () -> ET>,
 }
 
+impl<N: Clone, E: Clone, ET: EdgeType> Default for Graph<N, E, ET> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<N: Clone, E: Clone, ET: EdgeType> Graph<N, E, ET> {
     pub fn new() -> Self {
         Self {
@@ -212,12 +218,12 @@ mod tests {
         g.add_edge(0, 2, ());
         g.update_edge(1, 0, 3, ());
         let ins: Vec<Vec<_>> = (0..4).map(|i| {
-            let mut v: Vec<_> = g.incoming_edges(i).iter().map(|&eidx| eidx).collect();
+            let mut v: Vec<_> = g.incoming_edges(i).to_vec();
             v.sort();
             v
         }).collect();
         let outs: Vec<Vec<_>> = (0..4).map(|i| {
-            let mut v: Vec<_> = g.outgoing_edges(i).iter().map(|&eidx| eidx).collect();
+            let mut v: Vec<_> = g.outgoing_edges(i).to_vec();
             v.sort();
             v
         }).collect();
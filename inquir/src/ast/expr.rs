@@ -82,12 +82,28 @@ impl fmt::Display for Expr {
             Expr::BLit(true) => write!(f, "1"),
             Expr::BLit(false) => write!(f, "0"),
             Expr::Var(id)=> write!(f, "{}", id),
-            Expr::Not(b) => write!(f, "!{}", **b),
-            Expr::BinOp(op, l, r) => write!(f, "{} {} {}", **l, *op, **r),
+            Expr::Not(b) => {
+                write!(f, "!")?;
+                fmt_operand(f, b)
+            },
+            Expr::BinOp(op, l, r) => {
+                fmt_operand(f, l)?;
+                write!(f, " {} ", *op)?;
+                fmt_operand(f, r)
+            },
         }
     }
 }
 
+/// Binary operators have no precedence in the textual syntax,
+/// so compound operands are always parenthesized.
+fn fmt_operand(f: &mut fmt::Formatter, b: &Expr) -> fmt::Result {
+    match b {
+        Expr::BinOp(..) => write!(f, "({})", b),
+        _ => write!(f, "{}", b),
+    }
+}
+
 impl fmt::Display for BinOp {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
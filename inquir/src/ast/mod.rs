@@ -16,9 +16,6 @@ impl SessionId {
     pub fn new(s: String) -> Self {
         Self(s)
     }
-    pub fn to_string(self) -> String {
-        self.0.clone()
-    }
 }
 impl fmt::Display for SessionId {
     #[inline]
@@ -53,9 +50,6 @@ impl Label {
     pub fn new(l: String) -> Self {
         Self(l)
     }
-    pub fn to_string(&self) -> String {
-        self.0.clone()
-    }
 }
 impl fmt::Display for Label {
     #[inline]
@@ -154,17 +154,11 @@ pub struct MeasureProc {
 
 impl Process {
     pub fn is_app(&self) -> bool {
-        match self {
-            Process::Apply(_) => true,
-            _ => false,
-        }
+        matches!(self, Process::Apply(_))
     }
 
     pub fn is_measure(&self) -> bool {
-        match self {
-            Process::Measure(_) => true,
-            _ => false
-        }
+        matches!(self, Process::Measure(_))
     }
 
     pub fn as_app(&self) -> Option<ApplyProc> {
@@ -203,7 +197,21 @@ impl Process {
             Process::RCXT(proc) => [proc.arg.clone(), proc.ent.clone()].into(),
             Process::Apply(proc) => HashSet::from_iter(proc.args.clone()),
             Process::Measure(proc) => HashSet::from_iter(proc.args.clone()),
-            Process::Parallel(ps) => HashSet::from_iter(ps.iter().map(|p| p.free_variables().into_iter().collect())),
+            Process::Parallel(ps) => ps.iter().flat_map(|p| p.free_variables()).collect(),
+        }
+    }
+
+    /// Qubits which can no longer be used after the process.
+    pub fn consumed_variables(&self) -> Vec<String> {
+        match self {
+            Process::Free(proc) => vec![proc.arg.clone()],
+            Process::EntSwap(proc) => vec![proc.arg1.clone(), proc.arg2.clone()],
+            Process::QSend(proc) => vec![proc.arg.clone(), proc.ent.clone()],
+            Process::QRecv(proc) => vec![proc.ent.clone()],
+            Process::RCXC(proc) => vec![proc.ent.clone()],
+            Process::RCXT(proc) => vec![proc.ent.clone()],
+            Process::Parallel(ps) => ps.iter().flat_map(|p| p.consumed_variables()).collect(),
+            _ => vec![],
         }
     }
 
@@ -215,7 +223,7 @@ impl Process {
             Process::QRecv(proc) => vec![proc.dst.clone()],
             Process::Recv(proc) => vec![proc.data.1.clone()],
             Process::Measure(proc) => vec![proc.dst.clone()],
-            Process::Parallel(ps) => ps.iter().map(|p| p.gen_variables()).flatten().collect(),
+            Process::Parallel(ps) => ps.iter().flat_map(|p| p.gen_variables()).collect(),
             _ => vec![],
         }
     }
@@ -226,7 +234,7 @@ impl fmt::Display for Process {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Process::Open(OpenProc { id, ps }) => {
-                write!(f, "{} = open[", id.to_string())?;
+                write!(f, "{} = open[", id)?;
                 let ss: Vec<_> = ps.iter().map(|&pid| pid.to_u32().to_string()).collect();
                 let ss = ss.join(",");
                 write!(f, "{}]", ss)
@@ -246,7 +254,7 @@ impl fmt::Display for Process {
             Process::RCXT(RCXTProc { s, p, label, arg, ent, uid: _ })
                 => write!(f, "rcxt[{}]({}, {}, {}, {})", p, s, label, arg, ent),
             Process::Apply(ApplyProc { gate, args, ctrl }) => {
-                let args_str: Vec<_> = args.iter().map(|arg| arg.clone()).collect();
+                let args_str: Vec<_> = args.to_vec();
                 let args_str = args_str.join(" ");
                 if let Some(b) = ctrl {
                     write!(f, "{}[{}] {}", gate, b, args_str)
@@ -255,7 +263,7 @@ impl fmt::Display for Process {
                 }
             },
             Process::Measure(MeasureProc { dst, args }) => {
-                let args_str: Vec<_> = args.iter().map(|arg| arg.clone()).collect();
+                let args_str: Vec<_> = args.to_vec();
                 let args_str = args_str.join(" ");
                 write!(f, "{} = measure {}", dst, args_str)
             },
@@ -21,9 +21,9 @@ pub fn projection(s: &System, p: ParticipantId) -> Option<Vec<Process>> {
     match s {
         System::Located(proc) => {
             if proc.p == p {
-                return Some(proc.procs.clone());
+                Some(proc.procs.clone())
             } else {
-                return None;
+                None
             }
         },
         System::Composition(ss) => {
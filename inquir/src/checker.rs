@@ -0,0 +1,402 @@
+use crate::ast::{
+    SessionId,
+    ParticipantId,
+    Label,
+    Process,
+    OpenProc, GenEntProc, QSendProc, QRecvProc, SendProc, RecvProc, RCXCProc, RCXTProc,
+    System, LocProc,
+};
+use std::fmt;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// The position of an instruction: a participant and the index in its process list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Location {
+    pub p: ParticipantId,
+    pub index: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "participant {}, instruction {}", self.p, self.index)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// The same participant is located more than once.
+    DuplicateParticipant,
+    /// A communication uses a session which is not opened by the participant.
+    SessionNotOpened(SessionId),
+    /// A communication partner is not a member of the session.
+    NotInSession(SessionId, ParticipantId),
+    /// A sending instruction has no matching receiving instruction.
+    UnmatchedSend(SessionId, Label),
+    /// A receiving instruction has no matching sending instruction.
+    UnmatchedRecv(SessionId, Label),
+    /// Two or more instructions use the same label on the same session.
+    DuplicateLabel(SessionId, Label),
+    /// A message is received by a participant other than its destination.
+    WrongReceiver { s: SessionId, label: Label, expected: ParticipantId },
+    /// `genEnt[p](l)` has no dual `genEnt` on `p`.
+    UnmatchedGenEnt(ParticipantId, Label),
+    /// Two or more entanglement generations share the same label.
+    DuplicateGenEnt(Label),
+    /// The dual of `genEnt[p](l)` is not located at `p`, or does not point back.
+    WrongGenEntPartner { label: Label, expected: ParticipantId },
+    /// `rcxc` and `rcxt` for the same label are missing or inconsistent.
+    UnmatchedRemoteCX(Label),
+    /// A variable is used before it is bound.
+    Unbound(String),
+    /// A qubit is used after being freed or consumed.
+    UseAfterFree(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub loc: Location,
+    pub kind: DiagnosticKind,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.loc, self.kind)
+    }
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiagnosticKind::DuplicateParticipant => write!(f, "the participant is located more than once"),
+            DiagnosticKind::SessionNotOpened(s) => write!(f, "session `{}` is not opened", s),
+            DiagnosticKind::NotInSession(s, p) => write!(f, "participant {} is not a member of session `{}`", p, s),
+            DiagnosticKind::UnmatchedSend(s, l) => write!(f, "no receiver for label `{}` on session `{}`", l, s),
+            DiagnosticKind::UnmatchedRecv(s, l) => write!(f, "no sender for label `{}` on session `{}`", l, s),
+            DiagnosticKind::DuplicateLabel(s, l) => write!(f, "label `{}` is used more than once on session `{}`", l, s),
+            DiagnosticKind::WrongReceiver { s, label, expected } =>
+                write!(f, "label `{}` on session `{}` must be received by participant {}", label, s, expected),
+            DiagnosticKind::UnmatchedGenEnt(p, l) => write!(f, "no dual `genEnt` of label `{}` on participant {}", l, p),
+            DiagnosticKind::DuplicateGenEnt(l) => write!(f, "label `{}` is used by more than two `genEnt`s", l),
+            DiagnosticKind::WrongGenEntPartner { label, expected } =>
+                write!(f, "the dual `genEnt` of label `{}` must be on participant {}", label, expected),
+            DiagnosticKind::UnmatchedRemoteCX(l) => write!(f, "no matching remote CX for label `{}`", l),
+            DiagnosticKind::Unbound(x) => write!(f, "`{}` is used before it is bound", x),
+            DiagnosticKind::UseAfterFree(x) => write!(f, "`{}` is used after it is freed", x),
+        }
+    }
+}
+
+/// An endpoint of a communication: (location, the participant specified in the instruction).
+type Endpoint = (Location, Option<ParticipantId>);
+
+#[derive(Default)]
+struct Endpoints {
+    sends: Vec<Endpoint>,
+    recvs: Vec<Endpoint>,
+}
+
+#[derive(Default)]
+struct Checker {
+    diags: Vec<Diagnostic>,
+    /// Sessions known by each participant with their members.
+    sessions: HashMap<ParticipantId, HashMap<SessionId, Vec<ParticipantId>>>,
+    messages: BTreeMap<(SessionId, Label), Endpoints>,
+    teleports: BTreeMap<(SessionId, Label), Endpoints>,
+    remote_cxs: BTreeMap<Label, Endpoints>,
+    gen_ents: BTreeMap<Label, Vec<(Location, ParticipantId)>>,
+}
+
+/// Checks that a system is well-formed.
+///
+/// The following properties are verified:
+///   * each message on a session has exactly one sender and one receiver,
+///     and the receiver is the destination specified by the sender,
+///   * `qsend`/`qrecv` and `rcxc`/`rcxt` are paired in the same way,
+///   * each `genEnt[p](l)` has exactly one dual `genEnt[q](l)` located at `p`,
+///   * sessions are opened before being used and partners are their members,
+///   * variables are bound before being used and qubits are not used after `free`.
+pub fn check(s: &System) -> Result<(), Vec<Diagnostic>> {
+    let mut checker = Checker::default();
+    let mut seen = HashSet::new();
+    for LocProc { p, procs } in located_processes(s) {
+        if !seen.insert(*p) {
+            checker.report(Location { p: *p, index: 0 }, DiagnosticKind::DuplicateParticipant);
+            continue;
+        }
+        checker.check_participant(*p, procs);
+    }
+    checker.check_pairs();
+
+    if checker.diags.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.diags)
+    }
+}
+
+fn located_processes(s: &System) -> Vec<&LocProc> {
+    match s {
+        System::Located(proc) => vec![proc],
+        System::Composition(ss) => ss.iter().flat_map(located_processes).collect(),
+    }
+}
+
+impl Checker {
+    fn report(&mut self, loc: Location, kind: DiagnosticKind) {
+        self.diags.push(Diagnostic { loc, kind });
+    }
+
+    fn check_participant(&mut self, p: ParticipantId, procs: &[Process]) {
+        let mut bound = HashSet::new();
+        let mut freed = HashSet::new();
+        for (index, proc) in procs.iter().enumerate() {
+            let loc = Location { p, index };
+            self.check_variables(loc, proc, &mut bound, &mut freed);
+            self.collect_endpoints(loc, proc);
+        }
+    }
+
+    fn check_variables(&mut self, loc: Location, proc: &Process, bound: &mut HashSet<String>, freed: &mut HashSet<String>) {
+        let mut used: Vec<_> = proc.free_variables().into_iter().collect();
+        used.sort();
+        for x in used {
+            if freed.contains(&x) {
+                self.report(loc, DiagnosticKind::UseAfterFree(x));
+            } else if !bound.contains(&x) {
+                self.report(loc, DiagnosticKind::Unbound(x));
+            }
+        }
+        for x in proc.consumed_variables() {
+            bound.remove(&x);
+            freed.insert(x);
+        }
+        for x in proc.gen_variables() {
+            freed.remove(&x);
+            bound.insert(x);
+        }
+    }
+
+    fn collect_endpoints(&mut self, loc: Location, proc: &Process) {
+        let s = match proc {
+            Process::Send(SendProc { s, .. })
+            | Process::Recv(RecvProc { s, .. })
+            | Process::QSend(QSendProc { s, .. })
+            | Process::QRecv(QRecvProc { s, .. })
+            | Process::RCXC(RCXCProc { s, .. })
+            | Process::RCXT(RCXTProc { s, .. }) => Some(s),
+            _ => None,
+        };
+        if let Some(s) = s {
+            self.check_session(loc, s, proc);
+        }
+
+        match proc {
+            Process::Open(OpenProc { id, ps }) => {
+                self.sessions.entry(loc.p).or_default().insert(id.clone(), ps.clone());
+            },
+            Process::GenEnt(GenEntProc { x: _, p, label }) => {
+                self.gen_ents.entry(label.clone()).or_default().push((loc, *p));
+            },
+            Process::Send(SendProc { s, dst, data: (label, _) }) => {
+                self.messages.entry((s.clone(), label.clone())).or_default().sends.push((loc, Some(*dst)));
+            },
+            Process::Recv(RecvProc { s, data: (label, _) }) => {
+                self.messages.entry((s.clone(), label.clone())).or_default().recvs.push((loc, None));
+            },
+            Process::QSend(QSendProc { s, p, label, .. }) => {
+                self.teleports.entry((s.clone(), label.clone())).or_default().sends.push((loc, Some(*p)));
+            },
+            Process::QRecv(QRecvProc { s, label, .. }) => {
+                self.teleports.entry((s.clone(), label.clone())).or_default().recvs.push((loc, None));
+            },
+            Process::RCXC(RCXCProc { p, label, .. }) => {
+                self.remote_cxs.entry(label.clone()).or_default().sends.push((loc, Some(*p)));
+            },
+            Process::RCXT(RCXTProc { p, label, .. }) => {
+                self.remote_cxs.entry(label.clone()).or_default().recvs.push((loc, Some(*p)));
+            },
+            Process::Parallel(procs) => procs.iter().for_each(|proc| self.collect_endpoints(loc, proc)),
+            _ => {},
+        }
+    }
+
+    fn check_session(&mut self, loc: Location, s: &SessionId, proc: &Process) {
+        let partner = match proc {
+            Process::Send(SendProc { dst, .. }) => Some(*dst),
+            Process::QSend(QSendProc { p, .. })
+            | Process::RCXC(RCXCProc { p, .. })
+            | Process::RCXT(RCXTProc { p, .. }) => Some(*p),
+            _ => None,
+        };
+        let members = self.sessions.get(&loc.p).and_then(|sessions| sessions.get(s)).cloned();
+        match members {
+            None => self.report(loc, DiagnosticKind::SessionNotOpened(s.clone())),
+            Some(members) => {
+                for q in [Some(loc.p), partner].into_iter().flatten() {
+                    if !members.contains(&q) {
+                        self.report(loc, DiagnosticKind::NotInSession(s.clone(), q));
+                    }
+                }
+            },
+        }
+    }
+
+    fn check_pairs(&mut self) {
+        let messages = std::mem::take(&mut self.messages);
+        for ((s, label), endpoints) in messages.into_iter().chain(std::mem::take(&mut self.teleports)) {
+            self.check_message(s, label, endpoints);
+        }
+
+        for (label, Endpoints { sends: ctrls, recvs: tgts }) in std::mem::take(&mut self.remote_cxs) {
+            let consistent = match (&ctrls[..], &tgts[..]) {
+                ([(c, Some(cp))], [(t, Some(tp))]) => c.p == *tp && t.p == *cp,
+                _ => false,
+            };
+            if !consistent {
+                for (loc, _) in ctrls.iter().chain(tgts.iter()) {
+                    self.report(*loc, DiagnosticKind::UnmatchedRemoteCX(label.clone()));
+                }
+            }
+        }
+
+        for (label, gens) in std::mem::take(&mut self.gen_ents) {
+            match &gens[..] {
+                [(loc, p)] => self.report(*loc, DiagnosticKind::UnmatchedGenEnt(*p, label)),
+                [(loc1, p1), (loc2, p2)] => {
+                    if loc2.p != *p1 {
+                        self.report(*loc1, DiagnosticKind::WrongGenEntPartner { label: label.clone(), expected: *p1 });
+                    }
+                    if loc1.p != *p2 {
+                        self.report(*loc2, DiagnosticKind::WrongGenEntPartner { label, expected: *p2 });
+                    }
+                },
+                _ => gens.iter().for_each(|(loc, _)| self.report(*loc, DiagnosticKind::DuplicateGenEnt(label.clone()))),
+            }
+        }
+
+        self.diags.sort_by_key(|d| d.loc);
+    }
+
+    fn check_message(&mut self, s: SessionId, label: Label, Endpoints { sends, recvs }: Endpoints) {
+        if sends.len() > 1 || recvs.len() > 1 {
+            for (loc, _) in sends.iter().chain(recvs.iter()) {
+                self.report(*loc, DiagnosticKind::DuplicateLabel(s.clone(), label.clone()));
+            }
+            return;
+        }
+        match (sends.first(), recvs.first()) {
+            (Some((_, dst)), Some((recv_loc, _))) => {
+                let dst = dst.unwrap();
+                if recv_loc.p != dst {
+                    self.report(*recv_loc, DiagnosticKind::WrongReceiver { s, label, expected: dst });
+                }
+            },
+            (Some((loc, _)), None) => self.report(*loc, DiagnosticKind::UnmatchedSend(s, label)),
+            (None, Some((loc, _))) => self.report(*loc, DiagnosticKind::UnmatchedRecv(s, label)),
+            (None, None) => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn kinds(input: &str) -> Vec<(u32, usize, DiagnosticKind)> {
+        let s = parse(input).unwrap();
+        match check(&s) {
+            Ok(()) => Vec::new(),
+            Err(diags) => diags.into_iter().map(|d| (d.loc.p.to_u32(), d.loc.index, d.kind)).collect(),
+        }
+    }
+
+    #[test]
+    fn well_formed_teleportation() {
+        let input = "0 {
+  world = open[0,1];
+  q0 = init();
+  _cq0 = genEnt[1](l0);
+  qsend[1](world, l1, q0, _cq0);
+}
+1 {
+  world = open[0,1];
+  _cq1 = genEnt[0](l0);
+  q1 = qrecv(world, l1, _cq1);
+  H q1;
+}";
+        assert_eq!(kinds(input), vec![]);
+    }
+
+    #[test]
+    fn missing_partners() {
+        let input = "0 {
+  world = open[0,1];
+  _cq0 = genEnt[1](l0);
+  send[1](world, l1:1);
+}
+1 {
+  world = open[0,1];
+  recv(world, l2:x);
+}";
+        let ls = |l: &str| Label::new(l.to_string());
+        let world = SessionId::new("world".to_string());
+        assert_eq!(kinds(input), vec![
+            (0, 1, DiagnosticKind::UnmatchedGenEnt(ParticipantId::new(1), ls("l0"))),
+            (0, 2, DiagnosticKind::UnmatchedSend(world.clone(), ls("l1"))),
+            (1, 1, DiagnosticKind::UnmatchedRecv(world, ls("l2"))),
+        ]);
+    }
+
+    #[test]
+    fn wrong_receiver_and_session() {
+        let input = "0 {
+  s = open[0,1];
+  send[1](s, l0:1);
+  send[2](t, l1:1);
+}
+2 {
+  s = open[0,1];
+  recv(s, l0:x);
+}";
+        let diags = kinds(input);
+        assert!(diags.contains(&(0, 2, DiagnosticKind::SessionNotOpened(SessionId::new("t".to_string())))));
+        assert!(diags.contains(&(2, 1, DiagnosticKind::NotInSession(SessionId::new("s".to_string()), ParticipantId::new(2)))));
+        assert!(diags.contains(&(2, 1, DiagnosticKind::WrongReceiver {
+            s: SessionId::new("s".to_string()),
+            label: Label::new("l0".to_string()),
+            expected: ParticipantId::new(1),
+        })));
+    }
+
+    #[test]
+    fn variables() {
+        let input = "0 {
+  q0 = init();
+  H q1;
+  free q0;
+  X q0;
+  q0 = init();
+  X q0;
+}";
+        assert_eq!(kinds(input), vec![
+            (0, 1, DiagnosticKind::Unbound("q1".to_string())),
+            (0, 3, DiagnosticKind::UseAfterFree("q0".to_string())),
+        ]);
+    }
+
+    #[test]
+    fn variables_in_parallel() {
+        let input = "0 {
+  q0 = init();
+  q1 = init();
+  H q0 | X q1;
+  free q1;
+  H q0 | X q1 | Z q2;
+}";
+        assert_eq!(kinds(input), vec![
+            (0, 4, DiagnosticKind::UseAfterFree("q1".to_string())),
+            (0, 4, DiagnosticKind::Unbound("q2".to_string())),
+        ]);
+    }
+}
@@ -1,8 +1,5 @@
 pub mod ast;
+pub mod checker;
+pub mod parser;
 
-pub use ast::{
-    *,
-    expr::*,
-    process::*,
-    system::*,
-};
+pub use ast::*;
@@ -0,0 +1,438 @@
+use crate::ast::{
+    SessionId,
+    ParticipantId,
+    Label,
+    PrimitiveGate,
+    Expr, BinOp,
+    Process,
+    OpenProc, InitProc, FreeProc, GenEntProc, EntSwapProc, QSendProc, QRecvProc,
+    SendProc, RecvProc, RCXCProc, RCXTProc, ApplyProc, MeasureProc,
+    System, LocProc,
+};
+
+use nom::{
+    IResult,
+    branch::alt,
+    bytes::complete::{
+        tag,
+        take_while,
+        take_while1,
+    },
+    character::complete::{
+        char,
+        digit1,
+        satisfy,
+        space1,
+    },
+    combinator::{opt, map, map_res, not, recognize},
+    error::VerboseError,
+    multi::{many0, many1, separated_list0, separated_list1},
+    sequence::{
+        tuple,
+        delimited,
+        pair,
+        preceded,
+        terminated,
+    },
+    number::complete::double,
+};
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub enum Error<'a> {
+    Unexpected(&'a str),
+    Nom(nom::Err<VerboseError<&'a str>>),
+}
+
+impl<'a> From<nom::Err<VerboseError<&'a str>>> for Error<'a> {
+    fn from(err: nom::Err<VerboseError<&'a str>>) -> Self {
+        Error::Nom(err)
+    }
+}
+
+/// Parses the textual form of InQuIR printed by `impl Display for System`.
+///
+/// The result is always a `System::Composition` of located processes.
+/// Because the `uid` annotations of `qsend`/`qrecv` and `rcxc`/`rcxt` are not
+/// printed, fresh ones are assigned so that both sides of a label share a uid.
+pub fn parse(input: &str) -> Result<System, Error<'_>> {
+    let (input, _) = spaces_and_comments(input)?;
+    let (input, ss) = many0(terminated(parse_located, spaces_and_comments))(input)?;
+    if input.is_empty() {
+        let mut s = System::Composition(ss);
+        assign_uids(&mut s);
+        Ok(s)
+    } else {
+        Err(Error::Unexpected(input))
+    }
+}
+
+fn parse_located(input: &str) -> IResult<&str, System, VerboseError<&str>> {
+    let (input, p) = parse_participant(input)?;
+    let (input, _) = delimited(spaces_and_comments, char('{'), spaces_and_comments)(input)?;
+    let (input, procs) = many0(terminated(
+        parse_process,
+        tuple((spaces_and_comments, char(';'), spaces_and_comments))))(input)?;
+    let (input, _) = char('}')(input)?;
+    Ok((input, System::Located(LocProc { p, procs })))
+}
+
+/// Parses a process, possibly a parallel composition `P1 | .. | Pn`.
+pub fn parse_process(input: &str) -> IResult<&str, Process, VerboseError<&str>> {
+    let separator = delimited(spaces_and_comments, terminated(char('|'), not(char('|'))), spaces_and_comments);
+    let (input, mut procs) = separated_list1(separator, parse_single_process)(input)?;
+    if procs.len() == 1 {
+        Ok((input, procs.pop().unwrap()))
+    } else {
+        Ok((input, Process::Parallel(procs)))
+    }
+}
+
+fn parse_single_process(input: &str) -> IResult<&str, Process, VerboseError<&str>> {
+    alt((
+        parse_ent_swap,
+        parse_free,
+        parse_qsend,
+        parse_send,
+        parse_recv,
+        parse_rcx,
+        parse_assignment,
+        parse_apply,
+    ))(input)
+}
+
+/// `(x1, x2) = entSwap(y1, y2)`
+fn parse_ent_swap(input: &str) -> IResult<&str, Process, VerboseError<&str>> {
+    let (input, (x1, x2)) = parse_pair(input)?;
+    let (input, _) = delimited(spaces_and_comments, char('='), spaces_and_comments)(input)?;
+    let (input, _) = tag("entSwap")(input)?;
+    let (input, (arg1, arg2)) = parse_pair(input)?;
+    Ok((input, Process::EntSwap(EntSwapProc { x1, x2, arg1, arg2 })))
+}
+
+/// `free x`
+fn parse_free(input: &str) -> IResult<&str, Process, VerboseError<&str>> {
+    let (input, arg) = preceded(pair(tag("free"), space1), parse_identifier)(input)?;
+    Ok((input, Process::Free(FreeProc { arg })))
+}
+
+/// `qsend[p](s, l, x, ent)`
+fn parse_qsend(input: &str) -> IResult<&str, Process, VerboseError<&str>> {
+    let (input, p) = preceded(tag("qsend"), parse_bracketed_participant)(input)?;
+    let (input, (s, label, arg, ent)) = parse_comm_args(input)?;
+    Ok((input, Process::QSend(QSendProc { p, s, label, arg, ent, uid: 0 })))
+}
+
+/// `send[p](s, l:e)`
+fn parse_send(input: &str) -> IResult<&str, Process, VerboseError<&str>> {
+    let (input, dst) = preceded(tag("send"), parse_bracketed_participant)(input)?;
+    let (input, (s, label, e)) = delimited(
+        char('('),
+        tuple((
+            terminated(parse_session, comma),
+            parse_label,
+            preceded(delimited(spaces_and_comments, char(':'), spaces_and_comments), parse_expr),
+        )),
+        char(')'))(input)?;
+    Ok((input, Process::Send(SendProc { s, dst, data: (label, e) })))
+}
+
+/// `recv(s, l:x)`
+fn parse_recv(input: &str) -> IResult<&str, Process, VerboseError<&str>> {
+    let (input, (s, label, x)) = preceded(
+        tag("recv"),
+        delimited(
+            char('('),
+            tuple((
+                terminated(parse_session, comma),
+                parse_label,
+                preceded(delimited(spaces_and_comments, char(':'), spaces_and_comments), parse_identifier),
+            )),
+            char(')')))(input)?;
+    Ok((input, Process::Recv(RecvProc { s, data: (label, x) })))
+}
+
+/// `rcxc[p](s, l, x, ent)` or `rcxt[p](s, l, x, ent)`
+fn parse_rcx(input: &str) -> IResult<&str, Process, VerboseError<&str>> {
+    let (input, kind) = alt((tag("rcxc"), tag("rcxt")))(input)?;
+    let (input, p) = parse_bracketed_participant(input)?;
+    let (input, (s, label, arg, ent)) = parse_comm_args(input)?;
+    let proc = if kind == "rcxc" {
+        Process::RCXC(RCXCProc { s, p, label, arg, ent, uid: 0 })
+    } else {
+        Process::RCXT(RCXTProc { s, p, label, arg, ent, uid: 0 })
+    };
+    Ok((input, proc))
+}
+
+/// `x = open[..]`, `x = init()`, `x = genEnt[p](l)`, `x = qrecv(s, l, ent)` or `x = measure y1 .. yn`
+fn parse_assignment(input: &str) -> IResult<&str, Process, VerboseError<&str>> {
+    let (input, x) = parse_identifier(input)?;
+    let (input, _) = delimited(spaces_and_comments, char('='), spaces_and_comments)(input)?;
+    let (input, kind) = alt((tag("open"), tag("init"), tag("genEnt"), tag("qrecv"), tag("measure")))(input)?;
+    match kind {
+        "open" => {
+            let (input, ps) = delimited(char('['), separated_list0(comma, parse_participant), char(']'))(input)?;
+            Ok((input, Process::Open(OpenProc { id: SessionId::new(x), ps })))
+        },
+        "init" => {
+            let (input, _) = pair(char('('), char(')'))(input)?;
+            Ok((input, Process::Init(InitProc { dst: x })))
+        },
+        "genEnt" => {
+            let (input, p) = parse_bracketed_participant(input)?;
+            let (input, label) = delimited(char('('), parse_label, char(')'))(input)?;
+            Ok((input, Process::GenEnt(GenEntProc { x, p, label })))
+        },
+        "qrecv" => {
+            let (input, (s, label, ent)) = delimited(
+                char('('),
+                tuple((
+                    terminated(parse_session, comma),
+                    terminated(parse_label, comma),
+                    parse_identifier,
+                )),
+                char(')'))(input)?;
+            Ok((input, Process::QRecv(QRecvProc { s, label, dst: x, ent, uid: 0 })))
+        },
+        _ => { // measure
+            let (input, args) = parse_gate_args(input)?;
+            Ok((input, Process::Measure(MeasureProc { dst: x, args })))
+        },
+    }
+}
+
+/// `U x1 .. xn` or `U[e] x1 .. xn`
+fn parse_apply(input: &str) -> IResult<&str, Process, VerboseError<&str>> {
+    let (input, gate) = parse_gate(input)?;
+    let (input, ctrl) = opt(delimited(char('['), parse_expr, char(']')))(input)?;
+    let (input, args) = parse_gate_args(input)?;
+    Ok((input, Process::Apply(ApplyProc { gate, args, ctrl })))
+}
+
+pub fn parse_gate(input: &str) -> IResult<&str, PrimitiveGate, VerboseError<&str>> {
+    let (rest, name) = recognize(pair(satisfy(|c| c.is_ascii_uppercase()), take_while(|c: char| c.is_ascii_alphanumeric())))(input)?;
+    let gate = match name {
+        "I" => PrimitiveGate::I,
+        "X" => PrimitiveGate::X,
+        "Y" => PrimitiveGate::Y,
+        "Z" => PrimitiveGate::Z,
+        "H" => PrimitiveGate::H,
+        "T" => PrimitiveGate::T,
+        "Tdg" => PrimitiveGate::Tdg,
+        "S" => PrimitiveGate::S,
+        "CX" => PrimitiveGate::CX,
+        "RCX" => PrimitiveGate::RCX,
+        "Rz" => {
+            let (rest, theta) = delimited(char('('), double, char(')'))(rest)?;
+            return Ok((rest, PrimitiveGate::Rz(theta)));
+        },
+        _ => return Err(nom::Err::Error(VerboseError {
+            errors: vec![(input, nom::error::VerboseErrorKind::Context("unknown gate"))],
+        })),
+    };
+    Ok((rest, gate))
+}
+
+fn parse_gate_args(input: &str) -> IResult<&str, Vec<String>, VerboseError<&str>> {
+    many1(preceded(space1, parse_identifier))(input)
+}
+
+/// Parses a classical expression.
+/// Binary operators are left-associative and have the same precedence.
+pub fn parse_expr(input: &str) -> IResult<&str, Expr, VerboseError<&str>> {
+    let (input, head) = parse_expr_term(input)?;
+    let (input, tail) = many0(pair(
+        delimited(spaces_and_comments, parse_binop, spaces_and_comments),
+        parse_expr_term))(input)?;
+    let e = tail.into_iter().fold(head, |l, (op, r)| Expr::BinOp(op, Box::new(l), Box::new(r)));
+    Ok((input, e))
+}
+
+fn parse_expr_term(input: &str) -> IResult<&str, Expr, VerboseError<&str>> {
+    alt((
+        map(char('1'), |_| Expr::BLit(true)),
+        map(char('0'), |_| Expr::BLit(false)),
+        map(preceded(char('!'), parse_expr_term), |e| Expr::Not(Box::new(e))),
+        delimited(terminated(char('('), spaces_and_comments), parse_expr, preceded(spaces_and_comments, char(')'))),
+        map(parse_identifier, Expr::Var),
+    ))(input)
+}
+
+fn parse_binop(input: &str) -> IResult<&str, BinOp, VerboseError<&str>> {
+    alt((
+        map(tag("&"), |_| BinOp::And),
+        map(tag("||"), |_| BinOp::Or),
+        map(tag("+"), |_| BinOp::Xor),
+    ))(input)
+}
+
+fn parse_comm_args(input: &str) -> IResult<&str, (SessionId, Label, String, String), VerboseError<&str>> {
+    delimited(
+        char('('),
+        tuple((
+            terminated(parse_session, comma),
+            terminated(parse_label, comma),
+            terminated(parse_identifier, comma),
+            parse_identifier,
+        )),
+        char(')'))(input)
+}
+
+fn parse_pair(input: &str) -> IResult<&str, (String, String), VerboseError<&str>> {
+    delimited(char('('), pair(terminated(parse_identifier, comma), parse_identifier), char(')'))(input)
+}
+
+fn parse_bracketed_participant(input: &str) -> IResult<&str, ParticipantId, VerboseError<&str>> {
+    delimited(char('['), parse_participant, char(']'))(input)
+}
+
+pub fn parse_participant(input: &str) -> IResult<&str, ParticipantId, VerboseError<&str>> {
+    map_res(digit1, |s: &str| s.parse::<u32>().map(ParticipantId::new))(input)
+}
+
+fn parse_session(input: &str) -> IResult<&str, SessionId, VerboseError<&str>> {
+    map(parse_identifier, SessionId::new)(input)
+}
+
+fn parse_label(input: &str) -> IResult<&str, Label, VerboseError<&str>> {
+    map(parse_identifier, Label::new)(input)
+}
+
+pub fn parse_identifier(input: &str) -> IResult<&str, String, VerboseError<&str>> {
+    let (input, id) = recognize(pair(
+        satisfy(|c| c.is_ascii_alphabetic() || c == '_'),
+        take_while(|c: char| c.is_ascii_alphanumeric() || c == '_')))(input)?;
+    Ok((input, id.to_string()))
+}
+
+fn comma(input: &str) -> IResult<&str, (), VerboseError<&str>> {
+    let (input, _) = tuple((spaces_and_comments, char(','), spaces_and_comments))(input)?;
+    Ok((input, ()))
+}
+
+/// Skips whitespaces and line comments starting with `//`.
+pub fn spaces_and_comments(input: &str) -> IResult<&str, (), VerboseError<&str>> {
+    let skipped = " \t\r\n";
+    let (input, _) = many0(alt((
+        take_while1(move |c: char| skipped.contains(c)),
+        recognize(pair(tag("//"), take_while(|c: char| c != '\n'))),
+    )))(input)?;
+    Ok((input, ()))
+}
+
+/// Gives the same uid to both endpoints of each teleportation and remote CX.
+fn assign_uids(s: &mut System) {
+    let mut uids = HashMap::new();
+    assign_uids_impl(s, &mut uids);
+}
+
+fn assign_uids_impl(s: &mut System, uids: &mut HashMap<Label, u32>) {
+    match s {
+        System::Located(LocProc { p: _, procs }) => procs.iter_mut().for_each(|proc| assign_uids_proc(proc, uids)),
+        System::Composition(ss) => ss.iter_mut().for_each(|s| assign_uids_impl(s, uids)),
+    }
+}
+
+fn assign_uids_proc(proc: &mut Process, uids: &mut HashMap<Label, u32>) {
+    let mut uid_of = |label: &Label| {
+        let next = uids.len() as u32;
+        *uids.entry(label.clone()).or_insert(next)
+    };
+    match proc {
+        Process::QSend(QSendProc { label, uid, .. })
+        | Process::QRecv(QRecvProc { label, uid, .. })
+        | Process::RCXC(RCXCProc { label, uid, .. })
+        | Process::RCXT(RCXTProc { label, uid, .. }) => *uid = uid_of(label),
+        Process::Parallel(procs) => procs.iter_mut().for_each(|proc| assign_uids_proc(proc, uids)),
+        _ => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! check_process {
+        ($input:expr) => {
+            let (rest, proc) = parse_process($input).unwrap();
+            assert_eq!(rest, "");
+            assert_eq!(format!("{}", proc), $input);
+        }
+    }
+
+    #[test]
+    fn parse_processes() {
+        check_process!("world = open[0,1,2]");
+        check_process!("q0 = init()");
+        check_process!("free _cq3");
+        check_process!("_cq0 = genEnt[1](l0)");
+        check_process!("(_m1, _m2) = entSwap(_cq1, _cq2)");
+        check_process!("qsend[1](world, l3, q0, _cq0)");
+        check_process!("_x4 = qrecv(world, l3, _cq1)");
+        check_process!("send[0](world, l2:_m1)");
+        check_process!("recv(world, l2_2:_m5)");
+        check_process!("rcxc[1](world, l4, q0, _cq0)");
+        check_process!("rcxt[0](world, l4, q1, _cq1)");
+        check_process!("CX q0 q1");
+        check_process!("Rz(-0.15) q2");
+        check_process!("X[_m3] q0");
+        check_process!("_m7 = measure q0");
+        check_process!("H q0 | Tdg q1");
+    }
+
+    #[test]
+    fn parse_expr_test() {
+        let (_, e) = parse_expr("!(a + b) & 1").unwrap();
+        let expected = Expr::BinOp(
+            BinOp::And,
+            Box::new(Expr::Not(Box::new(Expr::BinOp(
+                BinOp::Xor,
+                Box::new(Expr::Var("a".to_string())),
+                Box::new(Expr::Var("b".to_string())))))),
+            Box::new(Expr::BLit(true)));
+        assert_eq!(e, expected);
+        assert_eq!(format!("{}", e), "!(a + b) & 1");
+    }
+
+    #[test]
+    fn parse_ctrl_or_is_not_parallel() {
+        let (_, proc) = parse_process("Z[a || b] q0").unwrap();
+        assert!(proc.is_app());
+        assert_eq!(proc.as_app().unwrap().ctrl.unwrap().to_string(), "a || b");
+    }
+
+    #[test]
+    fn parse_system_round_trip() {
+        let input = "0 {
+  world = open[0,1];
+  q0 = init();
+  _cq0 = genEnt[1](l0);
+  rcxc[1](world, l1, q0, _cq0);
+  _m0 = measure q0;
+
+}
+1 {
+  world = open[0,1];
+  q1 = init();
+  _cq1 = genEnt[0](l0);
+  rcxt[0](world, l1, q1, _cq1);
+
+}";
+        let s = parse(input).unwrap();
+        assert_eq!(format!("{}", s), input);
+        let rcxc = crate::projection(&s, ParticipantId::new(0)).unwrap();
+        let rcxt = crate::projection(&s, ParticipantId::new(1)).unwrap();
+        match (&rcxc[3], &rcxt[3]) {
+            (Process::RCXC(c), Process::RCXT(t)) => assert_eq!(c.uid, t.uid),
+            _ => panic!("expected rcxc and rcxt"),
+        }
+    }
+
+    #[test]
+    fn parse_comments() {
+        let input = "// teleport nothing\n0 {\n  q0 = init(); // data\n}\n";
+        let s = parse(input).unwrap();
+        assert_eq!(crate::projection(&s, ParticipantId::new(0)).unwrap().len(), 1);
+    }
+}
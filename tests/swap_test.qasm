@@ -1,6 +1,7 @@
 OPENQASM 2.0;
+include "qelib1.inc";
 qreg q[3];
-creg q[3];
+creg c[3];
 cx q[0],q[2];
 cx q[2],q[0];
 measure q[0] -> c[0];